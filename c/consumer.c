@@ -33,6 +33,10 @@ CONSUMER_(join)( void *shmem_region, CONSUMER_(consumer_t) *consumer ) {
             fprintf( stderr, "Incorrect capacity\n" );
             return 1;
         }
+        if( fingerprint_check( &spsc->fingerprint ) ) {
+            fprintf( stderr, "Element type mismatch\n" );
+            return 1;
+        }
 
         consumer->spsc = spsc;
         
@@ -68,6 +72,7 @@ CONSUMER_(maybe_sync)( CONSUMER_(consumer_t) *consumer ) {
     if( do_sync ) {
         consumer->items_since_last_sync = 0;
         atomic_store_explicit( &(consumer->spsc->head.value), consumer->head, memory_order_release );
+        notify_wake( &consumer->spsc->producer_notify );
     }
 }
 
@@ -86,7 +91,7 @@ CONSUMER_(pop)( CONSUMER_(consumer_t) *consumer, CHANNEL_T *value ) {
     /* Calculate the index in the circular buffer */
     uint64_t index = consumer->head & (CHANNEL_N - 1);
     
-    /* Get buffer pointer (starts at offset 640) */
+    /* Get buffer pointer (starts at offset 1280) */
     void *buffer_base = spsc + 1;
     void *addr = (char *)buffer_base + (index * sizeof(CHANNEL_T));
     
@@ -35,4 +35,10 @@ main( int argc, char *argv[] ) {
     fprintf( stderr, "padding offset:             %ld\n", ((size_t)(&spsc->padding) - (size_t)spsc));
     fprintf( stderr, "capacity offset:            %ld\n", ((size_t)(&spsc->capacity) - (size_t)spsc));
     fprintf( stderr, "magic offset:               %ld\n", ((size_t)(&spsc->magic) - (size_t)spsc));
+    fprintf( stderr, "consumer_notify offset:     %ld\n", ((size_t)(&spsc->consumer_notify) - (size_t)spsc));
+    fprintf( stderr, "producer_notify offset:     %ld\n", ((size_t)(&spsc->producer_notify) - (size_t)spsc));
+    fprintf( stderr, "fingerprint offset:         %ld\n", ((size_t)(&spsc->fingerprint) - (size_t)spsc));
+    fprintf( stderr, "owners offset:              %ld\n", ((size_t)(&spsc->owners) - (size_t)spsc));
+    fprintf( stderr, "metadata_seq offset:        %ld\n", ((size_t)(&spsc->metadata_seq) - (size_t)spsc));
+    fprintf( stderr, "buffer offset:              %ld\n", sizeof(QUE_(spsc_t)));
 }
\ No newline at end of file
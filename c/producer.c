@@ -49,6 +49,10 @@ PRODUCER_(join_or_initialize)( void *shmem_region, PRODUCER_(producer_t)* produc
             fprintf( stderr, "Incorrect capacity\n" );
             return 1;
         }
+        if( fingerprint_check( &spsc->fingerprint ) ) {
+            fprintf( stderr, "Element type mismatch\n" );
+            return 1;
+        }
 
         /* Increment producer heartbeat to signal we've joined */
         atomic_fetch_add_explicit( &(spsc->producer_heartbeat.value), 1, memory_order_release );
@@ -69,6 +73,7 @@ PRODUCER_(join_or_initialize)( void *shmem_region, PRODUCER_(producer_t)* produc
         atomic_store_explicit( &(spsc->producer_heartbeat.value), 0, memory_order_release );
         atomic_store_explicit( &(spsc->consumer_heartbeat.value), 0, memory_order_release );
         spsc->capacity = CHANNEL_N;
+        fingerprint_store( &spsc->fingerprint );
         spsc->magic = MAGIC;
 
         producer->spsc = spsc;
@@ -103,6 +108,10 @@ PRODUCER_(join)( void *shmem_region, PRODUCER_(producer_t)* producer ) {
             fprintf( stderr, "Incorrect capacity\n" );
             return 1;
         }
+        if( fingerprint_check( &spsc->fingerprint ) ) {
+            fprintf( stderr, "Element type mismatch\n" );
+            return 1;
+        }
 
         producer->spsc = spsc;
         producer->tail = atomic_load_explicit( &(spsc->tail.value), memory_order_acquire );
@@ -126,6 +135,7 @@ static inline void
 PRODUCER_(sync)( PRODUCER_(producer_t) *producer ) {
     producer->written = 0;
     atomic_store_explicit( &(producer->spsc->tail.value), producer->tail, memory_order_release );
+    notify_wake( &producer->spsc->consumer_notify );
 }
 
 /* Push with backpressure - returns 0 on success, 1 if full */
@@ -144,7 +154,7 @@ PRODUCER_(push)( PRODUCER_(producer_t) *producer, void *value ) {
     /* Calculate the index in the circular buffer */
     uint64_t index = producer->tail & (CHANNEL_N - 1);
 
-    /* Get buffer pointer (starts at offset 640) */
+    /* Get buffer pointer (starts at offset 1280) */
     void *buffer_base = spsc + 1;
     void *addr = (char *)buffer_base + (index * sizeof(CHANNEL_T));
 
@@ -4,6 +4,11 @@
 #include <stdatomic.h>
 #include <stddef.h>
 #include <stdint.h>
+#ifdef __linux__
+#include <linux/futex.h>
+#include <sys/syscall.h>
+#include <unistd.h>
+#endif
 
 #ifndef CHANNEL_NAME
 #error "CHANNEL_NAME must be defined"
@@ -21,6 +26,12 @@
 #error "CHANNEL_N must be positive/nonzero and a power of 2"
 #endif
 
+/* Optional hash of CHANNEL_T's fields, checked against the Rust side's
+   `type_hash` when both are nonzero */
+#ifndef CHANNEL_TYPE_HASH
+#define CHANNEL_TYPE_HASH 0
+#endif
+
 #define QUE_CONCAT3(a, b, c) a##b##c
 #define QUE_EXPAND_THEN_CONCAT3(a, b, c) QUE_CONCAT3(a, b, c)
 #define QUE_(x) QUE_EXPAND_THEN_CONCAT3(CHANNEL_NAME, _, x)
@@ -28,12 +39,46 @@
 /* Constants */
 #define MAGIC 5494763520971851092 /* "TEMPORAL" */
 #define ALIGNMENT 128
+#define LAYOUT_VERSION 4
 
 typedef struct {
     atomic_size_t value;
     char padding[128 - sizeof(atomic_size_t)];
 } __attribute__((aligned(128))) cache_padded_atomic_t;
 
+/* Futex wakeup word used by blocking endpoints */
+typedef struct {
+    atomic_uint epoch;
+    atomic_uint waiters;
+} __attribute__((aligned(128))) notify_t;
+
+/* Element type the channel was created with */
+typedef struct {
+    _Atomic uint64_t size;
+    _Atomic uint64_t align;
+    _Atomic uint64_t layout_version;
+    _Atomic uint64_t type_hash;
+} __attribute__((aligned(128))) fingerprint_t;
+
+/* Process holding one end of the channel. start_time is field 22 of
+   /proc/<pid>/stat, or 0 if unknown. Recorded by the Rust endpoints
+   only; the C endpoints leave it vacant (pid 0) */
+typedef struct {
+    _Atomic uint64_t pid;
+    _Atomic uint64_t start_time;
+} owner_t;
+
+typedef struct {
+    owner_t producer;
+    owner_t consumer;
+} __attribute__((aligned(128))) owners_t;
+
+/* Seqlock guarding typed metadata writes to the padding. Odd while a
+   write is in progress; half of it is the metadata version */
+typedef struct {
+    _Atomic uint64_t seq;
+} __attribute__((aligned(128))) metadata_seq_t;
+
 /* Channel struct matching Rust layout exactly */
 typedef struct QUE_(spsc) {
     /* Offset 0: tail (128 bytes) */
@@ -56,11 +101,45 @@ typedef struct QUE_(spsc) {
     
     /* Offset 632: magic (8 bytes) */
     uint64_t magic;
+
+    /* Offset 640: consumer_notify (128 bytes) */
+    notify_t consumer_notify;
+
+    /* Offset 768: producer_notify (128 bytes) */
+    notify_t producer_notify;
     
-    /* Offset 640: buffer starts here */
+    /* Offset 896: fingerprint (128 bytes) */
+    fingerprint_t fingerprint;
+
+    /* Offset 1024: owners (128 bytes) */
+    owners_t owners;
+
+    /* Offset 1152: metadata_seq (128 bytes) */
+    metadata_seq_t metadata_seq;
+
+    /* Offset 1280: buffer starts here */
     /* Buffer follows immediately after in memory */
 } __attribute__((aligned(128))) QUE_(spsc_t);
 
+/* Header offsets pinned to LAYOUT_VERSION, like the header layout test
+   in que/src/fingerprint.rs. Moving any of them needs a new version */
+#if LAYOUT_VERSION == 4
+_Static_assert( offsetof(QUE_(spsc_t), head) == 128, "header layout changed" );
+_Static_assert( offsetof(QUE_(spsc_t), producer_heartbeat) == 256, "header layout changed" );
+_Static_assert( offsetof(QUE_(spsc_t), consumer_heartbeat) == 384, "header layout changed" );
+_Static_assert( offsetof(QUE_(spsc_t), padding) == 512, "header layout changed" );
+_Static_assert( offsetof(QUE_(spsc_t), capacity) == 624, "header layout changed" );
+_Static_assert( offsetof(QUE_(spsc_t), magic) == 632, "header layout changed" );
+_Static_assert( offsetof(QUE_(spsc_t), consumer_notify) == 640, "header layout changed" );
+_Static_assert( offsetof(QUE_(spsc_t), producer_notify) == 768, "header layout changed" );
+_Static_assert( offsetof(QUE_(spsc_t), fingerprint) == 896, "header layout changed" );
+_Static_assert( offsetof(QUE_(spsc_t), owners) == 1024, "header layout changed" );
+_Static_assert( offsetof(QUE_(spsc_t), metadata_seq) == 1152, "header layout changed" );
+_Static_assert( sizeof(QUE_(spsc_t)) == 1280, "header layout changed" );
+#else
+#error "header offsets are not pinned for this LAYOUT_VERSION"
+#endif
+
 /* Calculate burst amount - 1/4 of buffer or minimum 1 */
 static inline uint64_t
 burst_amount( uint64_t N ) {
@@ -68,10 +147,45 @@ burst_amount( uint64_t N ) {
     return (x == 0) ? 1 : x;
 }
 
+/* Record CHANNEL_T as the element type (call before publishing magic) */
+static inline void
+fingerprint_store( fingerprint_t *fingerprint ) {
+    atomic_store_explicit( &fingerprint->size, sizeof(CHANNEL_T), memory_order_relaxed );
+    atomic_store_explicit( &fingerprint->align, _Alignof(CHANNEL_T), memory_order_relaxed );
+    atomic_store_explicit( &fingerprint->layout_version, LAYOUT_VERSION, memory_order_relaxed );
+    atomic_store_explicit( &fingerprint->type_hash, CHANNEL_TYPE_HASH, memory_order_relaxed );
+}
+
+/* Returns 0 if the channel was created for CHANNEL_T */
+static inline int
+fingerprint_check( fingerprint_t *fingerprint ) {
+    uint64_t type_hash = atomic_load_explicit( &fingerprint->type_hash, memory_order_relaxed );
+    if( atomic_load_explicit( &fingerprint->size, memory_order_relaxed ) != sizeof(CHANNEL_T) ||
+        atomic_load_explicit( &fingerprint->align, memory_order_relaxed ) != _Alignof(CHANNEL_T) ||
+        atomic_load_explicit( &fingerprint->layout_version, memory_order_relaxed ) != LAYOUT_VERSION ||
+        ( CHANNEL_TYPE_HASH != 0 && type_hash != 0 && type_hash != CHANNEL_TYPE_HASH ) ) {
+        return 1;
+    }
+    return 0;
+}
+
+/* Wake any endpoint parked on `notify` (call after publishing).
+   No fence: waiters bound every park and re-check on their own, so a
+   stale `waiters` only delays a wakeup. */
+static inline void
+notify_wake( notify_t *notify ) {
+    if( atomic_load_explicit( &notify->waiters, memory_order_relaxed ) != 0 ) {
+        atomic_fetch_add_explicit( &notify->epoch, 1, memory_order_release );
+#ifdef __linux__
+        syscall( SYS_futex, &notify->epoch, FUTEX_WAKE, INT32_MAX, NULL, NULL, 0 );
+#endif
+    }
+}
+
 /* Helper to get buffer pointer */
 static inline void*
 get_buffer_ptr( QUE_(spsc_t) *spsc ) {
-    return (char *)spsc + 640;  /* Buffer starts at offset 640 */
+    return (char *)spsc + 1280;  /* Buffer starts at offset 1280 */
 }
 
 #endif /* QUE_QUE_H */
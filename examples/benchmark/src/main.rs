@@ -63,6 +63,7 @@ fn main() {
             println!("Producer thread TID: {:?}", tid);
             while !START_FLAG.load(std::sync::atomic::Ordering::Relaxed)
             {
+                std::hint::spin_loop();
             }
             let timer = Instant::now();
             for _ in 0..ITERS {
@@ -87,6 +88,7 @@ fn main() {
             println!("Consumer thread TID: {:?}", tid);
             while !START_FLAG.load(std::sync::atomic::Ordering::Relaxed)
             {
+                std::hint::spin_loop();
             }
             let timer = Instant::now();
             let empty = consume_until_empty(&mut consumer);
@@ -0,0 +1,468 @@
+//! Async adapters for lossless and headless endpoints.
+//!
+//! [AsyncConsumer] implements [Stream] and [AsyncProducer] implements
+//! [Sink]. When an endpoint would otherwise spin, a helper thread parks
+//! on the futex words in the [Channel](crate::Channel) header and wakes
+//! the task once the other side publishes. Since the futex words live in
+//! the channel, producers in other processes (including ones that never
+//! use this module) wake async consumers as well.
+//!
+//! Requires the `async` feature.
+
+use std::{
+    pin::Pin,
+    sync::{Arc, Condvar, Mutex},
+    task::{Context, Poll, Waker},
+    thread::JoinHandle,
+};
+
+use futures_core::Stream;
+use futures_sink::Sink;
+
+use crate::{
+    error::QueError,
+    headless_spmc::{self, Stamping},
+    lossless,
+    wait::Notify,
+    ChannelMode,
+};
+
+/// Wraps a consumer to implement [Stream].
+///
+/// The stream never terminates. It yields [QueError::SpawnFailed] if
+/// the helper thread cannot be spawned; polling again retries.
+pub struct AsyncConsumer<C> {
+    // Declared first so the helper thread is joined before the channel
+    // it points into can be dropped.
+    waiter: Waiter,
+    inner: C,
+}
+
+/// Wraps a producer to implement [Sink].
+///
+/// Values are published on `poll_flush`/`poll_close`, so use e.g.
+/// `SinkExt::send` or `SinkExt::feed` followed by `SinkExt::flush`.
+/// `poll_ready` fails with [QueError::SpawnFailed] if the helper thread
+/// cannot be spawned.
+pub struct AsyncProducer<P> {
+    waiter: Waiter,
+    inner: P,
+}
+
+impl<C> AsyncConsumer<C> {
+    pub fn new(inner: C) -> Self {
+        AsyncConsumer {
+            waiter: Waiter::new(),
+            inner,
+        }
+    }
+
+    pub fn get_ref(&self) -> &C {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> C {
+        let AsyncConsumer { waiter, inner } = self;
+        drop(waiter);
+        inner
+    }
+}
+
+impl<P> AsyncProducer<P> {
+    pub fn new(inner: P) -> Self {
+        AsyncProducer {
+            waiter: Waiter::new(),
+            inner,
+        }
+    }
+
+    pub fn get_ref(&self) -> &P {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut P {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> P {
+        let AsyncProducer { waiter, inner } = self;
+        drop(waiter);
+        inner
+    }
+}
+
+impl<M: ChannelMode<T>, T, const N: usize> Stream
+    for AsyncConsumer<lossless::consumer::Consumer<M, T, N>>
+{
+    type Item = Result<T, QueError>;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<T, QueError>>> {
+        let this = self.get_mut();
+        if let Some(value) = this.inner.pop() {
+            return Poll::Ready(Some(Ok(value)));
+        }
+
+        // Release everything we've read so far; the producer may be
+        // parked waiting on exactly this space.
+        this.inner.publish_head();
+
+        // SAFETY: `inner` keeps the channel alive for this borrow
+        let notify: *const Notify =
+            &this.inner.channel().consumer_notify;
+        this.waiter
+            .poll(unsafe { &*notify }, cx, || this.inner.pop())
+            .map(Some)
+    }
+}
+
+impl<M: ChannelMode<T>, T, const N: usize, S: Stamping<T>> Stream
+    for AsyncConsumer<headless_spmc::consumer::Consumer<M, T, N, S>>
+{
+    type Item = Result<T, QueError>;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<T, QueError>>> {
+        let this = self.get_mut();
+
+        // SAFETY: `inner` keeps the channel alive for this borrow
+        let notify: *const Notify =
+            &this.inner.channel().consumer_notify;
+        this.waiter
+            .poll(unsafe { &*notify }, cx, || this.inner.pop())
+            .map(Some)
+    }
+}
+
+impl<M: ChannelMode<T>, T, const N: usize> Sink<T>
+    for AsyncProducer<lossless::producer::Producer<M, T, N>>
+{
+    type Error = QueError;
+
+    fn poll_ready(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), QueError>> {
+        let this = self.get_mut();
+        if !this.inner.is_full() {
+            return Poll::Ready(Ok(()));
+        }
+
+        // Publish what we have so far; the consumer may be parked
+        // waiting on exactly these values.
+        this.inner.sync();
+
+        // SAFETY: `inner` keeps the channel alive for this borrow
+        let notify: *const Notify =
+            &this.inner.channel().producer_notify;
+        this.waiter
+            .poll(unsafe { &*notify }, cx, || {
+                (!this.inner.is_full()).then_some(())
+            })
+    }
+
+    fn start_send(
+        self: Pin<&mut Self>,
+        item: T,
+    ) -> Result<(), QueError> {
+        self.get_mut().inner.push(item)
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<(), QueError>> {
+        self.get_mut().inner.sync();
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), QueError>> {
+        self.poll_flush(cx)
+    }
+}
+
+impl<M: ChannelMode<T>, T, const N: usize, S: Stamping<T>> Sink<T>
+    for AsyncProducer<headless_spmc::producer::Producer<M, T, N, S>>
+{
+    type Error = QueError;
+
+    /// The headless producer never waits for consumers.
+    fn poll_ready(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<(), QueError>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(
+        self: Pin<&mut Self>,
+        item: T,
+    ) -> Result<(), QueError> {
+        self.get_mut().inner.push(item);
+        Ok(())
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<(), QueError>> {
+        self.get_mut().inner.sync();
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), QueError>> {
+        self.poll_flush(cx)
+    }
+}
+
+/// Parks a helper thread on a [Notify] on behalf of a task and wakes
+/// the task once notified.
+struct Waiter {
+    shared: Arc<Shared>,
+    /// Helper thread and the [Notify] it parks on, spawned on first
+    /// use.
+    thread: Option<(JoinHandle<()>, NotifyPtr)>,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    cond: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    waker: Option<Waker>,
+    /// Epoch returned by [Notify::register] while a wait is in flight.
+    armed: Option<u32>,
+    closed: bool,
+}
+
+/// Pointer to a [Notify] inside a channel that outlives the helper
+/// thread.
+struct NotifyPtr(*const Notify);
+
+unsafe impl Send for NotifyPtr {}
+
+impl NotifyPtr {
+    /// # Safety
+    /// The channel containing the [Notify] must outlive `'a`.
+    unsafe fn as_ref<'a>(&self) -> &'a Notify {
+        &*self.0
+    }
+}
+
+impl Waiter {
+    fn new() -> Self {
+        Waiter {
+            shared: Arc::default(),
+            thread: None,
+        }
+    }
+
+    /// Returns `Ready` if `ready` yields a value, otherwise arms the
+    /// helper thread to wake `cx` on the next notification.
+    ///
+    /// Returns [QueError::SpawnFailed] if the helper thread cannot be
+    /// spawned.
+    fn poll<R>(
+        &mut self,
+        notify: &Notify,
+        cx: &mut Context<'_>,
+        mut ready: impl FnMut() -> Option<R>,
+    ) -> Poll<Result<R, QueError>> {
+        if let Some(r) = ready() {
+            return Poll::Ready(Ok(r));
+        }
+        if let Err(e) = self.spawn(notify) {
+            return Poll::Ready(Err(QueError::SpawnFailed(e)));
+        }
+
+        let mut state = self.shared.state.lock().unwrap();
+        match &state.waker {
+            Some(waker) if waker.will_wake(cx.waker()) => {}
+            _ => state.waker = Some(cx.waker().clone()),
+        }
+
+        if state.armed.is_none() {
+            let epoch = notify.register();
+
+            // Re-check after registering or we may miss a notification
+            if let Some(r) = ready() {
+                notify.deregister();
+                return Poll::Ready(Ok(r));
+            }
+
+            state.armed = Some(epoch);
+            drop(state);
+
+            self.shared.cond.notify_one();
+        }
+
+        Poll::Pending
+    }
+
+    /// Spawns the helper thread parked on `notify`, if not already
+    /// running.
+    fn spawn(&mut self, notify: &Notify) -> std::io::Result<()> {
+        if self.thread.is_some() {
+            return Ok(());
+        }
+
+        let shared = self.shared.clone();
+        let ptr = NotifyPtr(notify);
+        let handle = std::thread::Builder::new()
+            .name("que-async-waiter".to_string())
+            .spawn(move || {
+                // SAFETY: the channel outlives this thread since
+                // `Waiter` joins it on drop
+                let notify = unsafe { ptr.as_ref() };
+                Self::run(&shared, notify)
+            })?;
+        self.thread = Some((handle, NotifyPtr(notify)));
+        Ok(())
+    }
+
+    fn run(shared: &Shared, notify: &Notify) {
+        loop {
+            let epoch = {
+                let mut state = shared.state.lock().unwrap();
+                loop {
+                    if state.closed {
+                        return;
+                    }
+                    if let Some(epoch) = state.armed {
+                        break epoch;
+                    }
+                    state = shared.cond.wait(state).unwrap();
+                }
+            };
+
+            // Parks are bounded, so the task is also woken when a
+            // notification may have been missed and re-checks itself
+            if !notify.notified_since(epoch) {
+                notify.park(epoch, None);
+            }
+            notify.deregister();
+
+            let waker = {
+                let mut state = shared.state.lock().unwrap();
+                state.armed = None;
+                state.waker.take()
+            };
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl Drop for Waiter {
+    fn drop(&mut self) {
+        let Some((thread, notify)) = self.thread.take() else {
+            return;
+        };
+
+        let armed = {
+            let mut state = self.shared.state.lock().unwrap();
+            state.closed = true;
+            state.armed.is_some()
+        };
+        self.shared.cond.notify_one();
+
+        // Kick the helper thread off the futex. Other waiters on this
+        // channel see a spurious wakeup and go back to sleep.
+        if armed {
+            // SAFETY: the channel outlives the `Waiter`
+            unsafe { notify.as_ref() }.wake_all();
+        }
+
+        let _ = thread.join();
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use std::time::Duration;
+
+    use futures::{executor::block_on, SinkExt, StreamExt};
+
+    use super::*;
+    use crate::{
+        headless_spmc::headless_pair, lossless::lossless_pair,
+    };
+
+    #[test]
+    fn test_lossless_stream_wakes_on_sync() {
+        let (mut producer, consumer) = lossless_pair::<u64, 16>();
+        let mut consumer = AsyncConsumer::new(consumer);
+
+        let read = std::thread::spawn(move || {
+            block_on(async {
+                let mut got = Vec::new();
+                while got.len() < 100 {
+                    got.push(consumer.next().await.unwrap().unwrap());
+                }
+                got
+            })
+        });
+
+        for i in 0..100 {
+            std::thread::sleep(Duration::from_micros(100));
+            producer.push_blocking(i);
+            producer.sync();
+        }
+
+        assert_eq!(read.join().unwrap(), (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_lossless_sink_waits_for_space() {
+        let (producer, mut consumer) = lossless_pair::<u64, 4>();
+        let mut producer = AsyncProducer::new(producer);
+
+        let write = std::thread::spawn(move || {
+            block_on(async {
+                for i in 0..100 {
+                    producer.send(i).await.unwrap();
+                }
+            })
+        });
+
+        let read: Vec<u64> = (0..100)
+            .map(|_| consumer.pop_blocking())
+            .collect();
+        write.join().unwrap();
+        assert_eq!(read, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_headless_stream_and_sink() {
+        let (producer, consumer) = headless_pair::<u64, 16>();
+        let mut producer = AsyncProducer::new(producer);
+        let mut consumer = AsyncConsumer::new(consumer);
+
+        let read = std::thread::spawn(move || {
+            block_on(async { consumer.next().await.unwrap().unwrap() })
+        });
+
+        std::thread::sleep(Duration::from_millis(10));
+        block_on(producer.send(69)).unwrap();
+
+        assert_eq!(read.join().unwrap(), 69);
+    }
+}
@@ -0,0 +1,389 @@
+use std::{
+    mem::MaybeUninit,
+    ptr::NonNull,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use bytemuck::{AnyBitPattern, Pod};
+
+use crate::{
+    atomic_compat::{fence, Ordering},
+    cursor::Cursor,
+    error::QueError,
+    shmem::{Shmem, ShmemConfig},
+    Channel, ChannelMode, Metadata, MultiChannel, ShmemMode,
+};
+
+use super::{burst_amount, MAGIC};
+
+unsafe impl<M: ChannelMode<T>, T, const N: usize> Send
+    for Consumer<M, T, N>
+{
+}
+
+/// One of up to [MAX_CURSORS](crate::MAX_CURSORS) subscribers of a
+/// broadcast channel. Holds a cursor in the channel header until
+/// dropped or evicted.
+#[repr(C)]
+pub struct Consumer<M: ChannelMode<T>, T, const N: usize> {
+    spsc: NonNull<MultiChannel<M, T, N>>,
+    head: usize,
+    /// Position last stored in our cursor
+    published: usize,
+    items_since_last_sync: usize,
+    /// Index of our cursor in the channel header
+    cursor: usize,
+    /// Generation of our cursor when we claimed it
+    generation: u64,
+    last_producer_heartbeat: usize,
+    /// Mapping backing the channel, if joined through shmem.
+    shmem: Option<Shmem>,
+}
+
+impl<T: AnyBitPattern, const N: usize> Consumer<ShmemMode, T, N> {
+    /// Joins an existing broadcast channel backed by shared memory as a
+    /// subscriber.
+    ///
+    /// # Safety
+    /// The shared memory region must only ever be mapped as a broadcast
+    /// `Channel` with the same `T` and `N`.
+    pub unsafe fn join_shmem(
+        shmem_id: &str,
+        #[cfg(target_os = "linux")] config: impl Into<ShmemConfig>,
+    ) -> Result<Consumer<ShmemMode, T, N>, QueError> {
+        #[cfg(not(target_os = "linux"))]
+        let config = ShmemConfig::default();
+        #[cfg(target_os = "linux")]
+        let config: ShmemConfig = config.into();
+
+        // Calculate buffer size.
+        // If using huge pages, we must uplign to page size.
+        let buffer_size: i64 = config
+            .page_size()
+            .mem_size(
+                core::mem::size_of::<MultiChannel<ShmemMode, T, N>>(),
+            )
+            .try_into()
+            .map_err(|_| QueError::InvalidSize)?;
+
+        // Open shmem
+        let shmem = config.open_existing(shmem_id, buffer_size)?;
+
+        let mut consumer = Consumer::join(shmem.get_mut_ptr())?;
+        consumer.shmem = Some(shmem);
+
+        Ok(consumer)
+    }
+
+    /// Unlinks the shared memory object when this endpoint is dropped,
+    /// if it created the object. Has no effect for endpoints joined
+    /// to an existing object or to a caller-provided buffer.
+    pub fn set_unlink_on_drop(&mut self, unlink: bool) {
+        if let Some(shmem) = self.shmem.as_mut() {
+            shmem.set_unlink_on_drop(unlink);
+        }
+    }
+}
+
+impl<M: ChannelMode<T>, T: Copy, const N: usize> Consumer<M, T, N> {
+    const MODULO_MASK: usize = N - 1;
+
+    /// Joins an existing broadcast channel backed by `buffer` as a
+    /// subscriber, registering a cursor in the channel header. Only
+    /// elements published after joining are seen.
+    ///
+    /// # Safety
+    /// This must point to a buffer of proper size and alignment.
+    ///
+    /// In LocalMode, must point to a region allocated by an Arc with the strong count already incremented!
+    pub unsafe fn join(
+        buffer: *mut u8,
+    ) -> Result<Consumer<M, T, N>, QueError> {
+        assert!(
+            N > 0 && N.is_power_of_two(),
+            "Capacity must be a power of two"
+        );
+        assert!((buffer as usize).is_multiple_of(128), "unaligned");
+
+        let spsc: *mut MultiChannel<M, T, N> = buffer.cast();
+
+        // Check magic
+        let magic = (*spsc)
+            .channel
+            .magic
+            .load(Ordering::Acquire);
+        let capacity = (*spsc)
+            .channel
+            .capacity
+            .load(Ordering::Acquire);
+        if magic == MAGIC {
+            // Check capacity
+            if capacity != N {
+                return Err(QueError::IncorrectCapacity(capacity));
+            }
+
+            (*spsc).channel.check_fingerprint(0)?;
+
+            let (cursor, generation) = (*spsc)
+                .cursors
+                .acquire()
+                .ok_or(QueError::TooManyConsumers)?;
+
+            // The producer may have computed its limit before seeing our
+            // cursor, but never past the tail published at that time.
+            // Starting at a tail loaded after publishing the cursor is
+            // therefore safe either way.
+            let published = (*spsc)
+                .channel
+                .tail
+                .load(Ordering::SeqCst);
+            (*spsc)
+                .cursors
+                .get(cursor)
+                .announce(generation, published);
+            let head = (*spsc)
+                .channel
+                .tail
+                .load(Ordering::SeqCst);
+
+            if M::BACKED_BY_ARCC {
+                unsafe {
+                    Arc::increment_strong_count(spsc);
+                }
+            }
+
+            Ok(Consumer {
+                spsc: NonNull::new_unchecked(spsc),
+                shmem: None,
+                head,
+                published,
+                items_since_last_sync: 0,
+                cursor,
+                generation,
+                last_producer_heartbeat: (*spsc)
+                    .channel
+                    .producer_heartbeat
+                    .load(Ordering::Acquire),
+            })
+        } else if magic == 0 {
+            // Technically could be corrupted but uninitialized
+            // is most likely explanation
+            Err(QueError::Uninitialized)
+        } else {
+            // Magic is not MAGIC and not zero
+            Err(QueError::CorruptionDetected)
+        }
+    }
+
+    /// Attempts to read the next element. Returns `Ok(None)` if the
+    /// subscriber is caught up and [QueError::Evicted] once the producer
+    /// evicted this subscriber.
+    pub fn pop(&mut self) -> Result<Option<T>, QueError> {
+        let spsc = unsafe { &*self.spsc.as_ptr() };
+        if spsc.tail.load(Ordering::Acquire) <= self.head {
+            return self.check_evicted().map(|_| None);
+        }
+
+        // If we were evicted the producer may overwrite the slot while
+        // we copy it, so only assume the copy is valid once we know we
+        // still own our cursor.
+        let mut value = MaybeUninit::<T>::uninit();
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                spsc.buffer
+                    .as_ptr()
+                    .add(self.head & Self::MODULO_MASK),
+                value.as_mut_ptr(),
+                1,
+            );
+        }
+        fence(Ordering::Acquire);
+        self.check_evicted()?;
+
+        self.head += 1;
+        self.items_since_last_sync += 1;
+        if self.items_since_last_sync >= burst_amount::<N>() {
+            self.publish()?;
+        }
+
+        Ok(Some(unsafe { value.assume_init() }))
+    }
+
+    /// Reads the next element, waiting for the producer if the
+    /// subscriber is caught up.
+    ///
+    /// Spins briefly and then parks on a futex in the channel header,
+    /// so this also works across processes.
+    pub fn pop_blocking(&mut self) -> Result<T, QueError> {
+        loop {
+            // Without a deadline we can only return with a value
+            if let Some(value) = self.pop_until(None)? {
+                return Ok(value);
+            }
+        }
+    }
+
+    /// Reads the next element, waiting up to `timeout` for the producer.
+    /// Returns `Ok(None)` if nothing was published in time.
+    pub fn pop_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<Option<T>, QueError> {
+        self.pop_until(Instant::now().checked_add(timeout))
+    }
+
+    fn pop_until(
+        &mut self,
+        deadline: Option<Instant>,
+    ) -> Result<Option<T>, QueError> {
+        if let Some(value) = self.pop()? {
+            return Ok(Some(value));
+        }
+
+        // Release everything we've read so far; the producer may be
+        // parked waiting on exactly this space.
+        self.publish()?;
+
+        let spsc = unsafe { &*self.spsc.as_ptr() };
+        spsc.consumer_notify
+            .wait_until(deadline, || self.pop().transpose())
+            .transpose()
+    }
+
+    /// Returns `true` if the producer evicted this subscriber. Evicted
+    /// subscribers must join again to keep reading.
+    pub fn is_evicted(&self) -> bool {
+        self.cursor()
+            .generation
+            .load(Ordering::Relaxed)
+            != self.generation
+    }
+
+    /// Increments this subscriber's heartbeat.
+    ///
+    /// The producer evicts subscribers that neither beat nor published
+    /// a new position (every burst of reads) between two calls of
+    /// [Producer::evict_stale](super::producer::Producer::evict_stale),
+    /// so this is only needed while not reading.
+    pub fn beat(&self) {
+        if !self.is_evicted() {
+            self.cursor()
+                .heartbeat
+                .fetch_add(1, Ordering::Release);
+        }
+    }
+
+    /// Checks if the producer has incremented its heartbeat since last
+    /// called. Can be used by the subscriber to see if the producer is
+    /// still online if done periodically.
+    pub fn producer_heartbeat(&mut self) -> bool {
+        let heartbeat = unsafe {
+            (*self.spsc.as_ptr())
+                .channel
+                .producer_heartbeat
+                .load(Ordering::Acquire)
+        };
+
+        if heartbeat != self.last_producer_heartbeat {
+            self.last_producer_heartbeat = heartbeat;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns pointer to inner padding.
+    ///
+    /// User is responsible for safe usage.
+    ///
+    /// Unsynchronized: prefer [metadata](Self::metadata) for values
+    /// that change while the channel is in use.
+    ///
+    /// Byte array is 128 byte aligned.
+    pub fn get_padding_ptr(&self) -> NonNull<[u8; 112]> {
+        unsafe {
+            NonNull::new_unchecked(
+                self.spsc.cast::<u8>().as_ptr().add(512),
+            )
+            .cast()
+        }
+    }
+
+    /// Returns a typed, versioned view of the metadata region (the
+    /// same bytes as [get_padding_ptr](Self::get_padding_ptr)).
+    ///
+    /// Fails to compile if `U` is larger than 112 bytes.
+    pub fn metadata<U: Pod>(&self) -> Metadata<'_, U> {
+        unsafe { Channel::<M, T, N>::metadata(self.spsc.cast()) }
+    }
+
+    #[inline(always)]
+    fn check_evicted(&self) -> Result<(), QueError> {
+        if self.is_evicted() {
+            Err(QueError::Evicted)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Publishes the local head in our cursor, waking a parked
+    /// producer.
+    #[inline(always)]
+    fn publish(&mut self) -> Result<(), QueError> {
+        self.items_since_last_sync = 0;
+        if self.head == self.published {
+            return self.check_evicted();
+        }
+
+        // Fails if the producer evicted us, including when the cursor
+        // was handed to a new subscriber since the check
+        self.check_evicted()?;
+        if !self.cursor().advance(
+            self.generation,
+            self.published,
+            self.head,
+        ) {
+            return Err(QueError::Evicted);
+        }
+        self.published = self.head;
+
+        // Reading counts as being alive
+        self.cursor()
+            .heartbeat
+            .fetch_add(1, Ordering::Release);
+
+        unsafe {
+            (*self.spsc.as_ptr())
+                .channel
+                .producer_notify
+                .notify()
+        };
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn cursor(&self) -> &Cursor {
+        unsafe {
+            (*self.spsc.as_ptr())
+                .cursors
+                .get(self.cursor)
+        }
+    }
+}
+
+impl<M: ChannelMode<T>, T, const N: usize> Drop for Consumer<M, T, N> {
+    fn drop(&mut self) {
+        // No-op if we were evicted and the cursor changed hands
+        unsafe {
+            (*self.spsc.as_ptr())
+                .cursors
+                .release_generation(self.cursor, self.generation);
+        }
+
+        // LocalMode is backed by arc
+        if M::BACKED_BY_ARCC {
+            unsafe { drop(Arc::from_raw(self.spsc.as_ptr())) }
+        }
+    }
+}
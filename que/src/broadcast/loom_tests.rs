@@ -0,0 +1,132 @@
+//! Loom permutation tests for the broadcast channel (`ShmemMode`, stack
+//! buffer).
+//!
+//! See `lossless::loom_tests` for why `LocalMode` is not used here.
+//!
+//! ```text
+//! cargo test -p que --features loom broadcast::loom_tests -- --test-threads=1
+//! ```
+
+use std::mem::size_of;
+
+use loom::thread;
+
+use super::{consumer::Consumer, producer::Producer, MAGIC};
+use crate::{error::QueError, MultiChannel, ShmemMode};
+
+macro_rules! channel_storage {
+    ($name:ident, $n:literal) => {
+        #[repr(C, align(128))]
+        struct $name([u8; size_of::<MultiChannel<ShmemMode, u64, $n>>()]);
+
+        impl $name {
+            fn new_zeroed() -> Self {
+                Self([0; size_of::<MultiChannel<ShmemMode, u64, $n>>()])
+            }
+
+            fn prepare(&mut self) -> *mut u8 {
+                let ptr = self.0.as_mut_ptr();
+                unsafe {
+                    MultiChannel::<ShmemMode, u64, $n>::loom_write_fresh_at(
+                        ptr, MAGIC, [0; $n],
+                    );
+                }
+                ptr
+            }
+        }
+    };
+}
+
+channel_storage!(ChannelStorage2, 2);
+
+fn push_retry<const N: usize>(
+    producer: &mut Producer<ShmemMode, u64, N>,
+    value: u64,
+) {
+    while producer.push(value).is_err() {
+        thread::yield_now();
+    }
+    producer.sync();
+}
+
+#[test]
+fn loom_broadcast_two_subscribers() {
+    loom::model(|| {
+        let mut storage = ChannelStorage2::new_zeroed();
+        let ptr = storage.prepare();
+
+        let mut producer = unsafe {
+            Producer::<ShmemMode, u64, 2>::join(ptr).unwrap()
+        };
+        let mut consumers = unsafe {
+            [
+                Consumer::<ShmemMode, u64, 2>::join(ptr).unwrap(),
+                Consumer::<ShmemMode, u64, 2>::join(ptr).unwrap(),
+            ]
+        };
+
+        // Wraps around, so the producer has to wait for both
+        let p = thread::spawn(move || {
+            for i in 0u64..3 {
+                push_retry(&mut producer, i);
+            }
+        });
+
+        let mut got = [vec![], vec![]];
+        while got.iter().any(|got| got.len() < 3) {
+            for (consumer, got) in consumers.iter_mut().zip(&mut got) {
+                match consumer.pop().unwrap() {
+                    Some(value) => got.push(value),
+                    None => thread::yield_now(),
+                }
+            }
+        }
+        assert_eq!(got, [vec![0, 1, 2], vec![0, 1, 2]]);
+
+        p.join().unwrap();
+    });
+}
+
+#[test]
+fn loom_broadcast_evicted_subscriber_fails() {
+    loom::model(|| {
+        let mut storage = ChannelStorage2::new_zeroed();
+        let ptr = storage.prepare();
+
+        let mut producer = unsafe {
+            Producer::<ShmemMode, u64, 2>::join(ptr).unwrap()
+        };
+        let mut consumer = unsafe {
+            Consumer::<ShmemMode, u64, 2>::join(ptr).unwrap()
+        };
+
+        push_retry(&mut producer, 0);
+        push_retry(&mut producer, 1);
+
+        // Evicts the never-beating subscriber on the second call, then
+        // laps it
+        let p = thread::spawn(move || {
+            producer.evict_stale();
+            while producer.evict_stale() == 0 {
+                thread::yield_now();
+            }
+            push_retry(&mut producer, 2);
+        });
+
+        // Either reads valid elements in order or learns it was evicted
+        let mut expected = 0;
+        loop {
+            match consumer.pop() {
+                Ok(Some(value)) => {
+                    assert_eq!(value, expected);
+                    expected += 1;
+                }
+                Ok(None) => thread::yield_now(),
+                Err(QueError::Evicted) => break,
+                Err(e) => panic!("{e}"),
+            }
+        }
+
+        p.join().unwrap();
+    });
+}
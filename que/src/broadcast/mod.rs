@@ -0,0 +1,199 @@
+//! Lossless single-producer broadcast channel.
+//!
+//! Every subscriber sees every element. Each subscriber holds a cursor
+//! in the [MultiChannel] cursor table, registered
+//! on join and released on drop, and
+//! publishes its position every burst like the lossless consumer
+//! publishes `head`. The producer never writes past the slowest active
+//! subscriber.
+//!
+//! Subscribers show they are alive by publishing new positions or
+//! calling [consumer::Consumer::beat]. One that does neither is evicted
+//! by [producer::Producer::evict_stale], or by a blocked push once the
+//! stale timeout passes, after which its reads fail with
+//! [QueError::Evicted](crate::error::QueError::Evicted) instead of
+//! holding back the producer.
+
+pub mod consumer;
+pub mod producer;
+
+use std::{mem::MaybeUninit, sync::Arc};
+
+use consumer::Consumer;
+use producer::Producer;
+
+use crate::{LocalMode, MultiChannel};
+
+/// Marks a channel initialized as a broadcast channel.
+pub const MAGIC: u64 = u64::from_le_bytes(*b"TEMPBCST");
+
+pub fn broadcast_pair<T: Copy + Send, const N: usize>(
+) -> (Producer<LocalMode, T, N>, Consumer<LocalMode, T, N>) {
+    let arc_uninit = Arc::<MultiChannel<LocalMode, T, N>>::new_uninit();
+    let ptr: *mut MaybeUninit<MultiChannel<LocalMode, T, N>> =
+        Arc::into_raw(arc_uninit).cast_mut();
+
+    unsafe {
+        *ptr = core::mem::zeroed();
+    }
+
+    let producer = unsafe {
+        Producer::join_or_initialize_in_(ptr.cast()).unwrap()
+    };
+    let consumer = producer.subscribe().unwrap();
+
+    unsafe {
+        Arc::decrement_strong_count(ptr);
+    }
+
+    (producer, consumer)
+}
+
+const fn burst_amount<const N: usize>() -> usize {
+    // Subscribers release up to 1/4 of the buffer at a time
+    const BURST_DENOM: usize = 4;
+
+    let x = N / BURST_DENOM;
+
+    if x == 0 {
+        1
+    } else {
+        x
+    }
+}
+
+// Not run with `feature = "loom"`, see `lossless::tests`. Loom coverage
+// is in `loom_tests` (`ShmemMode`, stack buffer, `loom::thread`).
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use crate::error::QueError;
+
+    #[test]
+    fn test_every_subscriber_sees_every_element() {
+        let (mut producer, mut consumer) = broadcast_pair::<u64, 16>();
+        let mut consumer2 = producer.subscribe().unwrap();
+        assert_eq!(producer.subscribers(), 2);
+
+        producer.push(69).unwrap();
+        producer.push(70).unwrap();
+        assert_eq!(consumer.pop().unwrap(), None);
+
+        producer.sync();
+        assert_eq!(consumer.pop().unwrap(), Some(69));
+        assert_eq!(consumer.pop().unwrap(), Some(70));
+        assert_eq!(consumer.pop().unwrap(), None);
+        assert_eq!(consumer2.pop().unwrap(), Some(69));
+        assert_eq!(consumer2.pop().unwrap(), Some(70));
+        assert_eq!(consumer2.pop().unwrap(), None);
+    }
+
+    #[test]
+    fn test_blocks_on_slowest_subscriber() {
+        let (mut producer, mut consumer) = broadcast_pair::<u64, 4>();
+        let mut slow = producer.subscribe().unwrap();
+
+        for i in 0..4 {
+            producer.push(i).unwrap();
+        }
+        producer.sync();
+        while consumer.pop().unwrap().is_some() {}
+        assert!(matches!(producer.push(4), Err(QueError::Full)));
+
+        assert_eq!(slow.pop().unwrap(), Some(0));
+        producer.push(4).unwrap();
+        assert!(matches!(producer.push(5), Err(QueError::Full)));
+
+        // Dropping a subscriber releases its cursor
+        drop(slow);
+        producer.push(5).unwrap();
+        assert_eq!(producer.subscribers(), 1);
+    }
+
+    #[test]
+    fn test_evict_stale() {
+        let (mut producer, mut consumer) = broadcast_pair::<u64, 4>();
+        let mut stale = producer.subscribe().unwrap();
+
+        for i in 0..4 {
+            producer.push(i).unwrap();
+        }
+        producer.sync();
+        while consumer.pop().unwrap().is_some() {}
+
+        // The first call only records heartbeats
+        consumer.beat();
+        stale.beat();
+        assert_eq!(producer.evict_stale(), 0);
+
+        consumer.beat();
+        assert_eq!(producer.evict_stale(), 1);
+        assert_eq!(producer.subscribers(), 1);
+
+        producer.push(4).unwrap();
+        producer.sync();
+        assert!(stale.is_evicted());
+        assert!(matches!(stale.pop(), Err(QueError::Evicted)));
+
+        // The freed cursor can be reused
+        let mut fresh = producer.subscribe().unwrap();
+        assert_eq!(fresh.pop().unwrap(), None);
+        assert_eq!(consumer.pop().unwrap(), Some(4));
+        drop(stale);
+        assert_eq!(producer.subscribers(), 2);
+    }
+
+    #[test]
+    fn test_push_blocking_evicts_dead_subscriber() {
+        let (producer, mut consumer) = broadcast_pair::<u64, 4>();
+        let mut producer = producer.with_stale_timeout(Some(
+            std::time::Duration::from_millis(20),
+        ));
+
+        // Dies without releasing its cursor
+        core::mem::forget(producer.subscribe().unwrap());
+
+        let reader = std::thread::spawn(move || {
+            (0..16)
+                .map(|_| consumer.pop_blocking().unwrap())
+                .collect::<Vec<_>>()
+        });
+        for i in 0..16 {
+            producer.push_blocking(i);
+        }
+        producer.sync();
+
+        assert_eq!(reader.join().unwrap(), (0..16).collect::<Vec<_>>());
+
+        // The reader dropped its cursor, the dead one was evicted
+        assert_eq!(producer.subscribers(), 0);
+    }
+
+    #[test]
+    fn test_threads() {
+        const ITEMS: u64 = 20_000;
+
+        let (mut producer, consumer) = broadcast_pair::<u64, 64>();
+        let consumers = [consumer, producer.subscribe().unwrap()];
+
+        let handles = consumers.map(|mut consumer| {
+            std::thread::spawn(move || {
+                for i in 0..ITEMS {
+                    assert_eq!(consumer.pop_blocking().unwrap(), i);
+                }
+            })
+        });
+
+        for i in 0..ITEMS {
+            producer.push_blocking(i);
+        }
+        producer.sync();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests;
@@ -0,0 +1,494 @@
+use std::{
+    ptr::NonNull,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use bytemuck::{AnyBitPattern, Pod};
+
+use crate::{
+    atomic_compat::{fence, Ordering},
+    error::QueError,
+    shmem::{Shmem, ShmemConfig},
+    Channel, ChannelMode, LocalMode, Metadata, MultiChannel, ShmemMode,
+    MAX_CURSORS,
+};
+
+use super::{consumer::Consumer, MAGIC};
+
+/// Default for [Producer::with_stale_timeout].
+pub const DEFAULT_STALE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The single producer of a broadcast channel.
+#[repr(C, align(128))]
+pub struct Producer<M: ChannelMode<T>, T, const N: usize> {
+    spsc: NonNull<MultiChannel<M, T, N>>,
+    tail: usize,
+    /// Last lowest position subscribers may still read. Only reloaded
+    /// when the channel appears full.
+    limit: usize,
+    /// Generation and heartbeat of every cursor at the last
+    /// [Producer::evict_stale].
+    last_seen: [(u64, usize); MAX_CURSORS],
+    /// When [Producer::evict_stale] last ran.
+    last_evict: Option<Instant>,
+    /// How long a subscriber holding back a blocked push may go
+    /// without a heartbeat before it is evicted.
+    stale_timeout: Option<Duration>,
+    /// Mapping backing the channel, if joined through shmem.
+    shmem: Option<Shmem>,
+}
+
+unsafe impl<M: ChannelMode<T>, T, const N: usize> Send
+    for Producer<M, T, N>
+{
+}
+
+impl<T: AnyBitPattern, const N: usize> Producer<ShmemMode, T, N> {
+    /// Joins or creates a broadcast channel backed by shared memory as
+    /// the producer.
+    ///
+    /// # Safety
+    /// The shared memory region must only ever be mapped as a broadcast
+    /// `Channel` with the same `T` and `N`.
+    pub unsafe fn join_or_create_shmem(
+        shmem_id: &str,
+        #[cfg(target_os = "linux")] config: impl Into<ShmemConfig>,
+    ) -> Result<Producer<ShmemMode, T, N>, QueError> {
+        #[cfg(not(target_os = "linux"))]
+        let config = ShmemConfig::default();
+        #[cfg(target_os = "linux")]
+        let config: ShmemConfig = config.into();
+
+        // Calculate buffer size.
+        // If using huge pages, we must uplign to page size.
+        let buffer_size: i64 = config
+            .page_size()
+            .mem_size(
+                core::mem::size_of::<MultiChannel<ShmemMode, T, N>>(),
+            )
+            .try_into()
+            .map_err(|_| QueError::InvalidSize)?;
+
+        // Open or create shmem
+        let shmem = config.open_or_create(shmem_id, buffer_size)?;
+
+        let mut producer =
+            Self::join_or_initialize_in_(shmem.get_mut_ptr())?;
+        producer.shmem = Some(shmem);
+
+        Ok(producer)
+    }
+
+    /// Initializes a broadcast channel backed by `buffer` and joins as
+    /// the producer.
+    ///
+    /// # Safety
+    /// This must point to a buffer of proper size and alignment.
+    pub unsafe fn join_or_initialize_in(
+        buffer: *mut u8,
+    ) -> Result<Producer<ShmemMode, T, N>, QueError> {
+        Self::join_or_initialize_in_(buffer)
+    }
+
+    /// Joins an existing broadcast channel backed by `buffer` as the
+    /// producer.
+    ///
+    /// # Safety
+    /// This must point to a buffer of proper size and alignment.
+    pub unsafe fn join(
+        buffer: *mut u8,
+    ) -> Result<Producer<ShmemMode, T, N>, QueError> {
+        Self::join_(buffer)
+    }
+
+    /// Unlinks the shared memory object when this endpoint is dropped,
+    /// if it created the object. Has no effect for endpoints joined
+    /// to an existing object or to a caller-provided buffer.
+    pub fn set_unlink_on_drop(&mut self, unlink: bool) {
+        if let Some(shmem) = self.shmem.as_mut() {
+            shmem.set_unlink_on_drop(unlink);
+        }
+    }
+}
+
+impl<T: Copy + Send, const N: usize> Producer<LocalMode, T, N> {
+    /// Registers a new subscriber that sees elements published from
+    /// now on.
+    ///
+    /// Returns [QueError::TooManyConsumers] if all cursors are taken.
+    pub fn subscribe(
+        &self,
+    ) -> Result<Consumer<LocalMode, T, N>, QueError> {
+        unsafe { Consumer::join(self.spsc.as_ptr().cast()) }
+    }
+}
+
+impl<M: ChannelMode<T>, T: Copy, const N: usize> Producer<M, T, N> {
+    pub const MODULO_MASK: usize = N - 1;
+
+    pub(crate) unsafe fn join_or_initialize_in_(
+        buffer: *mut u8,
+    ) -> Result<Producer<M, T, N>, QueError> {
+        assert!(
+            N > 0 && N.is_power_of_two(),
+            "Capacity must be a power of two"
+        );
+        assert!((buffer as usize).is_multiple_of(128), "unaligned");
+
+        let spsc: *mut MultiChannel<M, T, N> = buffer.cast();
+
+        // Check magic
+        let magic = (*spsc)
+            .channel
+            .magic
+            .load(Ordering::Acquire);
+        if magic == MAGIC {
+            (*spsc)
+                .channel
+                .producer_heartbeat
+                .fetch_add(1, Ordering::Release);
+            Self::join_(buffer)
+        } else if magic == 0 {
+            (*spsc)
+                .channel
+                .tail
+                .store(0, Ordering::Release);
+            (*spsc)
+                .channel
+                .producer_heartbeat
+                .store(0, Ordering::Release);
+            (*spsc).cursors.reset();
+            (*spsc)
+                .channel
+                .capacity
+                .store(N, Ordering::Release);
+            (*spsc).channel.store_fingerprint(0);
+            (*spsc)
+                .channel
+                .magic
+                .store(MAGIC, Ordering::Release);
+            Self::join_(buffer)
+        } else {
+            // Magic is not MAGIC and not zero
+            Err(QueError::CorruptionDetected)
+        }
+    }
+
+    pub(crate) unsafe fn join_(
+        buffer: *mut u8,
+    ) -> Result<Producer<M, T, N>, QueError> {
+        assert!(
+            N > 0 && N.is_power_of_two(),
+            "Capacity must be a power of two"
+        );
+        assert!((buffer as usize).is_multiple_of(128), "unaligned");
+
+        let spsc: *mut MultiChannel<M, T, N> = buffer.cast();
+
+        let magic = (*spsc)
+            .channel
+            .magic
+            .load(Ordering::Acquire);
+        let capacity = (*spsc)
+            .channel
+            .capacity
+            .load(Ordering::Acquire);
+        if magic == MAGIC {
+            if capacity != N {
+                return Err(QueError::IncorrectCapacity(capacity));
+            }
+
+            (*spsc).channel.check_fingerprint(0)?;
+
+            if M::BACKED_BY_ARCC {
+                unsafe {
+                    Arc::increment_strong_count(spsc);
+                }
+            }
+
+            // Successful join if magic and capacity is correct
+            let tail = (*spsc)
+                .channel
+                .tail
+                .load(Ordering::Acquire);
+            Ok(Producer {
+                spsc: NonNull::new(spsc).unwrap(),
+                shmem: None,
+                tail,
+                limit: (*spsc).cursors.min_position(tail),
+                last_seen: [(0, 0); MAX_CURSORS],
+                last_evict: None,
+                stale_timeout: Some(DEFAULT_STALE_TIMEOUT),
+            })
+        } else if magic == 0 {
+            // Technically could be corrupted but uninitialized
+            // is most likely explanation
+            Err(QueError::Uninitialized)
+        } else {
+            // Magic is not MAGIC and not zero
+            Err(QueError::CorruptionDetected)
+        }
+    }
+
+    /// Attempts to write a new element to the channel. If the slowest
+    /// subscriber has not released the slot yet, returns
+    /// [QueError::Full].
+    ///
+    /// Like the lossless producer, the value is not published until
+    /// [Producer::sync].
+    #[inline(always)]
+    pub fn push(&mut self, value: T) -> Result<(), QueError> {
+        if self.is_full() {
+            return Err(QueError::Full);
+        }
+
+        self.write_unchecked(value);
+
+        Ok(())
+    }
+
+    /// Writes a new element to the channel, waiting for the slowest
+    /// live subscriber to make room if the channel is full.
+    ///
+    /// Spins briefly and then parks on a futex in the channel header,
+    /// so this also works across processes. While waiting, subscribers
+    /// holding back the producer are evicted once they go without a
+    /// heartbeat for the stale timeout, see
+    /// [Producer::with_stale_timeout].
+    pub fn push_blocking(&mut self, value: T) {
+        // Without a deadline we can only return once there is room
+        let _ = self.push_until(value, None);
+    }
+
+    /// Writes a new element to the channel, waiting up to `timeout` for
+    /// the slowest live subscriber to make room, like
+    /// [Producer::push_blocking]. If the channel is still full after
+    /// `timeout`, returns [QueError::Full].
+    pub fn push_timeout(
+        &mut self,
+        value: T,
+        timeout: Duration,
+    ) -> Result<(), QueError> {
+        self.push_until(value, Instant::now().checked_add(timeout))
+    }
+
+    fn push_until(
+        &mut self,
+        value: T,
+        deadline: Option<Instant>,
+    ) -> Result<(), QueError> {
+        if self.is_full() {
+            // Publish what we have so far; subscribers may be parked
+            // waiting on exactly these values.
+            self.sync();
+
+            let spsc = unsafe { &*self.spsc.as_ptr() };
+            spsc.producer_notify
+                .wait_until(deadline, || {
+                    if self.is_full() && self.stale_is_due() {
+                        // Only subscribers we are waiting on, so idle
+                        // ones parked for the next element stay
+                        self.evict_stale_before(Some(
+                            self.tail + 1 - N,
+                        ));
+                    }
+                    (!self.is_full()).then_some(())
+                })
+                .ok_or(QueError::Full)?;
+        }
+
+        self.write_unchecked(value);
+
+        Ok(())
+    }
+
+    /// Sets how long a subscriber that holds back a blocked
+    /// [push_blocking](Self::push_blocking) or
+    /// [push_timeout](Self::push_timeout) may go without a heartbeat
+    /// before it is evicted, or `None` to only evict through
+    /// [Producer::evict_stale]. Defaults to [DEFAULT_STALE_TIMEOUT].
+    pub fn with_stale_timeout(
+        mut self,
+        timeout: Option<Duration>,
+    ) -> Self {
+        self.set_stale_timeout(timeout);
+        self
+    }
+
+    /// Changes the stale timeout, see [Producer::with_stale_timeout].
+    pub fn set_stale_timeout(&mut self, timeout: Option<Duration>) {
+        self.stale_timeout = timeout;
+    }
+
+    /// Returns `true` if a blocked push should check for stale
+    /// subscribers.
+    fn stale_is_due(&self) -> bool {
+        self.stale_timeout
+            .is_some_and(|timeout| {
+                self.last_evict
+                    .is_none_or(|at| at.elapsed() >= timeout)
+            })
+    }
+
+    /// Returns `true` if writing at the local tail could overwrite an
+    /// element an active subscriber has not read yet.
+    #[inline(always)]
+    fn is_full(&mut self) -> bool {
+        if self.tail < self.limit + N {
+            return false;
+        }
+
+        self.reload_limit();
+        self.tail >= self.limit + N
+    }
+
+    /// Without subscribers the limit is the published tail, since a
+    /// subscriber that joins now starts reading there.
+    #[inline(always)]
+    fn reload_limit(&mut self) {
+        let spsc = unsafe { &*self.spsc.as_ptr() };
+        let published = spsc.tail.load(Ordering::SeqCst);
+        self.limit = spsc.cursors.min_position(published);
+    }
+
+    /// Writes `value` at the local tail. Caller must check capacity.
+    #[inline(always)]
+    fn write_unchecked(&mut self, value: T) {
+        let index = self.tail & Self::MODULO_MASK;
+        unsafe {
+            *(*self.spsc.as_ptr())
+                .channel
+                .buffer
+                .as_mut_ptr()
+                .add(index) = value;
+        };
+
+        self.tail += 1;
+    }
+
+    /// Synchronizes the local tail with the atomic tail in the channel,
+    /// publishing newly written values and waking parked subscribers.
+    #[inline(always)]
+    pub fn sync(&mut self) {
+        unsafe {
+            let spsc = &*self.spsc.as_ptr();
+            spsc.tail
+                .store(self.tail, Ordering::Release);
+            spsc.consumer_notify.notify();
+        }
+    }
+
+    /// Evicts every subscriber whose heartbeat has not changed since
+    /// the previous call, returning the number evicted. Subscribers
+    /// beat by calling [Consumer::beat] and whenever they publish a
+    /// new position.
+    ///
+    /// Call this periodically, less often than subscribers call
+    /// [Consumer::beat]. A subscriber is never evicted on the first
+    /// call after it joined.
+    pub fn evict_stale(&mut self) -> usize {
+        self.evict_stale_before(None)
+    }
+
+    /// Like [Producer::evict_stale], but if `before` is set only evicts
+    /// subscribers whose position is before it.
+    fn evict_stale_before(&mut self, before: Option<usize>) -> usize {
+        self.last_evict = Some(Instant::now());
+        let cursors = unsafe { &(*self.spsc.as_ptr()).cursors };
+
+        let mut evicted = 0;
+        let mut active = cursors.active();
+        while active != 0 {
+            let index = active.trailing_zeros() as usize;
+            active &= active - 1;
+
+            let cursor = cursors.get(index);
+            let seen = (
+                cursor
+                    .generation
+                    .load(Ordering::Acquire),
+                cursor.heartbeat.load(Ordering::Acquire),
+            );
+            if seen == self.last_seen[index]
+                && before.is_none_or(|before| {
+                    cursor
+                        .position(self.tail)
+                        .is_some_and(|position| position < before)
+                })
+                && cursors.release_generation(index, seen.0)
+            {
+                evicted += 1;
+            }
+            self.last_seen[index] = seen;
+        }
+
+        if evicted != 0 {
+            // Orders the generation bumps before the writes that reuse
+            // the slots. A subscriber whose copy in `pop` reads one of
+            // them sees its new generation after the acquire fence
+            // there, and discards the copy.
+            fence(Ordering::Release);
+            self.reload_limit();
+        }
+
+        evicted
+    }
+
+    /// Returns the number of registered subscribers.
+    pub fn subscribers(&self) -> u32 {
+        unsafe {
+            (*self.spsc.as_ptr())
+                .cursors
+                .active()
+                .count_ones()
+        }
+    }
+
+    /// Increments the producer heartbeat.
+    ///
+    /// Can be read by subscribers to see that the producer is still
+    /// online if done periodically.
+    pub fn beat(&self) {
+        unsafe {
+            (*self.spsc.as_ptr())
+                .channel
+                .producer_heartbeat
+                .fetch_add(1, Ordering::Release);
+        }
+    }
+
+    /// Returns pointer to inner padding.
+    ///
+    /// User is responsible for safe usage.
+    ///
+    /// Unsynchronized: prefer [metadata](Self::metadata) for values
+    /// that change while the channel is in use.
+    ///
+    /// Byte array is 128 byte aligned.
+    pub fn get_padding_ptr(&self) -> NonNull<[u8; 112]> {
+        unsafe {
+            NonNull::new_unchecked(
+                self.spsc.cast::<u8>().as_ptr().add(512),
+            )
+            .cast()
+        }
+    }
+
+    /// Returns a typed, versioned view of the metadata region (the
+    /// same bytes as [get_padding_ptr](Self::get_padding_ptr)).
+    ///
+    /// Fails to compile if `U` is larger than 112 bytes.
+    pub fn metadata<U: Pod>(&self) -> Metadata<'_, U> {
+        unsafe { Channel::<M, T, N>::metadata(self.spsc.cast()) }
+    }
+}
+
+impl<M: ChannelMode<T>, T, const N: usize> Drop for Producer<M, T, N> {
+    fn drop(&mut self) {
+        // LocalMode is backed by arc
+        if M::BACKED_BY_ARCC {
+            unsafe { drop(Arc::from_raw(self.spsc.as_ptr())) }
+        }
+    }
+}
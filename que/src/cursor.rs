@@ -0,0 +1,283 @@
+//! Table of reader cursors in the [MultiChannel](crate::MultiChannel)
+//! header, used by channel kinds with more than one reader that must
+//! not be overrun.
+
+use crate::atomic_compat::{AtomicU64, AtomicUsize, Ordering};
+
+/// Maximum number of cursors (readers) a single channel can hold.
+pub const MAX_CURSORS: usize = 64;
+
+#[repr(C, align(128))]
+pub(crate) struct CursorTable {
+    /// Bit `i` is set while `cursors[i]` is held by a reader.
+    active: AtomicU64,
+    cursors: [Cursor; MAX_CURSORS],
+}
+
+/// A reader's position, padded to its own cache line.
+#[repr(C, align(128))]
+pub(crate) struct Cursor {
+    /// Lowest position the owner may still read, packed with the low
+    /// bits of `generation` (see [Cursor::pack]), or [Cursor::IDLE].
+    position: AtomicU64,
+    /// Bumped whenever the cursor changes hands, so a reader that was
+    /// evicted can tell the cursor is no longer its own.
+    pub(crate) generation: AtomicU64,
+    /// Bumped by the owner to show it is still alive.
+    pub(crate) heartbeat: AtomicUsize,
+}
+
+impl Cursor {
+    /// Position word of a cursor that does not hold back the producer.
+    /// Never produced by [Cursor::pack], which leaves the top bit clear.
+    const IDLE: u64 = u64::MAX;
+
+    /// Low bits of the position kept in the position word.
+    const POSITION_BITS: u32 = 48;
+
+    /// Low bits of the generation kept in the position word.
+    const TAG_BITS: u32 = 15;
+
+    #[cfg(all(loom, test))]
+    pub(crate) fn new() -> Self {
+        Self {
+            position: AtomicU64::new(Self::IDLE),
+            generation: AtomicU64::new(0),
+            heartbeat: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl Cursor {
+    /// Packs `position` with the low bits of `generation`, so that an
+    /// owner updating its position with a CAS fails once the cursor
+    /// changed hands, even if the new owner is at the same position.
+    #[inline(always)]
+    fn pack(generation: u64, position: usize) -> u64 {
+        let tag = generation & ((1 << Self::TAG_BITS) - 1);
+        let position =
+            position as u64 & ((1 << Self::POSITION_BITS) - 1);
+        tag << Self::POSITION_BITS | position
+    }
+
+    /// Recovers a packed position from its low bits, given a position
+    /// `near` it. Readers are never more than the capacity away from
+    /// the positions the producer compares them with.
+    #[inline(always)]
+    fn unpack(word: u64, near: usize) -> usize {
+        let shift = 64 - Self::POSITION_BITS;
+        let diff =
+            ((word.wrapping_sub(near as u64) << shift) as i64) >> shift;
+        near.wrapping_add_signed(diff as isize)
+    }
+
+    /// Publishes `position` as the lowest position the owner, holding
+    /// the cursor at `generation`, may still read.
+    #[inline(always)]
+    pub(crate) fn announce(&self, generation: u64, position: usize) {
+        self.position
+            .store(Self::pack(generation, position), Ordering::SeqCst);
+    }
+
+    /// Moves the owner's position from `current` to `new`. Fails if
+    /// the cursor was released or changed hands since the owner
+    /// announced `current`.
+    #[inline(always)]
+    pub(crate) fn advance(
+        &self,
+        generation: u64,
+        current: usize,
+        new: usize,
+    ) -> bool {
+        self.position
+            .compare_exchange(
+                Self::pack(generation, current),
+                Self::pack(generation, new),
+                Ordering::SeqCst,
+                Ordering::Relaxed,
+            )
+            .is_ok()
+    }
+
+    /// Returns the owner's position, recovered relative to `near`, or
+    /// `None` if the cursor is idle.
+    #[inline(always)]
+    pub(crate) fn position(&self, near: usize) -> Option<usize> {
+        let word = self.position.load(Ordering::SeqCst);
+        (word != Self::IDLE).then(|| Self::unpack(word, near))
+    }
+
+    /// Stops holding back the producer.
+    #[inline(always)]
+    pub(crate) fn idle(&self) {
+        self.position
+            .store(Self::IDLE, Ordering::Release);
+    }
+}
+
+impl CursorTable {
+    /// Writes a table with no active cursors at `ptr`, field by field
+    /// since the table does not fit on a Loom thread's stack.
+    #[cfg(all(loom, test))]
+    pub(crate) unsafe fn loom_write_fresh_at(ptr: *mut Self) {
+        use core::ptr::{addr_of_mut, write};
+
+        write(addr_of_mut!((*ptr).active), AtomicU64::new(0));
+        for i in 0..MAX_CURSORS {
+            write(addr_of_mut!((*ptr).cursors[i]), Cursor::new());
+        }
+    }
+
+    /// Frees every cursor when a channel is initialized.
+    pub(crate) fn reset(&self) {
+        for cursor in &self.cursors {
+            cursor
+                .position
+                .store(Cursor::IDLE, Ordering::Relaxed);
+            cursor
+                .heartbeat
+                .store(0, Ordering::Relaxed);
+        }
+        self.active.store(0, Ordering::Release);
+    }
+
+    #[inline(always)]
+    pub(crate) fn get(&self, index: usize) -> &Cursor {
+        &self.cursors[index]
+    }
+
+    /// Claims a free cursor, returning its index and generation.
+    pub(crate) fn acquire(&self) -> Option<(usize, u64)> {
+        let mut active = self.active.load(Ordering::Acquire);
+        loop {
+            let index = active.trailing_ones() as usize;
+            if index == MAX_CURSORS {
+                return None;
+            }
+
+            match self.active.compare_exchange_weak(
+                active,
+                active | (1 << index),
+                Ordering::SeqCst,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => {
+                    let generation = self.cursors[index]
+                        .generation
+                        .fetch_add(1, Ordering::AcqRel)
+                        + 1;
+                    return Some((index, generation));
+                }
+                Err(current) => active = current,
+            }
+        }
+    }
+
+    /// Frees a cursor claimed by [CursorTable::acquire].
+    pub(crate) fn release(&self, index: usize) {
+        self.cursors[index].idle();
+        self.active
+            .fetch_and(!(1 << index), Ordering::Release);
+    }
+
+    /// Frees a cursor if it is still at `generation`. Used both by the
+    /// owner and to evict the owner. Returns `false` if the cursor had
+    /// already changed hands.
+    pub(crate) fn release_generation(
+        &self,
+        index: usize,
+        generation: u64,
+    ) -> bool {
+        let cursor = &self.cursors[index];
+        if cursor
+            .generation
+            .compare_exchange(
+                generation,
+                generation + 1,
+                Ordering::SeqCst,
+                Ordering::Relaxed,
+            )
+            .is_err()
+        {
+            return false;
+        }
+
+        self.release(index);
+        true
+    }
+
+    /// Returns a mask of the cursors currently held by readers.
+    #[inline(always)]
+    pub(crate) fn active(&self) -> u64 {
+        self.active.load(Ordering::SeqCst)
+    }
+
+    /// Returns the smallest position of all active cursors, or `limit`
+    /// if smaller. Idle cursors never count.
+    #[inline(always)]
+    pub(crate) fn min_position(&self, limit: usize) -> usize {
+        let mut active = self.active();
+        let mut min = limit;
+        while active != 0 {
+            let index = active.trailing_zeros() as usize;
+            active &= active - 1;
+            let word = self.cursors[index]
+                .position
+                .load(Ordering::SeqCst);
+            if word != Cursor::IDLE {
+                min = min.min(Cursor::unpack(word, limit));
+            }
+        }
+        min
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+
+    fn table() -> Box<CursorTable> {
+        // All-zero atomics are valid
+        let table: Box<CursorTable> =
+            unsafe { Box::new_zeroed().assume_init() };
+        table.reset();
+        table
+    }
+
+    #[test]
+    fn test_advance_fails_after_cursor_changed_hands() {
+        let table = table();
+
+        let (index, evicted) = table.acquire().unwrap();
+        table.get(index).announce(evicted, 5);
+        assert!(table.release_generation(index, evicted));
+
+        // The new owner happens to start where the evicted one was
+        let (reused, owner) = table.acquire().unwrap();
+        assert_eq!(reused, index);
+        table.get(index).announce(owner, 5);
+
+        assert!(!table.get(index).advance(evicted, 5, 9));
+        assert_eq!(table.min_position(100), 5);
+        assert!(table.get(index).advance(owner, 5, 9));
+        assert_eq!(table.min_position(100), 9);
+    }
+
+    #[test]
+    fn test_min_position_unpacks_near_limit() {
+        let table = table();
+        let (index, generation) = table.acquire().unwrap();
+
+        // Positions past the packed bits, on either side of the limit
+        let limit = (1 << 50) + 3;
+        for position in [limit - 64, limit, limit + 64] {
+            table
+                .get(index)
+                .announce(generation, position);
+            assert_eq!(table.min_position(limit), position.min(limit));
+        }
+
+        table.release(index);
+        assert_eq!(table.min_position(limit), limit);
+    }
+}
@@ -0,0 +1,353 @@
+use std::{
+    ptr::NonNull,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use bytemuck::{AnyBitPattern, Pod};
+
+use crate::{
+    atomic_compat::Ordering,
+    error::QueError,
+    shmem::{Shmem, ShmemConfig},
+    Channel, ChannelMode, Liveness, Metadata, ShmemMode, MAGIC,
+};
+
+use super::{burst_amount, Block, DynChannel};
+
+#[repr(C)]
+pub struct DynConsumer<M: ChannelMode<T>, T> {
+    spsc: NonNull<Channel<M, T, 0>>,
+    buffer: NonNull<T>,
+    capacity: usize,
+    mask: usize,
+    burst: usize,
+    head: usize,
+    items_since_last_sync: usize,
+    last_producer_heartbeat: usize,
+    _local: Option<Arc<[Block]>>,
+    /// Mapping backing the channel, if joined through shmem.
+    shmem: Option<Shmem>,
+}
+
+unsafe impl<M: ChannelMode<T>, T> Send for DynConsumer<M, T> {}
+
+impl<T: AnyBitPattern> DynConsumer<ShmemMode, T> {
+    /// Joins an existing channel backed by shared memory as a consumer,
+    /// taking the capacity from its header.
+    ///
+    /// # Safety
+    /// The shared memory region must only ever be mapped as a
+    /// `Channel` with the same `T`.
+    pub unsafe fn join_shmem(
+        shmem_id: &str,
+        #[cfg(target_os = "linux")] config: impl Into<ShmemConfig>,
+    ) -> Result<DynConsumer<ShmemMode, T>, QueError> {
+        Self::join_shmem_(
+            shmem_id,
+            false,
+            #[cfg(target_os = "linux")]
+            config,
+        )
+    }
+
+    /// Like [DynConsumer::join_shmem], but first reclaims the channel
+    /// if the process recorded as its consumer has died. Fails with
+    /// [QueError::OwnerAlive] if it is still running.
+    ///
+    /// # Safety
+    /// The shared memory region must only ever be mapped as a
+    /// `Channel` with the same `T`.
+    pub unsafe fn take_over_shmem(
+        shmem_id: &str,
+        #[cfg(target_os = "linux")] config: impl Into<ShmemConfig>,
+    ) -> Result<DynConsumer<ShmemMode, T>, QueError> {
+        Self::join_shmem_(
+            shmem_id,
+            true,
+            #[cfg(target_os = "linux")]
+            config,
+        )
+    }
+
+    unsafe fn join_shmem_(
+        shmem_id: &str,
+        take_over: bool,
+        #[cfg(target_os = "linux")] config: impl Into<ShmemConfig>,
+    ) -> Result<DynConsumer<ShmemMode, T>, QueError> {
+        #[cfg(not(target_os = "linux"))]
+        let config = ShmemConfig::default();
+        #[cfg(target_os = "linux")]
+        let config: ShmemConfig = config.into();
+
+        let shmem = config.open_existing_whole(shmem_id)?;
+        let mapped = Some(shmem.size as usize);
+
+        if take_over {
+            // Nothing in the header is touched unless it checks out
+            Self::check_header(shmem.get_mut_ptr(), mapped)?;
+            let spsc: *mut Channel<ShmemMode, T, 0> =
+                shmem.get_mut_ptr().cast();
+            (*spsc).owners.consumer.evict_dead()?;
+        }
+
+        let mut consumer =
+            Self::join_(shmem.get_mut_ptr(), None, mapped)?;
+        consumer.shmem = Some(shmem);
+
+        Ok(consumer)
+    }
+
+    /// Joins an existing channel backed by `buffer` as a consumer,
+    /// taking the capacity from its header.
+    ///
+    /// # Safety
+    /// This must point to a buffer of proper alignment and at least
+    /// [DynChannel::size] bytes for the capacity in its header.
+    pub unsafe fn join(
+        buffer: *mut u8,
+    ) -> Result<DynConsumer<ShmemMode, T>, QueError> {
+        Self::join_(buffer, None, None)
+    }
+
+    /// Unlinks the shared memory object when this endpoint is dropped,
+    /// if it created the object. Has no effect for endpoints joined
+    /// to an existing object or to a caller-provided buffer.
+    pub fn set_unlink_on_drop(&mut self, unlink: bool) {
+        if let Some(shmem) = self.shmem.as_mut() {
+            shmem.set_unlink_on_drop(unlink);
+        }
+    }
+}
+
+impl<M: ChannelMode<T>, T> DynConsumer<M, T> {
+    /// Checks the header at `buffer` without writing to it and returns
+    /// the capacity it records. If `mapped` bytes are known to be
+    /// mapped at `buffer`, also checks that they fit that capacity,
+    /// since a corrupt capacity would otherwise index past the mapping.
+    unsafe fn check_header(
+        buffer: *mut u8,
+        mapped: Option<usize>,
+    ) -> Result<usize, QueError> {
+        assert!((buffer as usize).is_multiple_of(128), "unaligned");
+
+        // The header must fit before we can read the capacity from it
+        if mapped.is_some_and(|mapped| {
+            mapped < DynChannel::<M, T>::BUFFER_OFFSET
+        }) {
+            return Err(QueError::InvalidSize);
+        }
+
+        // Zerocopy deserialize the header
+        let spsc: *mut Channel<M, T, 0> = buffer.cast();
+
+        // Check magic
+        let magic = (*spsc).magic.load(Ordering::Acquire);
+        if magic == MAGIC {
+            let capacity = (*spsc).capacity.load(Ordering::Acquire);
+            if capacity == 0 || !capacity.is_power_of_two() {
+                return Err(QueError::CorruptionDetected);
+            }
+
+            if let Some(mapped) = mapped {
+                let size = DynChannel::<M, T>::size(capacity)
+                    .ok_or(QueError::InvalidSize)?;
+                if mapped < size {
+                    return Err(QueError::InvalidSize);
+                }
+            }
+
+            (*spsc).check_fingerprint(0)?;
+            Ok(capacity)
+        } else if magic == 0 {
+            // Technically could be corrupted but uninitialized
+            // is most likely explanation
+            Err(QueError::Uninitialized)
+        } else {
+            // Magic is not MAGIC and not zero
+            Err(QueError::CorruptionDetected)
+        }
+    }
+
+    /// Joins the channel at `buffer`, see [DynConsumer::check_header]
+    /// for `mapped`. The header is only written once it checks out.
+    pub(crate) unsafe fn join_(
+        buffer: *mut u8,
+        local: Option<Arc<[Block]>>,
+        mapped: Option<usize>,
+    ) -> Result<DynConsumer<M, T>, QueError> {
+        let capacity = Self::check_header(buffer, mapped)?;
+
+        let spsc: *mut Channel<M, T, 0> = buffer.cast();
+        (*spsc).owners.consumer.claim()?;
+
+        // Assume channel is empty upon joining
+        let new_head = (*spsc).tail.load(Ordering::Acquire);
+        (*spsc)
+            .head
+            .store(new_head, Ordering::Release);
+
+        Ok(DynConsumer {
+            spsc: NonNull::new_unchecked(spsc),
+            shmem: None,
+            buffer: NonNull::new_unchecked(
+                buffer
+                    .add(DynChannel::<M, T>::BUFFER_OFFSET)
+                    .cast(),
+            ),
+            capacity,
+            mask: capacity - 1,
+            burst: burst_amount(capacity),
+            head: new_head,
+            items_since_last_sync: 0,
+            last_producer_heartbeat: (*spsc)
+                .producer_heartbeat
+                .load(Ordering::Acquire),
+            _local: local,
+        })
+    }
+
+    /// Returns the capacity read from the header on join.
+    #[inline(always)]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Attempts to read the next element. Returns `None` if the
+    /// consumer is caught up.
+    pub fn pop(&mut self) -> Option<T> {
+        let tail = unsafe {
+            (*self.spsc.as_ptr())
+                .tail
+                .load(Ordering::Acquire)
+        };
+        // Nothing else to read
+        if tail <= self.head {
+            return None;
+        }
+        let value = unsafe {
+            self.buffer
+                .as_ptr()
+                .add(self.head & self.mask)
+                .read()
+        };
+
+        self.head += 1;
+        self.items_since_last_sync += 1;
+        if self.items_since_last_sync >= self.burst {
+            self.publish_head();
+        }
+        Some(value)
+    }
+
+    /// Reads the next element, waiting for the producer if the consumer
+    /// is caught up.
+    pub fn pop_blocking(&mut self) -> T {
+        loop {
+            // Without a deadline we can only return with a value
+            if let Some(value) = self.pop_until(None) {
+                return value;
+            }
+        }
+    }
+
+    /// Reads the next element, waiting up to `timeout` for the producer.
+    /// Returns `None` if nothing was published in time.
+    pub fn pop_timeout(&mut self, timeout: Duration) -> Option<T> {
+        self.pop_until(Instant::now().checked_add(timeout))
+    }
+
+    fn pop_until(&mut self, deadline: Option<Instant>) -> Option<T> {
+        if let Some(value) = self.pop() {
+            return Some(value);
+        }
+
+        // Release everything we've read so far; the producer may be
+        // parked waiting on exactly this space.
+        self.publish_head();
+
+        let spsc = unsafe { &*self.spsc.as_ptr() };
+        spsc.consumer_notify
+            .wait_until(deadline, || self.pop())
+    }
+
+    /// Increments the consumer heartbeat.
+    pub fn beat(&self) {
+        unsafe {
+            (*self.spsc.as_ptr())
+                .consumer_heartbeat
+                .fetch_add(1, Ordering::Release);
+        }
+    }
+
+    /// Checks if the producer has incremented its heartbeat since last
+    /// called.
+    pub fn producer_heartbeat(&mut self) -> bool {
+        let heartbeat = unsafe {
+            (*self.spsc.as_ptr())
+                .producer_heartbeat
+                .load(Ordering::Acquire)
+        };
+
+        if heartbeat != self.last_producer_heartbeat {
+            self.last_producer_heartbeat = heartbeat;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Checks whether the processes holding either end of the channel
+    /// are still running, see [Channel::inspect_liveness].
+    pub fn inspect_liveness(&self) -> Liveness {
+        unsafe { (*self.spsc.as_ptr()).inspect_liveness() }
+    }
+
+    /// Returns pointer to inner padding.
+    ///
+    /// User is responsible for safe usage.
+    ///
+    /// Unsynchronized: prefer [metadata](Self::metadata) for values
+    /// that change while the channel is in use.
+    ///
+    /// Byte array is 128 byte aligned.
+    pub fn get_padding_ptr(&self) -> NonNull<[u8; 112]> {
+        unsafe {
+            NonNull::new_unchecked(
+                self.spsc.cast::<u8>().as_ptr().add(512),
+            )
+            .cast()
+        }
+    }
+
+    /// Returns a typed, versioned view of the metadata region (the
+    /// same bytes as [get_padding_ptr](Self::get_padding_ptr)).
+    ///
+    /// Fails to compile if `U` is larger than 112 bytes.
+    pub fn metadata<U: Pod>(&self) -> Metadata<'_, U> {
+        unsafe { Channel::metadata(self.spsc) }
+    }
+
+    /// Publishes the local head, waking a parked producer.
+    #[inline(always)]
+    fn publish_head(&mut self) {
+        self.items_since_last_sync = 0;
+        unsafe {
+            let spsc = &*self.spsc.as_ptr();
+            spsc.head
+                .store(self.head, Ordering::Release);
+            spsc.producer_notify.notify();
+        }
+    }
+}
+
+impl<M: ChannelMode<T>, T> Drop for DynConsumer<M, T> {
+    fn drop(&mut self) {
+        unsafe {
+            (*self.spsc.as_ptr())
+                .owners
+                .consumer
+                .release()
+        }
+    }
+}
@@ -0,0 +1,296 @@
+//! Lossless SPSC channel whose capacity is chosen at runtime.
+//!
+//! Uses the same header and protocol as [crate::lossless], but the
+//! endpoints read `capacity` from the header when they join and index
+//! the buffer with a runtime mask. A [consumer::DynConsumer] therefore
+//! never needs to be rebuilt to match the producer, and it can read a
+//! channel created by a const-generic [crate::lossless] producer (and
+//! vice versa) as long as the capacities agree.
+
+pub mod consumer;
+pub mod producer;
+
+use std::{cell::UnsafeCell, marker::PhantomData, sync::Arc};
+
+use consumer::DynConsumer;
+use producer::DynProducer;
+
+use crate::{Channel, LocalMode};
+
+/// Size and layout of a runtime-sized channel.
+pub struct DynChannel<M, T>(PhantomData<(M, T)>);
+
+impl<M, T> DynChannel<M, T> {
+    /// Offset of the first element from the start of the header.
+    pub const BUFFER_OFFSET: usize =
+        core::mem::offset_of!(Channel<M, T, 0>, buffer);
+
+    /// Returns the number of bytes needed for a channel of `capacity`
+    /// elements, rounded up to the header alignment, or `None` if that
+    /// exceeds `isize::MAX`.
+    pub const fn size(capacity: usize) -> Option<usize> {
+        let Some(buffer) =
+            capacity.checked_mul(core::mem::size_of::<T>())
+        else {
+            return None;
+        };
+        let Some(size) = Self::BUFFER_OFFSET.checked_add(buffer) else {
+            return None;
+        };
+        match size.checked_next_multiple_of(128) {
+            Some(size) if size <= isize::MAX as usize => Some(size),
+            _ => None,
+        }
+    }
+}
+
+/// Backing storage for [LocalMode] endpoints, shared by refcount.
+#[repr(C, align(128))]
+pub(crate) struct Block(UnsafeCell<[u8; 128]>);
+
+pub fn dyn_pair<T: Send>(
+    capacity: usize,
+) -> (DynProducer<LocalMode, T>, DynConsumer<LocalMode, T>) {
+    assert!(
+        capacity > 0 && capacity.is_power_of_two(),
+        "Capacity must be a power of two"
+    );
+
+    let blocks = DynChannel::<LocalMode, T>::size(capacity)
+        .expect("Capacity overflows the address space")
+        / 128;
+    let local: Arc<[Block]> = (0..blocks)
+        .map(|_| Block(UnsafeCell::new([0; 128])))
+        .collect();
+    let buffer = local.as_ptr().cast_mut().cast::<u8>();
+
+    let producer = unsafe {
+        DynProducer::join_or_initialize_in_(
+            buffer,
+            capacity,
+            Some(local.clone()),
+        )
+        .unwrap()
+    };
+    let consumer = unsafe {
+        DynConsumer::join_(buffer, Some(local), None).unwrap()
+    };
+
+    (producer, consumer)
+}
+
+const fn burst_amount(capacity: usize) -> usize {
+    // Producer can write up to 1/4 of the buffer at a time
+    const BURST_DENOM: usize = 4;
+
+    let x = capacity / BURST_DENOM;
+
+    if x == 0 {
+        1
+    } else {
+        x
+    }
+}
+
+// Not run with `feature = "loom"`, see `lossless::tests`.
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use crate::{
+        error::QueError,
+        lossless::{consumer::Consumer, producer::Producer},
+        page_size::PageSize,
+        shmem::ShmemConfig,
+        ShmemMode,
+    };
+
+    #[repr(C, align(128))]
+    struct Aligned<const B: usize>([u8; B]);
+
+    #[test]
+    fn test_push_pop_multiple() {
+        let (mut producer, mut consumer) = dyn_pair::<u64>(16);
+        assert_eq!(producer.capacity(), 16);
+        assert_eq!(consumer.capacity(), 16);
+
+        producer.push(69).unwrap();
+        producer.push(70).unwrap();
+        assert_eq!(consumer.pop(), None);
+
+        producer.sync();
+        assert_eq!(consumer.pop(), Some(69));
+        assert_eq!(consumer.pop(), Some(70));
+        assert_eq!(consumer.pop(), None);
+    }
+
+    #[test]
+    fn test_push_pop_overrun() {
+        let (mut producer, mut consumer) = dyn_pair::<u64>(4);
+
+        for i in 0..4 {
+            producer.push(i).unwrap();
+        }
+        assert!(matches!(producer.push(4), Err(QueError::Full)));
+
+        producer.sync();
+        for i in 0..4 {
+            assert_eq!(consumer.pop(), Some(i));
+        }
+
+        // Wraps around the runtime mask
+        producer.push(4).unwrap();
+        producer.sync();
+        assert_eq!(consumer.pop(), Some(4));
+    }
+
+    #[test]
+    fn test_blocking_across_threads() {
+        let (mut producer, mut consumer) = dyn_pair::<u64>(8);
+
+        let reader = std::thread::spawn(move || {
+            (0..1000)
+                .map(|_| consumer.pop_blocking())
+                .sum::<u64>()
+        });
+        for i in 0..1000 {
+            producer.push_blocking(i);
+        }
+        producer.sync();
+
+        assert_eq!(reader.join().unwrap(), (0..1000).sum());
+    }
+
+    #[test]
+    fn test_interop_with_const_capacity() {
+        const SIZE: usize =
+            DynChannel::<ShmemMode, u64>::size(32).unwrap();
+        let mut buffer = Box::new(Aligned([0u8; SIZE]));
+        let ptr = buffer.0.as_mut_ptr();
+
+        // Const-generic producer, runtime-sized consumer
+        let mut producer = unsafe {
+            Producer::<ShmemMode, u64, 32>::join_or_initialize_in(ptr)
+                .unwrap()
+        };
+        let mut consumer = unsafe {
+            DynConsumer::<ShmemMode, u64>::join(ptr).unwrap()
+        };
+        assert_eq!(consumer.capacity(), 32);
+
+        producer.push(69).unwrap();
+        producer.sync();
+        assert_eq!(consumer.pop(), Some(69));
+        drop(consumer);
+
+        // Runtime-sized producer, const-generic consumer
+        let mut producer = unsafe {
+            DynProducer::<ShmemMode, u64>::join(ptr).unwrap()
+        };
+        let mut consumer = unsafe {
+            Consumer::<ShmemMode, u64, 32>::join(ptr).unwrap()
+        };
+        producer.push(70).unwrap();
+        producer.sync();
+        assert_eq!(consumer.pop(), Some(70));
+
+        // Capacity is checked against the header
+        assert!(matches!(
+            unsafe {
+                DynProducer::<ShmemMode, u64>::join_or_initialize_in(
+                    ptr, 64,
+                )
+            },
+            Err(QueError::IncorrectCapacity(32))
+        ));
+    }
+
+    #[test]
+    fn test_overflowing_capacity() {
+        assert!(DynChannel::<ShmemMode, u64>::size(1 << 61).is_none());
+        assert!(DynChannel::<ShmemMode, u64>::size(1 << 60).is_none());
+        assert!(DynChannel::<ShmemMode, u64>::size(1 << 59).is_some());
+
+        let id = format!("que_dyn_overflow_{}", std::process::id());
+        assert!(matches!(
+            unsafe {
+                DynProducer::<ShmemMode, u64>::join_or_create_shmem(
+                    &id,
+                    1 << 61,
+                    #[cfg(target_os = "linux")]
+                    PageSize::Standard,
+                )
+            },
+            Err(QueError::InvalidSize)
+        ));
+
+        let mut producer = unsafe {
+            DynProducer::<ShmemMode, u64>::join_or_create_shmem(
+                &id,
+                4,
+                #[cfg(target_os = "linux")]
+                PageSize::Standard,
+            )
+            .unwrap()
+        };
+        producer.set_unlink_on_drop(true);
+        producer.push(69).unwrap();
+        producer.sync();
+
+        // A corrupt capacity in the header must not wrap the size check
+        let shmem = ShmemConfig::default()
+            .open_existing_whole(&id)
+            .unwrap();
+        let spsc: *mut Channel<ShmemMode, u64, 0> =
+            shmem.get_mut_ptr().cast();
+        unsafe {
+            (*spsc)
+                .capacity
+                .store(1 << 61, crate::atomic_compat::Ordering::Release)
+        };
+        assert!(matches!(
+            unsafe {
+                DynConsumer::<ShmemMode, u64>::join_shmem(
+                    &id,
+                    #[cfg(target_os = "linux")]
+                    PageSize::Standard,
+                )
+            },
+            Err(QueError::InvalidSize)
+        ));
+
+        // The failed join left the header alone: the head did not move
+        // and the consumer end was not claimed
+        unsafe {
+            assert_eq!(
+                (*spsc)
+                    .head
+                    .load(crate::atomic_compat::Ordering::Acquire),
+                0
+            );
+            (*spsc)
+                .capacity
+                .store(4, crate::atomic_compat::Ordering::Release);
+        }
+        assert!(unsafe {
+            DynConsumer::<ShmemMode, u64>::join_shmem(
+                &id,
+                #[cfg(target_os = "linux")]
+                PageSize::Standard,
+            )
+        }
+        .is_ok());
+    }
+
+    #[test]
+    fn test_join_uninitialized() {
+        let mut buffer = Box::new(Aligned(
+            [0u8; DynChannel::<ShmemMode, u64>::size(4).unwrap()],
+        ));
+        let ptr = buffer.0.as_mut_ptr();
+
+        assert!(matches!(
+            unsafe { DynConsumer::<ShmemMode, u64>::join(ptr) },
+            Err(QueError::Uninitialized)
+        ));
+    }
+}
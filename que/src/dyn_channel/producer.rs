@@ -0,0 +1,424 @@
+use std::{
+    ptr::NonNull,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use bytemuck::{AnyBitPattern, Pod};
+
+use crate::{
+    atomic_compat::Ordering,
+    error::QueError,
+    shmem::{Shmem, ShmemConfig, ShmemError},
+    Channel, ChannelMode, Liveness, Metadata, ShmemMode, MAGIC,
+};
+
+use super::{Block, DynChannel};
+
+#[repr(C, align(128))]
+pub struct DynProducer<M: ChannelMode<T>, T> {
+    spsc: NonNull<Channel<M, T, 0>>,
+    buffer: NonNull<T>,
+    capacity: usize,
+    mask: usize,
+    tail: usize,
+    last_consumer_heartbeat: usize,
+    _local: Option<Arc<[Block]>>,
+    /// Mapping backing the channel, if joined through shmem.
+    shmem: Option<Shmem>,
+}
+
+unsafe impl<M: ChannelMode<T>, T> Send for DynProducer<M, T> {}
+
+impl<T: AnyBitPattern> DynProducer<ShmemMode, T> {
+    /// Joins or creates a channel of `capacity` elements backed by
+    /// shared memory as a producer.
+    ///
+    /// An existing channel is joined whatever its mapping size, but its
+    /// capacity must equal `capacity`.
+    ///
+    /// # Safety
+    /// The shared memory region must only ever be mapped as a
+    /// `Channel` with the same `T`.
+    pub unsafe fn join_or_create_shmem(
+        shmem_id: &str,
+        capacity: usize,
+        #[cfg(target_os = "linux")] config: impl Into<ShmemConfig>,
+    ) -> Result<DynProducer<ShmemMode, T>, QueError> {
+        let shmem = Self::open_shmem(
+            shmem_id,
+            capacity,
+            #[cfg(target_os = "linux")]
+            config,
+        )?;
+
+        let mut producer = Self::join_or_initialize_in_(
+            shmem.get_mut_ptr(),
+            capacity,
+            None,
+        )?;
+        producer.shmem = Some(shmem);
+
+        Ok(producer)
+    }
+
+    /// Like [DynProducer::join_or_create_shmem], but first reclaims the
+    /// channel if the process recorded as its producer has died. Fails
+    /// with [QueError::OwnerAlive] if it is still running.
+    ///
+    /// # Safety
+    /// The shared memory region must only ever be mapped as a
+    /// `Channel` with the same `T`.
+    pub unsafe fn take_over_shmem(
+        shmem_id: &str,
+        capacity: usize,
+        #[cfg(target_os = "linux")] config: impl Into<ShmemConfig>,
+    ) -> Result<DynProducer<ShmemMode, T>, QueError> {
+        let shmem = Self::open_shmem(
+            shmem_id,
+            capacity,
+            #[cfg(target_os = "linux")]
+            config,
+        )?;
+
+        let spsc: *mut Channel<ShmemMode, T, 0> =
+            shmem.get_mut_ptr().cast();
+        (*spsc).owners.producer.evict_dead()?;
+
+        let mut producer = Self::join_or_initialize_in_(
+            shmem.get_mut_ptr(),
+            capacity,
+            None,
+        )?;
+        producer.shmem = Some(shmem);
+
+        Ok(producer)
+    }
+
+    /// Opens the channel's shmem, creating it with room for `capacity`
+    /// elements if it does not exist.
+    fn open_shmem(
+        shmem_id: &str,
+        capacity: usize,
+        #[cfg(target_os = "linux")] config: impl Into<ShmemConfig>,
+    ) -> Result<Shmem, QueError> {
+        #[cfg(not(target_os = "linux"))]
+        let config = ShmemConfig::default();
+        #[cfg(target_os = "linux")]
+        let config: ShmemConfig = config.into();
+
+        let size = DynChannel::<ShmemMode, T>::size(capacity)
+            .ok_or(QueError::InvalidSize)?;
+        let shmem = loop {
+            match config.open_existing_whole(shmem_id) {
+                Err(ShmemError::NotFound) => {}
+                res => break res?,
+            }
+
+            // Calculate buffer size.
+            // If using huge pages, we must uplign to page size.
+            let buffer_size: i64 = config
+                .page_size()
+                .mem_size(size)
+                .try_into()
+                .map_err(|_| QueError::InvalidSize)?;
+
+            match config.create_exclusive(shmem_id, buffer_size) {
+                // Created by someone else since we tried to open it
+                Err(ShmemError::AlreadyExists) => {}
+                res => break res?,
+            }
+        };
+
+        if (shmem.size as usize) < size {
+            return Err(QueError::InvalidSize);
+        }
+
+        Ok(shmem)
+    }
+
+    /// Initializes a channel of `capacity` elements backed by `buffer`
+    /// and joins as a producer.
+    ///
+    /// # Safety
+    /// This must point to a buffer of proper alignment and at least
+    /// [DynChannel::size] bytes.
+    pub unsafe fn join_or_initialize_in(
+        buffer: *mut u8,
+        capacity: usize,
+    ) -> Result<DynProducer<ShmemMode, T>, QueError> {
+        Self::join_or_initialize_in_(buffer, capacity, None)
+    }
+
+    /// Joins an existing channel backed by `buffer` as a producer,
+    /// taking the capacity from its header.
+    ///
+    /// # Safety
+    /// This must point to a buffer of proper alignment and at least
+    /// [DynChannel::size] bytes for the capacity in its header.
+    pub unsafe fn join(
+        buffer: *mut u8,
+    ) -> Result<DynProducer<ShmemMode, T>, QueError> {
+        let spsc: *mut Channel<ShmemMode, T, 0> = buffer.cast();
+        let magic = (*spsc).magic.load(Ordering::Acquire);
+        if magic == MAGIC {
+            let capacity = (*spsc).capacity.load(Ordering::Acquire);
+            if capacity == 0 || !capacity.is_power_of_two() {
+                return Err(QueError::CorruptionDetected);
+            }
+            Self::join_or_initialize_in_(buffer, capacity, None)
+        } else if magic == 0 {
+            Err(QueError::Uninitialized)
+        } else {
+            Err(QueError::CorruptionDetected)
+        }
+    }
+
+    /// Unlinks the shared memory object when this endpoint is dropped,
+    /// if it created the object. Has no effect for endpoints joined
+    /// to an existing object or to a caller-provided buffer.
+    pub fn set_unlink_on_drop(&mut self, unlink: bool) {
+        if let Some(shmem) = self.shmem.as_mut() {
+            shmem.set_unlink_on_drop(unlink);
+        }
+    }
+}
+
+impl<M: ChannelMode<T>, T> DynProducer<M, T> {
+    pub(crate) unsafe fn join_or_initialize_in_(
+        buffer: *mut u8,
+        capacity: usize,
+        local: Option<Arc<[Block]>>,
+    ) -> Result<DynProducer<M, T>, QueError> {
+        assert!(
+            capacity > 0 && capacity.is_power_of_two(),
+            "Capacity must be a power of two"
+        );
+        assert!((buffer as usize).is_multiple_of(128), "unaligned");
+
+        // Zerocopy deserialize the header
+        let spsc: *mut Channel<M, T, 0> = buffer.cast();
+
+        // Check magic
+        let magic = (*spsc).magic.load(Ordering::Acquire);
+        let tail = if magic == MAGIC {
+            // Check capacity
+            let found = (*spsc).capacity.load(Ordering::Acquire);
+            if found != capacity {
+                return Err(QueError::IncorrectCapacity(found));
+            }
+
+            (*spsc).check_fingerprint(0)?;
+            (*spsc).owners.producer.claim()?;
+
+            (*spsc)
+                .producer_heartbeat
+                .fetch_add(1, Ordering::Release);
+
+            (*spsc).tail.load(Ordering::Acquire)
+        } else if magic == 0 {
+            (*spsc).owners.producer.claim()?;
+            (*spsc).tail.store(0, Ordering::Release);
+            (*spsc)
+                .consumer_heartbeat
+                .store(0, Ordering::Release);
+            (*spsc)
+                .producer_heartbeat
+                .store(0, Ordering::Release);
+            (*spsc)
+                .capacity
+                .store(capacity, Ordering::Release);
+            (*spsc).store_fingerprint(0);
+            (*spsc)
+                .magic
+                .store(MAGIC, Ordering::Release);
+
+            0
+        } else {
+            // Magic is not MAGIC and not zero
+            return Err(QueError::CorruptionDetected);
+        };
+
+        Ok(DynProducer {
+            spsc: NonNull::new_unchecked(spsc),
+            shmem: None,
+            buffer: NonNull::new_unchecked(
+                buffer
+                    .add(DynChannel::<M, T>::BUFFER_OFFSET)
+                    .cast(),
+            ),
+            capacity,
+            mask: capacity - 1,
+            tail,
+            last_consumer_heartbeat: (*spsc)
+                .consumer_heartbeat
+                .load(Ordering::Acquire),
+            _local: local,
+        })
+    }
+
+    /// Returns the capacity read from (or written to) the header.
+    #[inline(always)]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Attempts to write a new element to the channel. If full, returns
+    /// [QueError::Full].
+    #[inline(always)]
+    pub fn push(&mut self, value: T) -> Result<(), QueError> {
+        if self.is_full() {
+            return Err(QueError::Full);
+        }
+
+        self.write_unchecked(value);
+
+        Ok(())
+    }
+
+    /// Writes a new element to the channel, waiting for the consumer to
+    /// make room if the channel is full. Like [DynProducer::push], the
+    /// value is not published until [DynProducer::sync].
+    pub fn push_blocking(&mut self, value: T) {
+        // Without a deadline we can only return once there is room
+        let _ = self.push_until(value, None);
+    }
+
+    /// Writes a new element to the channel, waiting up to `timeout` for
+    /// the consumer to make room. If the channel is still full after
+    /// `timeout`, returns [QueError::Full].
+    pub fn push_timeout(
+        &mut self,
+        value: T,
+        timeout: Duration,
+    ) -> Result<(), QueError> {
+        self.push_until(value, Instant::now().checked_add(timeout))
+    }
+
+    fn push_until(
+        &mut self,
+        value: T,
+        deadline: Option<Instant>,
+    ) -> Result<(), QueError> {
+        if self.is_full() {
+            // Publish what we have so far; the consumer may be parked
+            // waiting on exactly these values.
+            self.sync();
+
+            let spsc = unsafe { &*self.spsc.as_ptr() };
+            spsc.producer_notify
+                .wait_until(deadline, || {
+                    (!self.is_full()).then_some(())
+                })
+                .ok_or(QueError::Full)?;
+        }
+
+        self.write_unchecked(value);
+
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn is_full(&self) -> bool {
+        self.tail
+            == unsafe {
+                (*self.spsc.as_ptr())
+                    .head
+                    .load(Ordering::Acquire)
+            } + self.capacity
+    }
+
+    /// Writes `value` at the local tail. Caller must check capacity.
+    #[inline(always)]
+    fn write_unchecked(&mut self, value: T) {
+        let index = self.tail & self.mask;
+        unsafe {
+            self.buffer
+                .as_ptr()
+                .add(index)
+                .write(value);
+        }
+
+        self.tail += 1;
+    }
+
+    /// Synchronizes the local tail with the atomic tail in the channel,
+    /// publishing newly written values and waking a parked consumer.
+    #[inline(always)]
+    pub fn sync(&mut self) {
+        unsafe {
+            let spsc = &*self.spsc.as_ptr();
+            spsc.tail
+                .store(self.tail, Ordering::Release);
+            spsc.consumer_notify.notify();
+        }
+    }
+
+    /// Increments the producer heartbeat.
+    pub fn beat(&self) {
+        unsafe {
+            (*self.spsc.as_ptr())
+                .producer_heartbeat
+                .fetch_add(1, Ordering::Release);
+        }
+    }
+
+    /// Checks if a consumer has incremented its heartbeat since last
+    /// called.
+    pub fn consumer_heartbeat(&mut self) -> bool {
+        let heartbeat = unsafe {
+            (*self.spsc.as_ptr())
+                .consumer_heartbeat
+                .load(Ordering::Acquire)
+        };
+
+        if heartbeat != self.last_consumer_heartbeat {
+            self.last_consumer_heartbeat = heartbeat;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Checks whether the processes holding either end of the channel
+    /// are still running, see [Channel::inspect_liveness].
+    pub fn inspect_liveness(&self) -> Liveness {
+        unsafe { (*self.spsc.as_ptr()).inspect_liveness() }
+    }
+
+    /// Returns pointer to inner padding.
+    ///
+    /// User is responsible for safe usage.
+    ///
+    /// Unsynchronized: prefer [metadata](Self::metadata) for values
+    /// that change while the channel is in use.
+    ///
+    /// Byte array is 128 byte aligned.
+    pub fn get_padding_ptr(&self) -> NonNull<[u8; 112]> {
+        unsafe {
+            NonNull::new_unchecked(
+                self.spsc.cast::<u8>().as_ptr().add(512),
+            )
+            .cast()
+        }
+    }
+
+    /// Returns a typed, versioned view of the metadata region (the
+    /// same bytes as [get_padding_ptr](Self::get_padding_ptr)).
+    ///
+    /// Fails to compile if `U` is larger than 112 bytes.
+    pub fn metadata<U: Pod>(&self) -> Metadata<'_, U> {
+        unsafe { Channel::metadata(self.spsc) }
+    }
+}
+
+impl<M: ChannelMode<T>, T> Drop for DynProducer<M, T> {
+    fn drop(&mut self) {
+        unsafe {
+            (*self.spsc.as_ptr())
+                .owners
+                .producer
+                .release()
+        }
+    }
+}
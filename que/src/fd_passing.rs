@@ -0,0 +1,128 @@
+//! Passing file descriptors over Unix domain sockets with
+//! `SCM_RIGHTS`.
+//!
+//! This lets a supervisor create an anonymous channel and hand it to
+//! other processes without giving it a name in any filesystem.
+
+use std::{
+    ffi::c_void,
+    mem::size_of,
+    os::{
+        fd::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd},
+        unix::net::UnixStream,
+    },
+};
+
+use nix::{errno::Errno, libc};
+
+use crate::shmem::ShmemError;
+
+/// Room for a control message carrying one fd, aligned for `cmsghdr`.
+#[repr(C)]
+union ControlBuffer {
+    _align: libc::cmsghdr,
+    bytes: [u8; 64],
+}
+
+/// Sends `fd` over `socket` along with a single payload byte.
+pub fn send_fd(
+    socket: &UnixStream,
+    fd: BorrowedFd<'_>,
+) -> Result<(), ShmemError> {
+    let payload = [0_u8];
+    let mut iov = libc::iovec {
+        iov_base: payload.as_ptr() as *mut c_void,
+        iov_len: payload.len(),
+    };
+    let mut control = ControlBuffer { bytes: [0; 64] };
+
+    unsafe {
+        let space = libc::CMSG_SPACE(size_of::<RawFd>() as u32);
+        let mut msg: libc::msghdr = core::mem::zeroed();
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.bytes.as_mut_ptr().cast();
+        msg.msg_controllen = space as _;
+
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+        (*cmsg).cmsg_len =
+            libc::CMSG_LEN(size_of::<RawFd>() as u32) as _;
+        libc::CMSG_DATA(cmsg)
+            .cast::<RawFd>()
+            .write_unaligned(fd.as_raw_fd());
+
+        loop {
+            match libc::sendmsg(
+                socket.as_raw_fd(),
+                &msg,
+                libc::MSG_NOSIGNAL,
+            ) {
+                -1 if Errno::last() == Errno::EINTR => {}
+                -1 => return Err(Errno::last().into()),
+                _ => return Ok(()),
+            }
+        }
+    }
+}
+
+/// Receives an fd sent with [send_fd]. It is close-on-exec.
+///
+/// Fails with [ShmemError::NoFdReceived] if the peer closed the socket
+/// or sent a message without an fd.
+pub fn recv_fd(socket: &UnixStream) -> Result<OwnedFd, ShmemError> {
+    let mut payload = [0_u8];
+    let mut iov = libc::iovec {
+        iov_base: payload.as_mut_ptr().cast(),
+        iov_len: payload.len(),
+    };
+    let mut control = ControlBuffer { bytes: [0; 64] };
+
+    unsafe {
+        let mut msg: libc::msghdr = core::mem::zeroed();
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.bytes.as_mut_ptr().cast();
+        msg.msg_controllen =
+            libc::CMSG_SPACE(size_of::<RawFd>() as u32) as _;
+
+        let received = loop {
+            match libc::recvmsg(
+                socket.as_raw_fd(),
+                &mut msg,
+                libc::MSG_CMSG_CLOEXEC,
+            ) {
+                -1 if Errno::last() == Errno::EINTR => {}
+                -1 => return Err(Errno::last().into()),
+                n => break n,
+            }
+        };
+        if received == 0 {
+            return Err(ShmemError::NoFdReceived);
+        }
+
+        let mut fd = None;
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET
+                && (*cmsg).cmsg_type == libc::SCM_RIGHTS
+            {
+                let data = libc::CMSG_DATA(cmsg).cast::<RawFd>();
+                let count = ((*cmsg).cmsg_len as usize
+                    - libc::CMSG_LEN(0) as usize)
+                    / size_of::<RawFd>();
+                // Own every fd received so extras get closed
+                for i in 0..count {
+                    let owned = OwnedFd::from_raw_fd(
+                        data.add(i).read_unaligned(),
+                    );
+                    fd.get_or_insert(owned);
+                }
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+
+        fd.ok_or(ShmemError::NoFdReceived)
+    }
+}
@@ -0,0 +1,145 @@
+//! Description of the element type a channel was created with, stored
+//! in the channel header so that a reader built with a different `T`
+//! is rejected on join instead of reinterpreting bytes.
+
+use crate::{
+    atomic_compat::{AtomicU64, Ordering},
+    error::QueError,
+};
+
+/// Version of the channel header layout. Bumped whenever the header
+/// changes in a way older binaries cannot read, in the same change;
+/// the header layout test fails until it is.
+pub const LAYOUT_VERSION: u64 = 4;
+
+/// Size, alignment and optional user-supplied hash of an element type.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TypeFingerprint {
+    pub size: u64,
+    pub align: u64,
+    pub layout_version: u64,
+    /// User-supplied hash of the type's fields, or 0 if none. Only
+    /// compared when both sides supply one.
+    pub type_hash: u64,
+}
+
+impl TypeFingerprint {
+    /// Returns the fingerprint of `T` under the current layout version,
+    /// with the given `type_hash` (0 for none).
+    pub const fn of<T>(type_hash: u64) -> Self {
+        Self {
+            size: core::mem::size_of::<T>() as u64,
+            align: core::mem::align_of::<T>() as u64,
+            layout_version: LAYOUT_VERSION,
+            type_hash,
+        }
+    }
+
+    fn matches(&self, found: &Self) -> bool {
+        self.size == found.size
+            && self.align == found.align
+            && self.layout_version == found.layout_version
+            && (self.type_hash == 0
+                || found.type_hash == 0
+                || self.type_hash == found.type_hash)
+    }
+}
+
+/// Header storage for a [TypeFingerprint].
+#[repr(C, align(128))]
+pub(crate) struct Fingerprint {
+    size: AtomicU64,
+    align: AtomicU64,
+    layout_version: AtomicU64,
+    type_hash: AtomicU64,
+}
+
+impl Fingerprint {
+    #[cfg(all(loom, test))]
+    pub(crate) fn new(fingerprint: TypeFingerprint) -> Self {
+        Self {
+            size: AtomicU64::new(fingerprint.size),
+            align: AtomicU64::new(fingerprint.align),
+            layout_version: AtomicU64::new(fingerprint.layout_version),
+            type_hash: AtomicU64::new(fingerprint.type_hash),
+        }
+    }
+
+    /// Records `fingerprint`. Must happen before the magic is
+    /// published, which orders these stores for joiners.
+    pub(crate) fn store(&self, fingerprint: TypeFingerprint) {
+        self.size
+            .store(fingerprint.size, Ordering::Relaxed);
+        self.align
+            .store(fingerprint.align, Ordering::Relaxed);
+        self.layout_version
+            .store(fingerprint.layout_version, Ordering::Relaxed);
+        self.type_hash
+            .store(fingerprint.type_hash, Ordering::Relaxed);
+    }
+
+    /// Reads the recorded fingerprint. Must happen after the magic was
+    /// loaded with `Acquire`.
+    pub(crate) fn load(&self) -> TypeFingerprint {
+        TypeFingerprint {
+            size: self.size.load(Ordering::Relaxed),
+            align: self.align.load(Ordering::Relaxed),
+            layout_version: self
+                .layout_version
+                .load(Ordering::Relaxed),
+            type_hash: self.type_hash.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Checks the recorded fingerprint against `expected`.
+    pub(crate) fn check(
+        &self,
+        expected: TypeFingerprint,
+    ) -> Result<(), QueError> {
+        let found = self.load();
+        if expected.matches(&found) {
+            Ok(())
+        } else {
+            Err(QueError::TypeMismatch { expected, found })
+        }
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use core::mem::offset_of;
+
+    use super::LAYOUT_VERSION;
+    use crate::{Channel, LocalMode};
+
+    /// Any change to these offsets must come with a new
+    /// [LAYOUT_VERSION] (and the same change in `c/spsc.c`).
+    #[test]
+    fn test_layout_pinned_to_version() {
+        type Header = Channel<LocalMode, u64, 4>;
+        let offsets = [
+            offset_of!(Header, tail),
+            offset_of!(Header, head),
+            offset_of!(Header, producer_heartbeat),
+            offset_of!(Header, consumer_heartbeat),
+            offset_of!(Header, padding),
+            offset_of!(Header, capacity),
+            offset_of!(Header, magic),
+            offset_of!(Header, consumer_notify),
+            offset_of!(Header, producer_notify),
+            offset_of!(Header, fingerprint),
+            offset_of!(Header, owners),
+            offset_of!(Header, metadata_seq),
+            offset_of!(Header, buffer),
+        ];
+
+        assert_eq!(LAYOUT_VERSION, 4);
+        assert_eq!(
+            offsets,
+            [
+                0, 128, 256, 384, 512, 624, 632, 640, 768, 896, 1024,
+                1152, 1280
+            ]
+        );
+    }
+}
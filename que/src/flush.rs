@@ -0,0 +1,89 @@
+//! Policies for when producers publish written elements on their own,
+//! shared by the lossless and headless producers.
+
+use std::time::{Duration, Instant};
+
+/// When a producer publishes written elements to its consumers on its
+/// own, in addition to explicit calls to `sync`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FlushPolicy {
+    /// Only publish on `sync` (and on drop).
+    #[default]
+    Manual,
+    /// Publish once this many elements are unpublished.
+    EveryN(usize),
+    /// Publish once the oldest unpublished element has waited this
+    /// long. To keep the clock off the push path, it is only read on
+    /// the first write after a publish and then every 8 writes, so a
+    /// producer that writes slowly or goes idle must call `sync_if_due`
+    /// to bound latency.
+    Linger(Duration),
+    /// Publish every element as it is pushed.
+    Immediate,
+}
+
+/// Writes between clock reads under [FlushPolicy::Linger].
+pub(crate) const LINGER_CHECK_INTERVAL: usize = 8;
+
+/// Tracks a producer's unpublished elements against its [FlushPolicy].
+#[derive(Debug, Default)]
+pub(crate) struct Flusher {
+    policy: FlushPolicy,
+    /// When the oldest unpublished element was written, under
+    /// [FlushPolicy::Linger].
+    pending_since: Option<Instant>,
+    /// Unpublished count at which [FlushPolicy::Linger] next reads the
+    /// clock.
+    check_at: usize,
+}
+
+impl Flusher {
+    pub(crate) fn new(policy: FlushPolicy) -> Self {
+        Flusher {
+            policy,
+            pending_since: None,
+            check_at: 0,
+        }
+    }
+
+    /// Returns whether to publish after a write left `pending`
+    /// elements unpublished.
+    #[inline(always)]
+    pub(crate) fn on_write(&mut self, pending: usize) -> bool {
+        match self.policy {
+            FlushPolicy::Manual => false,
+            FlushPolicy::EveryN(n) => pending >= n,
+            FlushPolicy::Linger(linger) => {
+                if pending < self.check_at {
+                    return false;
+                }
+                self.check_at = pending + LINGER_CHECK_INTERVAL;
+
+                let now = Instant::now();
+                now - *self.pending_since.get_or_insert(now) >= linger
+            }
+            FlushPolicy::Immediate => true,
+        }
+    }
+
+    /// Returns whether elements written before now are due without
+    /// another write.
+    #[inline(always)]
+    pub(crate) fn is_due(&self, pending: usize) -> bool {
+        match self.policy {
+            FlushPolicy::Manual => false,
+            FlushPolicy::EveryN(n) => pending >= n,
+            FlushPolicy::Linger(linger) => self
+                .pending_since
+                .is_some_and(|since| since.elapsed() >= linger),
+            FlushPolicy::Immediate => pending > 0,
+        }
+    }
+
+    /// Records that everything written was published.
+    #[inline(always)]
+    pub(crate) fn flushed(&mut self) {
+        self.pending_since = None;
+        self.check_at = 0;
+    }
+}
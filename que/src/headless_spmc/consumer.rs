@@ -1,83 +1,161 @@
 use crate::atomic_compat::Ordering;
 use std::{ptr::NonNull, sync::Arc};
 
-use bytemuck::AnyBitPattern;
+use bytemuck::{AnyBitPattern, Pod};
 
 use crate::{
-    error::QueError, headless_spmc::MAGIC, page_size::PageSize,
-    shmem::Shmem, ChannelMode, ShmemMode,
+    error::QueError,
+    shmem::{Shmem, ShmemConfig},
+    ChannelMode, Liveness, Metadata, ShmemMode,
 };
 
-use super::{burst_amount, Channel};
+use super::{burst_amount, Channel, Stamping, Unstamped};
 
-unsafe impl<M: ChannelMode<T>, T, const N: usize> Send
-    for Consumer<M, T, N>
+unsafe impl<M: ChannelMode<T>, T, const N: usize, S: Stamping<T>> Send
+    for Consumer<M, T, N, S>
 {
 }
 
+/// Outcome of [Consumer::pop_batch].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PopResult {
+    /// Elements copied to the front of the output
+    pub read: usize,
+    /// Elements of this consumer the producer overwrote before they
+    /// could be read
+    pub lost: u64,
+}
+
+/// Returned by [Consumer::read_with] when this consumer's elements were
+/// lost to overrun, including an element overwritten while it was being
+/// read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Overrun {
+    /// Elements lost since the previous element was returned
+    pub lost: u64,
+}
+
 #[repr(C)]
-pub struct Consumer<M: ChannelMode<T>, T, const N: usize> {
-    spsc: NonNull<Channel<M, T, N>>,
+pub struct Consumer<
+    M: ChannelMode<T>,
+    T,
+    const N: usize,
+    S: Stamping<T> = Unstamped,
+> {
+    spsc: NonNull<Channel<M, S::Slot, N>>,
     head: usize,
     interval: usize,
     consumer_index: usize,
     last_producer_heartbeat: usize,
+    /// Elements lost to overrun since joining
+    lost: u64,
+    /// Elements lost since the last element was returned
+    gap: u64,
+    on_overrun: Option<Box<dyn FnMut(u64) + Send>>,
+    /// Mapping backing the channel, if joined through shmem.
+    shmem: Option<Shmem>,
 }
 
-impl<T: AnyBitPattern, const N: usize> Consumer<ShmemMode, T, N> {
+impl<T: AnyBitPattern, const N: usize, S: Stamping<T>>
+    Consumer<ShmemMode, T, N, S>
+{
     /// Joins an existing channel back by shared memory as a consumer.
+    ///
+    /// Never creates or resizes the shmem: fails with
+    /// [ShmemError::NotFound](crate::shmem::ShmemError::NotFound) if no
+    /// producer has created it yet.
+    ///
+    /// # Safety
+    /// The shared memory region must only ever be mapped as a
+    /// `Channel` with the same `T` and `N`.
     pub unsafe fn join_shmem(
         shmem_id: &str,
-        #[cfg(target_os = "linux")] page_size: PageSize,
-    ) -> Result<Consumer<ShmemMode, T, N>, QueError> {
+        #[cfg(target_os = "linux")] config: impl Into<ShmemConfig>,
+    ) -> Result<Consumer<ShmemMode, T, N, S>, QueError> {
         Self::join_shmem_multi(
             shmem_id,
             #[cfg(target_os = "linux")]
-            page_size,
+            config,
             0,
             1,
         )
     }
 
+    /// Like [Consumer::join_shmem], additionally checking `type_hash`
+    /// against the one recorded by the producer. A hash of 0 is not
+    /// checked.
+    ///
+    /// # Safety
+    /// The shared memory region must only ever be mapped as a
+    /// `Channel` with the same `T` and `N`.
+    pub unsafe fn join_shmem_with_type_hash(
+        shmem_id: &str,
+        type_hash: u64,
+        #[cfg(target_os = "linux")] config: impl Into<ShmemConfig>,
+    ) -> Result<Consumer<ShmemMode, T, N, S>, QueError> {
+        let consumer = Self::join_shmem(
+            shmem_id,
+            #[cfg(target_os = "linux")]
+            config,
+        )?;
+        consumer
+            .spsc
+            .as_ref()
+            .check_fingerprint(type_hash)?;
+
+        Ok(consumer)
+    }
+
     /// Joins an existing channel back by shared memory as a consumer.
     ///
     /// `interval` is the number of consumers. This channel is not FIFO!
     /// To consume all produced values, you must consume all values
     /// generated by all consumers generated via `next_multi`.
+    ///
+    /// # Safety
+    /// The shared memory region must only ever be mapped as a
+    /// `Channel` with the same `T` and `N`.
     pub unsafe fn join_shmem_multi(
         shmem_id: &str,
-        #[cfg(target_os = "linux")] page_size: PageSize,
+        #[cfg(target_os = "linux")] config: impl Into<ShmemConfig>,
         index: usize,
         num_consumers: usize,
-    ) -> Result<Consumer<ShmemMode, T, N>, QueError> {
+    ) -> Result<Consumer<ShmemMode, T, N, S>, QueError> {
         #[cfg(not(target_os = "linux"))]
-        let page_size = PageSize::Standard;
+        let config = ShmemConfig::default();
+        #[cfg(target_os = "linux")]
+        let config: ShmemConfig = config.into();
 
         // Calculate buffer size.
         // If using huge pages, we must uplign to page size.
-        let buffer_size: i64 = page_size
-            .mem_size(core::mem::size_of::<Channel<ShmemMode, T, N>>())
+        let buffer_size: i64 = config
+            .page_size()
+            .mem_size(core::mem::size_of::<
+                Channel<ShmemMode, S::Slot, N>,
+            >())
             .try_into()
             .map_err(|_| QueError::InvalidSize)?;
 
         // Open shmem
-        let shmem = Shmem::open_or_create(
-            shmem_id,
-            buffer_size,
-            #[cfg(target_os = "linux")]
-            page_size,
+        let shmem = config.open_existing(shmem_id, buffer_size)?;
+
+        let mut consumer = Consumer::join_multi(
+            shmem.get_mut_ptr(),
+            index,
+            num_consumers,
         )?;
+        consumer.shmem = Some(shmem);
 
-        Consumer::join_multi(shmem.get_mut_ptr(), index, num_consumers)
+        Ok(consumer)
     }
 
     /// Joins an existing channel backed by `buffer`.
     ///
-    /// SAFETY:
+    /// # Safety
     /// This must point to a buffer of proper size and alignment.
     pub unsafe fn join(
         buffer: *mut u8,
-    ) -> Result<Consumer<ShmemMode, T, N>, QueError> {
+    ) -> Result<Consumer<ShmemMode, T, N, S>, QueError> {
         Self::join_multi(buffer, 0, 1)
     }
 
@@ -87,30 +165,62 @@ impl<T: AnyBitPattern, const N: usize> Consumer<ShmemMode, T, N> {
     /// To consume all produced values, you must consume all values
     /// generated by all consumers generated via `next_multi`.
     ///
-    /// SAFETY:
+    /// # Safety
     /// This must point to a buffer of proper size and alignment.
     pub unsafe fn join_multi(
         buffer: *mut u8,
         index: usize,
         consumers: usize,
-    ) -> Result<Consumer<ShmemMode, T, N>, QueError> {
+    ) -> Result<Consumer<ShmemMode, T, N, S>, QueError> {
         Self::join_multi_(buffer, index, consumers)
     }
+
+    /// Joins the channel in `shmem` as a consumer, e.g. one received
+    /// with [Shmem::recv]. The endpoint owns the mapping.
+    ///
+    /// # Safety
+    /// The shared memory region must only ever be mapped as a
+    /// `Channel` with the same `T` and `N`.
+    pub unsafe fn from_shmem(
+        shmem: Shmem,
+    ) -> Result<Consumer<ShmemMode, T, N, S>, QueError> {
+        if (shmem.size as usize)
+            < core::mem::size_of::<Channel<ShmemMode, S::Slot, N>>()
+        {
+            return Err(QueError::InvalidSize);
+        }
+
+        let mut consumer = Self::join(shmem.get_mut_ptr())?;
+        consumer.shmem = Some(shmem);
+
+        Ok(consumer)
+    }
+
+    /// Unlinks the shared memory object when this endpoint is dropped,
+    /// if it created the object. Has no effect for endpoints joined
+    /// to an existing object or to a caller-provided buffer.
+    pub fn set_unlink_on_drop(&mut self, unlink: bool) {
+        if let Some(shmem) = self.shmem.as_mut() {
+            shmem.set_unlink_on_drop(unlink);
+        }
+    }
 }
 
-impl<M: ChannelMode<T>, T, const N: usize> Consumer<M, T, N> {
+impl<M: ChannelMode<T>, T, const N: usize, S: Stamping<T>>
+    Consumer<M, T, N, S>
+{
     pub const MODULO_MASK: usize = N - 1;
 
     pub(crate) unsafe fn join_multi_(
         buffer: *mut u8,
         index: usize,
         consumers: usize,
-    ) -> Result<Consumer<M, T, N>, QueError> {
+    ) -> Result<Consumer<M, T, N, S>, QueError> {
         assert!(
             N > 0 && N.is_power_of_two(),
             "Capacity must be a power of two"
         );
-        assert!(buffer as usize % 128 == 0, "unaligned");
+        assert!((buffer as usize).is_multiple_of(128), "unaligned");
         assert!(
             consumers <= 64,
             "interval must be less than or equal to 64"
@@ -120,17 +230,19 @@ impl<M: ChannelMode<T>, T, const N: usize> Consumer<M, T, N> {
             "index must be less than the total number of consumers"
         );
 
-        let spsc: *mut Channel<M, T, N> = buffer.cast();
+        let spsc: *mut Channel<M, S::Slot, N> = buffer.cast();
 
         // Check magic
         let magic = (*spsc).magic.load(Ordering::Acquire);
         let capacity = (*spsc).capacity.load(Ordering::Acquire);
-        if magic == MAGIC {
+        if magic == S::MAGIC {
             // Check capacity
             if capacity != N {
                 return Err(QueError::IncorrectCapacity(capacity));
             }
 
+            (*spsc).check_fingerprint(0)?;
+
             // Assume spsc is empty upon joining and set our cursor to the tail
             let head = (*spsc).tail.load(Ordering::Acquire);
 
@@ -143,12 +255,16 @@ impl<M: ChannelMode<T>, T, const N: usize> Consumer<M, T, N> {
             // Successful join if magic and capacity is correct
             Ok(Consumer {
                 spsc: NonNull::new_unchecked(buffer.cast()),
+                shmem: None,
                 head: next_modulo(head, index, consumers),
                 interval: consumers,
                 consumer_index: index,
                 last_producer_heartbeat: (*spsc)
                     .producer_heartbeat
                     .load(Ordering::Acquire),
+                lost: 0,
+                gap: 0,
+                on_overrun: None,
             })
         } else if magic == 0 {
             // Technically could be corrupted but uninitialized
@@ -156,13 +272,36 @@ impl<M: ChannelMode<T>, T, const N: usize> Consumer<M, T, N> {
             Err(QueError::Uninitialized)
         } else {
             // Magic is not MAGIC and not zero
-            println!("magic = {}; expected {}", magic, MAGIC);
+            println!("magic = {}; expected {}", magic, S::MAGIC);
             Err(QueError::CorruptionDetected)
         }
     }
     /// Attempts to read the next element. Returns `None` if the
     /// consuemr is caught up.
+    ///
+    /// Elements overwritten before they could be read are skipped,
+    /// see [Consumer::lost].
     pub fn pop(&mut self) -> Option<T> {
+        self.pop_with_gap()
+            .map(|(value, _)| value)
+    }
+
+    /// Like [Consumer::pop], additionally returning the element's
+    /// sequence number, its position in the stream of every element
+    /// pushed to the channel.
+    ///
+    /// Sequence numbers skip elements lost to overrun and, for
+    /// consumers joined with an interval, those read by the other
+    /// consumers.
+    pub fn pop_with_seq(&mut self) -> Option<(u64, T)> {
+        let (value, _) = self.pop_with_gap()?;
+        Some(((self.head - self.interval) as u64, value))
+    }
+
+    /// Like [Consumer::pop], additionally returning how many of this
+    /// consumer's elements were lost to overrun since the previous
+    /// element was returned.
+    pub fn pop_with_gap(&mut self) -> Option<(T, u64)> {
         loop {
             let initial_tail = unsafe {
                 (*self.spsc.as_ptr())
@@ -184,24 +323,24 @@ impl<M: ChannelMode<T>, T, const N: usize> Consumer<M, T, N> {
                     .wrapping_add(N - burst_amount::<N>()));
             if !not_overrun {
                 // Must reset to next integer that is consumer_index % interval
-                self.head = next_modulo(
+                self.skip_to(
                     initial_tail.wrapping_sub(N - burst_amount::<N>()),
-                    self.consumer_index,
-                    self.interval,
                 );
                 continue;
             }
 
             // Optimistically read value and then check if valid
-            let head_index = self.head & Self::MODULO_MASK;
-            let value = unsafe {
-                core::ptr::read(
-                    (*self.spsc.as_ptr())
-                        .buffer
-                        .as_ptr()
-                        .add(head_index),
-                )
+            let value = match unsafe { self.read_at(self.head) } {
+                Ok(value) => value,
+                Err(reached) => {
+                    self.skip_past(reached);
+                    continue;
+                }
             };
+            if S::VERIFIED {
+                self.head += self.interval;
+                return Some((value, core::mem::take(&mut self.gap)));
+            }
 
             // Check if still not overrun
             let current_tail = unsafe {
@@ -218,19 +357,285 @@ impl<M: ChannelMode<T>, T, const N: usize> Consumer<M, T, N> {
             if !still_not_overrun {
                 // Must reset to next integer that is consumer_index %
                 // interval
-                self.head = next_modulo(
+                self.skip_to(
                     current_tail.wrapping_sub(N - burst_amount::<N>()),
-                    self.consumer_index,
-                    self.interval,
                 );
                 continue;
             }
 
             self.head += self.interval;
-            return Some(value);
+            return Some((value, core::mem::take(&mut self.gap)));
         }
     }
 
+    /// Runs `f` over the next element in place in the ring instead of
+    /// copying it out, then checks that the element was not overwritten
+    /// meanwhile, as [Consumer::pop] does. Returns `None` if the
+    /// consumer is caught up.
+    ///
+    /// If elements were lost, returns [Overrun] instead and discards
+    /// the result of `f` if it ran on an overwritten element. The next
+    /// call reads the oldest element still available.
+    pub fn read_with<R>(
+        &mut self,
+        f: impl FnOnce(&T) -> R,
+    ) -> Option<Result<R, Overrun>> {
+        let window = N - burst_amount::<N>();
+        let initial_tail = unsafe {
+            (*self.spsc.as_ptr())
+                .tail
+                .load(Ordering::Acquire)
+        };
+
+        // Check if there's anything to read
+        if initial_tail <= self.head {
+            return None;
+        }
+
+        // Check for overrun
+        if initial_tail > self.head.wrapping_add(window) {
+            self.skip_to(initial_tail - window);
+            return Some(Err(self.overrun()));
+        }
+
+        let result = unsafe {
+            S::read_with(
+                (*self.spsc.as_ptr())
+                    .buffer
+                    .as_ptr()
+                    .add(self.head & Self::MODULO_MASK),
+                self.head,
+                f,
+            )
+        };
+        let result = match result {
+            Ok(result) => result,
+            Err(reached) => {
+                self.skip_past(reached);
+                return Some(Err(self.overrun()));
+            }
+        };
+
+        // Check if still not overrun
+        if !S::VERIFIED {
+            let current_tail = unsafe {
+                (*self.spsc.as_ptr())
+                    .tail
+                    .load(Ordering::Acquire)
+            };
+            if current_tail > self.head.wrapping_add(window) {
+                self.skip_to(current_tail - window);
+                return Some(Err(self.overrun()));
+            }
+        }
+
+        self.head += self.interval;
+        Some(Ok(result))
+    }
+
+    fn overrun(&mut self) -> Overrun {
+        Overrun {
+            lost: core::mem::take(&mut self.gap),
+        }
+    }
+
+    /// Reads up to `values.len()` of this consumer's elements into
+    /// `values`, checking for overrun once before and once after the
+    /// copy instead of around every element.
+    ///
+    /// A consumer joined with an interval gathers every interval-th
+    /// element, as [Consumer::pop] would. Like [Consumer::pop_with_gap],
+    /// the result counts the elements lost since the previous element
+    /// was returned.
+    ///
+    /// On a [Stamped](super::Stamped) channel every element's stamp is
+    /// checked instead, and the batch ends at the first overwritten one.
+    pub fn pop_batch(&mut self, values: &mut [T]) -> PopResult
+    where
+        T: Copy,
+    {
+        // Elements older than `tail - window` may be overwritten
+        let window = N - burst_amount::<N>();
+
+        loop {
+            let initial_tail = unsafe {
+                (*self.spsc.as_ptr())
+                    .tail
+                    .load(Ordering::Acquire)
+            };
+
+            // Check if there's anything to read
+            if initial_tail <= self.head || values.is_empty() {
+                return PopResult {
+                    read: 0,
+                    lost: core::mem::take(&mut self.gap),
+                };
+            }
+
+            // Check for overrun
+            if initial_tail > self.head.wrapping_add(window) {
+                self.skip_to(initial_tail - window);
+                continue;
+            }
+
+            // Optimistically copy values and then check if valid
+            let count = (initial_tail - self.head)
+                .div_ceil(self.interval)
+                .min(values.len());
+            if S::VERIFIED {
+                let read =
+                    unsafe { self.read_verified(&mut values[..count]) };
+                if read == 0 {
+                    continue;
+                }
+                return PopResult {
+                    read,
+                    lost: core::mem::take(&mut self.gap),
+                };
+            }
+            unsafe { self.gather(&mut values[..count]) };
+
+            let current_tail = unsafe {
+                (*self.spsc.as_ptr())
+                    .tail
+                    .load(Ordering::Acquire)
+            };
+
+            // Drop the front of the batch if it was overwritten
+            let overrun = current_tail
+                .saturating_sub(self.head.wrapping_add(window))
+                .div_ceil(self.interval)
+                .min(count);
+            self.head += count * self.interval;
+            self.record_lost(overrun as u64);
+            if overrun == count {
+                continue;
+            }
+            values.copy_within(overrun..count, 0);
+
+            return PopResult {
+                read: count - overrun,
+                lost: core::mem::take(&mut self.gap),
+            };
+        }
+    }
+
+    /// Reads the element at `position` from its slot, see
+    /// [Stamping::read].
+    #[inline(always)]
+    unsafe fn read_at(&self, position: usize) -> Result<T, usize> {
+        S::read(
+            (*self.spsc.as_ptr())
+                .buffer
+                .as_ptr()
+                .add(position & Self::MODULO_MASK),
+            position,
+        )
+    }
+
+    /// Reads this consumer's next `values.len()` elements from a
+    /// verified ring, stopping at the first overwritten one. Returns the
+    /// number read.
+    unsafe fn read_verified(&mut self, values: &mut [T]) -> usize {
+        for (read, value) in values.iter_mut().enumerate() {
+            match self.read_at(self.head) {
+                Ok(read_value) => *value = read_value,
+                Err(reached) => {
+                    // Elements read so far are returned before the loss
+                    // is counted, so it is left for the next call
+                    if read == 0 {
+                        self.skip_past(reached);
+                    }
+                    return read;
+                }
+            }
+            self.head += self.interval;
+        }
+
+        values.len()
+    }
+
+    /// Copies this consumer's next `values.len()` elements from an
+    /// unstamped ring, without checking for overrun.
+    unsafe fn gather(&self, values: &mut [T]) {
+        // Unstamped slots are bare elements
+        let buffer_ptr = (*self.spsc.as_ptr())
+            .buffer
+            .as_ptr()
+            .cast::<T>();
+        let start_index = self.head & Self::MODULO_MASK;
+
+        if self.interval == 1 {
+            // At most two contiguous runs
+            let first_len = values.len().min(N - start_index);
+            core::ptr::copy_nonoverlapping(
+                buffer_ptr.add(start_index),
+                values.as_mut_ptr(),
+                first_len,
+            );
+            core::ptr::copy_nonoverlapping(
+                buffer_ptr,
+                values.as_mut_ptr().add(first_len),
+                values.len() - first_len,
+            );
+        } else {
+            let mut index = start_index;
+            for value in values.iter_mut() {
+                *value = core::ptr::read(buffer_ptr.add(index));
+                index = (index + self.interval) & Self::MODULO_MASK;
+            }
+        }
+    }
+
+    /// Moves the head to this consumer's first element at or after
+    /// `position`, counting the elements skipped as lost.
+    fn skip_to(&mut self, position: usize) {
+        let head =
+            next_modulo(position, self.consumer_index, self.interval);
+        let skipped = head.saturating_sub(self.head) / self.interval;
+        self.head = head.max(self.head);
+
+        self.record_lost(skipped as u64);
+    }
+
+    /// Skips the elements the producer may have overwritten by the time
+    /// it wrote up to `reached`, and at least the one at the head.
+    fn skip_past(&mut self, reached: usize) {
+        self.skip_to(
+            reached
+                .saturating_sub(N - burst_amount::<N>())
+                .max(self.head + 1),
+        );
+    }
+
+    fn record_lost(&mut self, count: u64) {
+        if count == 0 {
+            return;
+        }
+
+        self.lost += count;
+        self.gap += count;
+        if let Some(on_overrun) = self.on_overrun.as_mut() {
+            on_overrun(count);
+        }
+    }
+
+    /// Returns the number of this consumer's elements lost to overrun
+    /// since it joined.
+    pub fn lost(&self) -> u64 {
+        self.lost
+    }
+
+    /// Calls `hook` with the number of elements lost whenever the
+    /// consumer detects an overrun, before the read that detected it
+    /// returns. Replaces any previous hook.
+    pub fn on_overrun(
+        &mut self,
+        hook: impl FnMut(u64) + Send + 'static,
+    ) {
+        self.on_overrun = Some(Box::new(hook));
+    }
+
     /// Increments the consumer heartbeat.
     ///
     /// Can be read by the producer to see that the consumer is still
@@ -263,11 +668,18 @@ impl<M: ChannelMode<T>, T, const N: usize> Consumer<M, T, N> {
         }
     }
 
+    /// Checks whether the processes holding either end of the channel
+    /// are still running, see [Channel::inspect_liveness].
+    pub fn inspect_liveness(&self) -> Liveness {
+        unsafe { (*self.spsc.as_ptr()).inspect_liveness() }
+    }
+
     /// Returns pointer to inner padding.
     ///
     /// User is responsible for safe usage.
     ///
-    /// Can be used to store metadata (e.g. hash seed).
+    /// Unsynchronized: prefer [metadata](Self::metadata) for values
+    /// that change while the channel is in use.
     ///
     /// Byte array is 128 byte aligned.
     pub fn get_padding_ptr(&self) -> NonNull<[u8; 112]> {
@@ -278,6 +690,21 @@ impl<M: ChannelMode<T>, T, const N: usize> Consumer<M, T, N> {
             .cast()
         }
     }
+
+    /// Returns a typed, versioned view of the metadata region (the
+    /// same bytes as [get_padding_ptr](Self::get_padding_ptr)).
+    ///
+    /// Fails to compile if `U` is larger than 112 bytes.
+    pub fn metadata<U: Pod>(&self) -> Metadata<'_, U> {
+        unsafe { Channel::metadata(self.spsc) }
+    }
+
+    /// Returns the shared channel header.
+    #[cfg(feature = "async")]
+    #[inline(always)]
+    pub(crate) fn channel(&self) -> &Channel<M, S::Slot, N> {
+        unsafe { self.spsc.as_ref() }
+    }
 }
 
 #[inline(always)]
@@ -291,7 +718,9 @@ fn next_modulo(
     head + add_value
 }
 
-impl<M: ChannelMode<T>, T, const N: usize> Drop for Consumer<M, T, N> {
+impl<M: ChannelMode<T>, T, const N: usize, S: Stamping<T>> Drop
+    for Consumer<M, T, N, S>
+{
     fn drop(&mut self) {
         // LocalMode is backed by arc
         if M::BACKED_BY_ARCC {
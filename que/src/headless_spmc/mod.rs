@@ -1,33 +1,223 @@
+//! Single-producer channel that never waits for its consumers. The
+//! producer overwrites the oldest elements when the ring is full, and
+//! consumers detect that they were lapped by checking the tail.
+//!
+//! Elements large enough to be torn by a lapping producer can be
+//! verified per slot: a channel created with [Stamped] stamps every
+//! slot seqlock-style, so a consumer discards any element that was
+//! overwritten while it was being copied.
+
 pub mod consumer;
 pub mod producer;
 
-use std::{array, mem::MaybeUninit, sync::Arc};
+use std::{
+    array,
+    mem::MaybeUninit,
+    ptr::{addr_of, addr_of_mut},
+    sync::Arc,
+};
 
 use consumer::Consumer;
 use producer::Producer;
 
-use crate::{Channel, LocalMode, MAGIC};
+use crate::{
+    atomic_compat::{fence, AtomicUsize, Ordering},
+    private, Channel, LocalMode, MAGIC,
+};
+
+/// Marks a headless channel created with [Stamped]. Differs from
+/// [crate::MAGIC] since the buffer holds [StampedSlot]s rather than
+/// bare elements.
+pub const STAMPED_MAGIC: u64 = u64::from_le_bytes(*b"TEMPSTMP");
+
+/// How a headless channel lays out its ring, chosen when the channel is
+/// created and recorded in the header through its magic. Endpoints
+/// fail to join a channel created with the other layout.
+#[allow(private_bounds)]
+pub trait Stamping<T>: private::Sealed {
+    type Slot;
+    const MAGIC: u64;
+    /// Whether [Stamping::read] detects overwritten slots itself, so
+    /// consumers need not re-check the tail.
+    const VERIFIED: bool;
+
+    /// Writes the element for `position` to `slot`.
+    ///
+    /// # Safety
+    /// `slot` must point to a slot of the ring at `position` modulo the
+    /// capacity, written only by this producer.
+    unsafe fn write(slot: *mut Self::Slot, position: usize, value: T);
+
+    /// Like [Stamping::write], constructing the element in place with
+    /// `init`.
+    ///
+    /// # Safety
+    /// As for [Stamping::write], and `init` must initialize the
+    /// element.
+    unsafe fn write_with(
+        slot: *mut Self::Slot,
+        position: usize,
+        init: impl FnOnce(&mut MaybeUninit<T>),
+    );
+
+    /// Reads the element for `position` from `slot`. If the slot was
+    /// overwritten, returns a position the producer has written up to.
+    ///
+    /// # Safety
+    /// `slot` must point to a slot of the ring at `position` modulo the
+    /// capacity, published by the producer.
+    unsafe fn read(
+        slot: *const Self::Slot,
+        position: usize,
+    ) -> Result<T, usize>;
+
+    /// Like [Stamping::read], running `f` over the element in place
+    /// instead of copying it out.
+    ///
+    /// # Safety
+    /// As for [Stamping::read]. `f` may observe an element that is
+    /// being overwritten if the slot is not verified.
+    unsafe fn read_with<R>(
+        slot: *const Self::Slot,
+        position: usize,
+        f: impl FnOnce(&T) -> R,
+    ) -> Result<R, usize>;
+}
 
-pub fn headless_pair<T: Send, const N: usize>(
-) -> (Producer<LocalMode, T, N>, Consumer<LocalMode, T, N>) {
-    let arc_uninit = Arc::<Channel<LocalMode, T, N>>::new_uninit();
-    let ptr: *mut MaybeUninit<Channel<LocalMode, T, N>> =
-        Arc::into_raw(arc_uninit).cast_mut();
+/// Bare elements, checked for overrun against the tail only. The
+/// layout is shared with the lossless channel.
+pub struct Unstamped;
 
-    unsafe {
-        *ptr = core::mem::zeroed();
+/// Elements stamped with the position they were written at.
+pub struct Stamped;
+
+impl private::Sealed for Unstamped {}
+impl private::Sealed for Stamped {}
+
+impl<T> Stamping<T> for Unstamped {
+    type Slot = T;
+    const MAGIC: u64 = MAGIC;
+    const VERIFIED: bool = false;
+
+    #[inline(always)]
+    unsafe fn write(slot: *mut T, _: usize, value: T) {
+        *slot = value;
     }
 
-    let producer = unsafe {
-        Producer::join_or_initialize_in_(ptr.cast()).unwrap()
-    };
-    let consumer =
-        unsafe { Consumer::join_multi_(ptr.cast(), 0, 1).unwrap() };
+    #[inline(always)]
+    unsafe fn write_with(
+        slot: *mut T,
+        _: usize,
+        init: impl FnOnce(&mut MaybeUninit<T>),
+    ) {
+        init(&mut *slot.cast());
+    }
 
-    unsafe {
-        Arc::decrement_strong_count(ptr);
+    #[inline(always)]
+    unsafe fn read(slot: *const T, _: usize) -> Result<T, usize> {
+        Ok(core::ptr::read(slot))
+    }
+
+    #[inline(always)]
+    unsafe fn read_with<R>(
+        slot: *const T,
+        _: usize,
+        f: impl FnOnce(&T) -> R,
+    ) -> Result<R, usize> {
+        Ok(f(&*slot))
+    }
+}
+
+impl<T> Stamping<T> for Stamped {
+    type Slot = StampedSlot<T>;
+    const MAGIC: u64 = STAMPED_MAGIC;
+    const VERIFIED: bool = true;
+
+    #[inline(always)]
+    unsafe fn write(
+        slot: *mut StampedSlot<T>,
+        position: usize,
+        value: T,
+    ) {
+        Self::write_with(slot, position, |element| {
+            element.write(value);
+        });
+    }
+
+    #[inline(always)]
+    unsafe fn write_with(
+        slot: *mut StampedSlot<T>,
+        position: usize,
+        init: impl FnOnce(&mut MaybeUninit<T>),
+    ) {
+        let stamp = &(*slot).stamp;
+        stamp.store(2 * position + 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+        init(&mut *addr_of_mut!((*slot).value).cast());
+        stamp.store(2 * position + 2, Ordering::Release);
     }
 
+    #[inline(always)]
+    unsafe fn read(
+        slot: *const StampedSlot<T>,
+        position: usize,
+    ) -> Result<T, usize> {
+        let expected = 2 * position + 2;
+        let stamp = &(*slot).stamp;
+
+        let before = stamp.load(Ordering::Acquire);
+        if before != expected {
+            return Err(before.div_ceil(2));
+        }
+        let value = core::ptr::read_volatile(
+            addr_of!((*slot).value).cast::<MaybeUninit<T>>(),
+        );
+        fence(Ordering::Acquire);
+        let after = stamp.load(Ordering::Relaxed);
+        if after != expected {
+            return Err(after.div_ceil(2));
+        }
+
+        Ok(value.assume_init())
+    }
+
+    #[inline(always)]
+    unsafe fn read_with<R>(
+        slot: *const StampedSlot<T>,
+        position: usize,
+        f: impl FnOnce(&T) -> R,
+    ) -> Result<R, usize> {
+        let expected = 2 * position + 2;
+        let stamp = &(*slot).stamp;
+
+        let before = stamp.load(Ordering::Acquire);
+        if before != expected {
+            return Err(before.div_ceil(2));
+        }
+        let result = f(&*addr_of!((*slot).value));
+        fence(Ordering::Acquire);
+        let after = stamp.load(Ordering::Relaxed);
+        if after != expected {
+            return Err(after.div_ceil(2));
+        }
+
+        Ok(result)
+    }
+}
+
+/// A buffer element of a [Stamped] channel.
+#[repr(C)]
+pub struct StampedSlot<T> {
+    /// `2 * position + 1` while the element for `position` is being
+    /// written and `2 * position + 2` once it is. Zero (never written)
+    /// is a valid initial state.
+    stamp: AtomicUsize,
+    value: T,
+}
+
+pub fn headless_pair<T: Send, const N: usize>(
+) -> (Producer<LocalMode, T, N>, Consumer<LocalMode, T, N>) {
+    let (producer, [consumer]) = local_multi::<T, N, 1, Unstamped>();
     (producer, consumer)
 }
 
@@ -39,8 +229,41 @@ pub fn headless_multi<
     Producer<LocalMode, T, N>,
     [Consumer<LocalMode, T, N>; NUM_CONSUMERS],
 ) {
-    let arc_uninit = Arc::<Channel<LocalMode, T, N>>::new_uninit();
-    let ptr: *mut MaybeUninit<Channel<LocalMode, T, N>> =
+    local_multi()
+}
+
+/// Like [headless_pair], with every slot stamped.
+pub fn headless_stamped_pair<T: Send, const N: usize>() -> (
+    Producer<LocalMode, T, N, Stamped>,
+    Consumer<LocalMode, T, N, Stamped>,
+) {
+    let (producer, [consumer]) = local_multi::<T, N, 1, Stamped>();
+    (producer, consumer)
+}
+
+/// Like [headless_multi], with every slot stamped.
+pub fn headless_stamped_multi<
+    T: Send,
+    const N: usize,
+    const NUM_CONSUMERS: usize,
+>() -> LocalEndpoints<T, N, NUM_CONSUMERS, Stamped> {
+    local_multi()
+}
+
+type LocalEndpoints<T, const N: usize, const NUM_CONSUMERS: usize, S> = (
+    Producer<LocalMode, T, N, S>,
+    [Consumer<LocalMode, T, N, S>; NUM_CONSUMERS],
+);
+
+fn local_multi<
+    T: Send,
+    const N: usize,
+    const NUM_CONSUMERS: usize,
+    S: Stamping<T>,
+>() -> LocalEndpoints<T, N, NUM_CONSUMERS, S> {
+    let arc_uninit =
+        Arc::<Channel<LocalMode, S::Slot, N>>::new_uninit();
+    let ptr: *mut MaybeUninit<Channel<LocalMode, S::Slot, N>> =
         Arc::into_raw(arc_uninit).cast_mut();
 
     unsafe { *ptr = core::mem::zeroed() };
@@ -49,10 +272,10 @@ pub fn headless_multi<
     };
 
     let mut consumers = array::from_fn(|_| {
-        MaybeUninit::<Consumer<LocalMode, T, N>>::uninit()
+        MaybeUninit::<Consumer<LocalMode, T, N, S>>::uninit()
     });
-    for i in 0..NUM_CONSUMERS {
-        consumers[i].write(unsafe {
+    for (i, consumer) in consumers.iter_mut().enumerate() {
+        consumer.write(unsafe {
             Consumer::join_multi_(ptr.cast(), i, NUM_CONSUMERS).unwrap()
         });
     }
@@ -82,10 +305,11 @@ const fn burst_amount<const N: usize>() -> usize {
 // `lossless::loom_tests` for Loom coverage (`ShmemMode`, stack buffer).
 #[cfg(all(test, not(loom)))]
 mod tests {
+    use consumer::{Overrun, PopResult};
     use producer::Producer;
 
     use super::*;
-    use crate::LocalMode;
+    use crate::{error::QueError, FlushPolicy, LocalMode, ShmemMode};
 
     #[test]
     fn test_push_pop_multiple() {
@@ -117,6 +341,524 @@ mod tests {
         assert_eq!(consumer.pop(), Some(73));
     }
 
+    #[test]
+    fn test_flush_policy() {
+        let (producer, mut consumer) = headless_pair::<u64, 16>();
+        let mut producer =
+            producer.with_flush_policy(FlushPolicy::Immediate);
+        producer.push(69);
+        assert_eq!(consumer.pop(), Some(69));
+
+        // A burst (4 here) is always published before writing past it
+        producer.set_flush_policy(FlushPolicy::Manual);
+        for i in 0..5 {
+            producer.push(i);
+        }
+        assert_eq!(consumer.pop_with_seq(), Some((1, 0)));
+        for i in 1..4 {
+            assert_eq!(consumer.pop(), Some(i));
+        }
+        assert_eq!(consumer.pop(), None);
+
+        producer.set_flush_policy(FlushPolicy::EveryN(2));
+        producer.push(5);
+        assert_eq!(consumer.pop(), Some(4));
+        assert_eq!(consumer.pop(), Some(5));
+
+        // Anything unpublished is published on drop
+        producer.push(6);
+        drop(producer);
+        assert_eq!(consumer.pop(), Some(6));
+    }
+
+    #[test]
+    fn test_push_with_in_place() {
+        let (mut producer, mut consumer) =
+            headless_pair::<[u64; 4], 8>();
+        let (mut stamped_producer, mut stamped_consumer) =
+            headless_stamped_pair::<[u64; 4], 8>();
+
+        for i in 0..3 {
+            unsafe {
+                producer.push_with(|slot| {
+                    slot.write([i; 4]);
+                });
+                stamped_producer.push_with(|slot| {
+                    let words = slot.as_mut_ptr().cast::<u64>();
+                    for word in 0..4 {
+                        words.add(word).write(i);
+                    }
+                });
+            }
+        }
+        producer.sync();
+        stamped_producer.sync();
+        for i in 0..3 {
+            assert_eq!(consumer.pop(), Some([i; 4]));
+            assert_eq!(
+                stamped_consumer.pop_with_seq(),
+                Some((i, [i; 4]))
+            );
+        }
+    }
+
+    #[test]
+    fn test_reserve_commit() {
+        let (mut producer, mut consumer) = headless_pair::<u64, 16>();
+
+        // Reservations are bounded by the burst (4 here)
+        assert!(matches!(
+            producer.reserve(0),
+            Err(QueError::InvalidSize)
+        ));
+        assert!(matches!(
+            producer.reserve(5),
+            Err(QueError::InvalidSize)
+        ));
+
+        for i in 0..8 {
+            producer.push(i);
+        }
+        producer.sync();
+        for i in 0..8 {
+            assert_eq!(consumer.pop(), Some(i));
+        }
+
+        // Pending pushes are published if the reservation would exceed
+        // a burst
+        for i in 8..14 {
+            producer.push(i);
+        }
+        for i in 8..12 {
+            assert_eq!(consumer.pop(), Some(i));
+        }
+        assert_eq!(consumer.pop(), None);
+        let mut reservation = producer.reserve(4).unwrap();
+        for i in 12..14 {
+            assert_eq!(consumer.pop(), Some(i));
+        }
+
+        // Wraps around the end of the ring
+        reservation.write_all(&[14, 15, 16]);
+        assert_eq!(reservation.write_iter(&mut (17..20)), 1);
+        assert_eq!(reservation.remaining(), 0);
+        assert_eq!(consumer.pop(), None);
+        reservation.commit();
+        for i in 14..18 {
+            assert_eq!(consumer.pop(), Some(i));
+        }
+
+        // Only written values are published, and cancelling publishes
+        // nothing
+        let mut reservation = producer.reserve(4).unwrap();
+        reservation.write_next(18);
+        reservation.commit();
+        producer.reserve(2).unwrap().cancel();
+        assert_eq!(consumer.pop_with_seq(), Some((18, 18)));
+        assert_eq!(consumer.pop(), None);
+    }
+
+    #[test]
+    fn test_reserve_concurrent() {
+        let (mut producer, mut consumer) =
+            headless_stamped_pair::<[u64; 16], 64>();
+        const COUNT: u64 = 200_000;
+
+        let write = std::thread::spawn(move || {
+            let mut next = 0;
+            while next < COUNT {
+                let mut reservation = producer.reserve(16).unwrap();
+                let values: Vec<_> = (next..COUNT.min(next + 16))
+                    .map(|i| [i; 16])
+                    .collect();
+                reservation.write_all(&values);
+                reservation.commit();
+                next += values.len() as u64;
+            }
+        });
+
+        // Every element is either read whole and in order or lost
+        let mut values = [[0; 16]; 8];
+        let mut next = 0;
+        while next < COUNT {
+            let result = consumer.pop_batch(&mut values);
+            next += result.lost;
+            for value in &values[..result.read] {
+                assert_eq!(*value, [next; 16]);
+                next += 1;
+            }
+        }
+        write.join().unwrap();
+    }
+
+    #[test]
+    fn test_read_with() {
+        let (mut producer, mut consumer) = headless_pair::<u64, 4>();
+        assert_eq!(
+            consumer.read_with(|_| unreachable!()),
+            None::<Result<(), _>>
+        );
+
+        for i in 69..74 {
+            producer.push(i);
+        }
+        producer.sync();
+        assert_eq!(
+            consumer.read_with(|value| *value),
+            Some(Err(Overrun { lost: 2 }))
+        );
+        assert_eq!(
+            consumer.read_with(|value| *value + 1),
+            Some(Ok(72))
+        );
+        assert_eq!(consumer.pop(), Some(72));
+        assert_eq!(consumer.lost(), 2);
+    }
+
+    #[test]
+    fn test_read_with_overwritten_during_read() {
+        // The closure itself laps the consumer
+        let (mut producer, mut consumer) = headless_pair::<u64, 4>();
+        producer.push(0);
+        producer.sync();
+        let result = consumer.read_with(|value| {
+            for i in 1..6 {
+                producer.push(i);
+            }
+            producer.sync();
+            *value
+        });
+        assert_eq!(result, Some(Err(Overrun { lost: 3 })));
+        assert_eq!(consumer.pop(), Some(3));
+
+        let (mut producer, mut consumer) =
+            headless_stamped_pair::<u64, 4>();
+        producer.push(0);
+        producer.sync();
+        let result = consumer.read_with(|value| {
+            // Not yet published, but the slot is already overwritten
+            for i in 1..5 {
+                producer.push(i);
+            }
+            *value
+        });
+        // Elements the producer may overwrite next are skipped too
+        assert_eq!(result, Some(Err(Overrun { lost: 2 })));
+        assert_eq!(consumer.pop_with_seq(), Some((2, 2)));
+    }
+
+    #[test]
+    fn test_pop_with_seq() {
+        let (mut producer, [mut consumer1, mut consumer2]) =
+            headless_multi::<u64, 4, 2>();
+        assert_eq!(producer.next_seq(), 0);
+
+        for i in 69..74 {
+            producer.push(i);
+        }
+        assert_eq!(producer.next_seq(), 5);
+        producer.sync();
+
+        // Overrun and the other consumer's elements show up as gaps
+        assert_eq!(consumer1.pop_with_seq(), Some((2, 71)));
+        assert_eq!(consumer1.pop_with_seq(), Some((4, 73)));
+        assert_eq!(consumer1.pop_with_seq(), None);
+        assert_eq!(consumer2.pop_with_seq(), Some((3, 72)));
+        assert_eq!(consumer2.pop_with_seq(), None);
+    }
+
+    #[test]
+    fn test_pop_with_gap() {
+        let (mut producer, mut consumer) = headless_pair::<u64, 4>();
+
+        for i in 69..74 {
+            producer.push(i);
+        }
+        producer.sync();
+
+        assert_eq!(consumer.pop_with_gap(), Some((71, 2)));
+        assert_eq!(consumer.pop_with_gap(), Some((72, 0)));
+        assert_eq!(consumer.lost(), 2);
+
+        // The gap is reported with the first element after it
+        for i in 74..80 {
+            producer.push(i);
+        }
+        producer.sync();
+        assert_eq!(consumer.pop_with_gap(), Some((77, 4)));
+        assert_eq!(consumer.pop(), Some(78));
+        assert_eq!(consumer.pop_with_gap(), Some((79, 0)));
+        assert_eq!(consumer.pop_with_gap(), None);
+        assert_eq!(consumer.lost(), 6);
+    }
+
+    #[test]
+    fn test_on_overrun() {
+        let (mut producer, [mut consumer1, mut consumer2]) =
+            headless_multi::<u64, 4, 2>();
+        let reported = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let hook_reported = reported.clone();
+        consumer1.on_overrun(move |lost| {
+            hook_reported.lock().unwrap().push(lost)
+        });
+
+        for i in 69..74 {
+            producer.push(i);
+        }
+        producer.sync();
+        assert_eq!(consumer1.pop(), Some(71));
+        assert_eq!(consumer1.pop(), Some(73));
+        assert_eq!(consumer1.pop(), None);
+        assert_eq!(*reported.lock().unwrap(), [1]);
+
+        // Each consumer counts only its own elements
+        assert_eq!(consumer2.pop_with_gap(), Some((72, 1)));
+        assert_eq!(consumer2.lost(), 1);
+
+        // Losses found by batch reads are reported too
+        for i in 74..84 {
+            producer.push(i);
+        }
+        producer.sync();
+        let mut values = [0; 4];
+        assert_eq!(
+            consumer1.pop_batch(&mut values),
+            PopResult { read: 2, lost: 3 }
+        );
+        assert_eq!(values[..2], [81, 83]);
+        assert_eq!(*reported.lock().unwrap(), [1, 3]);
+        assert_eq!(consumer1.lost(), 4);
+    }
+
+    #[test]
+    fn test_pop_batch() {
+        let (mut producer, mut consumer) = headless_pair::<u64, 8>();
+        let mut values = [0; 8];
+
+        for i in 0..6 {
+            producer.push(i);
+        }
+        producer.sync();
+        assert_eq!(
+            consumer.pop_batch(&mut values),
+            PopResult { read: 6, lost: 0 }
+        );
+        assert_eq!(values[..6], [0, 1, 2, 3, 4, 5]);
+
+        // Wrap around the end of the ring, limited by the output
+        for i in 6..12 {
+            producer.push(i);
+        }
+        producer.sync();
+        assert_eq!(
+            consumer.pop_batch(&mut values[..4]),
+            PopResult { read: 4, lost: 0 }
+        );
+        assert_eq!(values[..4], [6, 7, 8, 9]);
+        assert_eq!(consumer.pop_batch(&mut values).read, 2);
+        assert_eq!(values[..2], [10, 11]);
+        assert_eq!(
+            consumer.pop_batch(&mut values),
+            PopResult::default()
+        );
+    }
+
+    #[test]
+    fn test_pop_batch_overrun() {
+        let (mut producer, mut consumer) = headless_pair::<u64, 4>();
+        let mut values = [0; 4];
+
+        for i in 69..74 {
+            producer.push(i);
+        }
+        producer.sync();
+
+        // since burst_amount here is 1, we will only read last 3
+        assert_eq!(
+            consumer.pop_batch(&mut values),
+            PopResult { read: 3, lost: 2 }
+        );
+        assert_eq!(values[..3], [71, 72, 73]);
+    }
+
+    #[test]
+    fn test_pop_batch_concurrent() {
+        let (mut producer, mut consumer) = headless_pair::<u64, 64>();
+        const COUNT: u64 = 1_000_000;
+
+        let write = std::thread::spawn(move || {
+            for i in 0..COUNT {
+                producer.push(i);
+            }
+            producer.sync();
+        });
+
+        // Every element is either read in order or counted as lost
+        let mut values = [0; 16];
+        let mut next = 0;
+        while next < COUNT {
+            let result = consumer.pop_batch(&mut values);
+            next += result.lost;
+            for &value in &values[..result.read] {
+                assert_eq!(value, next);
+                next += 1;
+            }
+        }
+        write.join().unwrap();
+    }
+
+    #[test]
+    fn test_multi_consumer_pop_batch() {
+        let (mut producer, [mut consumer1, mut consumer2]) =
+            headless_multi::<u64, 16, 2>();
+        let mut values = [0; 8];
+
+        for i in 0..10 {
+            producer.push(i);
+        }
+        producer.sync();
+
+        assert_eq!(consumer1.pop_batch(&mut values).read, 5);
+        assert_eq!(values[..5], [0, 2, 4, 6, 8]);
+        assert_eq!(
+            consumer2
+                .pop_batch(&mut values[..2])
+                .read,
+            2
+        );
+        assert_eq!(values[..2], [1, 3]);
+        assert_eq!(consumer2.pop_batch(&mut values).read, 3);
+        assert_eq!(values[..3], [5, 7, 9]);
+
+        // Each consumer only counts its own lost elements
+        let (mut producer, [mut consumer1, mut consumer2]) =
+            headless_multi::<u64, 4, 2>();
+        for i in 69..74 {
+            producer.push(i);
+        }
+        producer.sync();
+        assert_eq!(
+            consumer1.pop_batch(&mut values),
+            PopResult { read: 2, lost: 1 }
+        );
+        assert_eq!(values[..2], [71, 73]);
+        assert_eq!(
+            consumer2.pop_batch(&mut values),
+            PopResult { read: 1, lost: 1 }
+        );
+        assert_eq!(values[0], 72);
+    }
+
+    #[test]
+    fn test_stamped_slot() {
+        let mut slot = StampedSlot {
+            stamp: AtomicUsize::new(0),
+            value: 0_u64,
+        };
+        unsafe {
+            // Never written
+            assert_eq!(Stamped::read(&slot, 3), Err(0));
+
+            Stamped::write(&mut slot, 3, 69);
+            assert_eq!(Stamped::read(&slot, 3), Ok(69));
+
+            // Overwritten by a later lap, or in the middle of it
+            Stamped::write(&mut slot, 7, 70);
+            assert_eq!(Stamped::read(&slot, 3), Err(8));
+            slot.stamp
+                .store(2 * 11 + 1, Ordering::Relaxed);
+            assert_eq!(Stamped::read(&slot, 7), Err(12));
+        }
+    }
+
+    #[test]
+    fn test_stamped_push_pop() {
+        let (mut producer, [mut consumer1, mut consumer2]) =
+            headless_stamped_multi::<u64, 4, 2>();
+
+        for i in 69..74 {
+            producer.push(i);
+        }
+        producer.sync();
+
+        assert_eq!(consumer1.pop_with_seq(), Some((2, 71)));
+        assert_eq!(consumer1.pop_with_gap(), Some((73, 0)));
+        assert_eq!(consumer1.lost(), 1);
+        let mut values = [0; 4];
+        assert_eq!(
+            consumer2.pop_batch(&mut values),
+            PopResult { read: 1, lost: 1 }
+        );
+        assert_eq!(values[0], 72);
+    }
+
+    #[test]
+    fn test_stamped_recorded_in_header() {
+        #[repr(C, align(128))]
+        struct Aligned([u8; 16384]);
+        let mut buffer = Box::new(Aligned([0; 16384]));
+        let ptr = buffer.0.as_mut_ptr();
+
+        let mut producer = unsafe {
+            Producer::<ShmemMode, u64, 16, Stamped>::join_or_initialize_in(
+                ptr,
+            )
+            .unwrap()
+        };
+        producer.push(69);
+        producer.sync();
+
+        // The layout is fixed by whoever created the channel
+        assert!(matches!(
+            unsafe { Consumer::<ShmemMode, u64, 16>::join(ptr) },
+            Err(QueError::CorruptionDetected)
+        ));
+        drop(producer);
+        assert!(matches!(
+            unsafe { Producer::<ShmemMode, u64, 16>::join(ptr) },
+            Err(QueError::CorruptionDetected)
+        ));
+
+        let mut producer = unsafe {
+            Producer::<ShmemMode, u64, 16, Stamped>::join(ptr).unwrap()
+        };
+        let mut consumer = unsafe {
+            Consumer::<ShmemMode, u64, 16, Stamped>::join(ptr).unwrap()
+        };
+        producer.push(70);
+        producer.sync();
+        assert_eq!(consumer.pop_with_seq(), Some((1, 70)));
+    }
+
+    #[test]
+    fn test_stamped_large_elements_never_torn() {
+        const COUNT: u64 = 200_000;
+        let (mut producer, mut consumer) =
+            headless_stamped_pair::<[u64; 154], 8>();
+
+        let write = std::thread::spawn(move || {
+            for i in 0..COUNT {
+                producer.push([i; 154]);
+            }
+            producer.sync();
+        });
+
+        // Every element read must be a whole one, in order
+        let mut next = 0;
+        let mut lost = 0;
+        while next < COUNT {
+            let Some((seq, value)) = consumer.pop_with_seq() else {
+                continue;
+            };
+            assert!(value.iter().all(|&word| word == seq));
+            assert!(seq >= next);
+            lost += seq - next;
+            next = seq + 1;
+        }
+        write.join().unwrap();
+        assert_eq!(consumer.lost(), lost);
+    }
+
     #[test]
     fn test_multi_consumer_sequential_reads() {
         let (mut producer, [mut consumer1, mut consumer2]) =
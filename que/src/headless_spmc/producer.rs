@@ -1,68 +1,140 @@
 use crate::atomic_compat::Ordering;
-use std::{ptr::NonNull, sync::Arc};
+use std::{mem::MaybeUninit, ptr::NonNull, sync::Arc};
 
-use bytemuck::AnyBitPattern;
+use bytemuck::{AnyBitPattern, Pod};
 
 use crate::{
-    error::QueError, headless_spmc::MAGIC, page_size::PageSize,
-    shmem::Shmem, ChannelMode, ShmemMode,
+    error::QueError,
+    flush::Flusher,
+    shmem::{Shmem, ShmemConfig},
+    ChannelMode, FlushPolicy, Liveness, Metadata, ShmemMode,
 };
 
-use super::{burst_amount, Channel};
+use super::{burst_amount, Channel, Stamping, Unstamped};
 
 #[repr(C, align(128))]
-pub struct Producer<M: ChannelMode<T>, T, const N: usize> {
-    spsc: NonNull<Channel<M, T, N>>,
+pub struct Producer<
+    M: ChannelMode<T>,
+    T,
+    const N: usize,
+    S: Stamping<T> = Unstamped,
+> {
+    spsc: NonNull<Channel<M, S::Slot, N>>,
     tail: usize,
     /// Number of elements written since last sync
     written: usize,
     last_consumer_heartbeat: usize,
+    flusher: Flusher,
+    /// Mapping backing the channel, if joined through shmem.
+    shmem: Option<Shmem>,
 }
 
-unsafe impl<M: ChannelMode<T>, T, const N: usize> Send
-    for Producer<M, T, N>
+unsafe impl<M: ChannelMode<T>, T, const N: usize, S: Stamping<T>> Send
+    for Producer<M, T, N, S>
 {
 }
 
-impl<T: AnyBitPattern, const N: usize> Producer<ShmemMode, T, N> {
+impl<T: AnyBitPattern, const N: usize, S: Stamping<T>>
+    Producer<ShmemMode, T, N, S>
+{
     /// Joins or creates a channel backed by shared memory as a
     /// producer.
+    ///
+    /// # Safety
+    /// The shared memory region must only ever be mapped as a
+    /// `Channel` with the same `T` and `N`.
     pub unsafe fn join_or_create_shmem(
         shmem_id: &str,
-        #[cfg(target_os = "linux")] page_size: PageSize,
-    ) -> Result<Producer<ShmemMode, T, N>, QueError> {
+        #[cfg(target_os = "linux")] config: impl Into<ShmemConfig>,
+    ) -> Result<Producer<ShmemMode, T, N, S>, QueError> {
+        Self::join_or_create_shmem_with_type_hash(
+            shmem_id,
+            0,
+            #[cfg(target_os = "linux")]
+            config,
+        )
+    }
+
+    /// Creates a channel backed by shared memory and joins it as the
+    /// producer. Fails with
+    /// [ShmemError::AlreadyExists](crate::shmem::ShmemError::AlreadyExists)
+    /// if the shmem exists.
+    ///
+    /// # Safety
+    /// The shared memory region must only ever be mapped as a
+    /// `Channel` with the same `T` and `N`.
+    pub unsafe fn create_shmem(
+        shmem_id: &str,
+        #[cfg(target_os = "linux")] config: impl Into<ShmemConfig>,
+    ) -> Result<Producer<ShmemMode, T, N, S>, QueError> {
+        #[cfg(not(target_os = "linux"))]
+        let config = ShmemConfig::default();
+        #[cfg(target_os = "linux")]
+        let config: ShmemConfig = config.into();
+
+        let buffer_size: i64 = config
+            .page_size()
+            .mem_size(core::mem::size_of::<
+                Channel<ShmemMode, S::Slot, N>,
+            >())
+            .try_into()
+            .map_err(|_| QueError::InvalidSize)?;
+        let shmem = config.create_exclusive(shmem_id, buffer_size)?;
+
+        let mut producer =
+            Self::join_or_initialize_in_(shmem.get_mut_ptr())?;
+        producer.shmem = Some(shmem);
+
+        Ok(producer)
+    }
+
+    /// Like [Producer::join_or_create_shmem], additionally recording
+    /// `type_hash` when creating the channel and checking it when
+    /// joining. A hash of 0 is not checked.
+    ///
+    /// # Safety
+    /// The shared memory region must only ever be mapped as a
+    /// `Channel` with the same `T` and `N`.
+    pub unsafe fn join_or_create_shmem_with_type_hash(
+        shmem_id: &str,
+        type_hash: u64,
+        #[cfg(target_os = "linux")] config: impl Into<ShmemConfig>,
+    ) -> Result<Producer<ShmemMode, T, N, S>, QueError> {
         #[cfg(not(target_os = "linux"))]
-        let page_size = PageSize::Standard;
+        let config = ShmemConfig::default();
+        #[cfg(target_os = "linux")]
+        let config: ShmemConfig = config.into();
 
         // Calculate buffer size.
         // If using huge pages, we must uplign to page size.
-        let buffer_size: i64 = page_size
-            .mem_size(core::mem::size_of::<Channel<ShmemMode, T, N>>())
+        let buffer_size: i64 = config
+            .page_size()
+            .mem_size(core::mem::size_of::<
+                Channel<ShmemMode, S::Slot, N>,
+            >())
             .try_into()
             .map_err(|_| QueError::InvalidSize)?;
 
         // Open or create shmem
-        let shmem = Shmem::open_or_create(
-            shmem_id,
-            buffer_size,
-            #[cfg(target_os = "linux")]
-            page_size,
-        )?;
+        let shmem = config.open_or_create(shmem_id, buffer_size)?;
 
         // Zerocopy deserialize the SPSC
-        let spsc: *mut Channel<ShmemMode, T, N> =
+        let spsc: *mut Channel<ShmemMode, S::Slot, N> =
             shmem.get_mut_ptr().cast();
 
         // Check magic
         let magic = (*spsc).magic.load(Ordering::Acquire);
         let capacity = (*spsc).capacity.load(Ordering::Acquire);
         #[rustfmt::skip]
-        return if magic == MAGIC {
+        return if magic == S::MAGIC {
             // Check capacity
             if capacity != N {
                 return Err(QueError::IncorrectCapacity(capacity));
             }
 
+            (*spsc).check_fingerprint(type_hash)?;
+            (*spsc).owners.producer.claim()?;
+
             (*spsc)
                 .producer_heartbeat
                 .fetch_add(1, Ordering::Release);
@@ -70,22 +142,28 @@ impl<T: AnyBitPattern, const N: usize> Producer<ShmemMode, T, N> {
             // Successful join if magic and capacity is correct
             Ok(Producer {
                 spsc: NonNull::new(shmem.get_mut_ptr().cast()).unwrap(),
+                shmem: Some(shmem),
                 tail: (*spsc).tail.load(Ordering::Acquire),
                 written: 0,
+                flusher: Flusher::default(),
                 last_consumer_heartbeat: (*spsc).consumer_heartbeat
                     .load(Ordering::Acquire),
             })
         } else if magic == 0 {
+            (*spsc).owners.producer.claim()?;
             (*spsc).tail.store(0, Ordering::Release);
             (*spsc).producer_heartbeat.store(0, Ordering::Release);
             (*spsc).consumer_heartbeat.store(0, Ordering::Release);
             (*spsc).capacity.store(N, Ordering::Release);
-            (*spsc).magic.store(MAGIC, Ordering::Release);
+            (*spsc).store_fingerprint(type_hash);
+            (*spsc).magic.store(S::MAGIC, Ordering::Release);
 
             Ok(Producer {
                 spsc: NonNull::new(shmem.get_mut_ptr().cast()).unwrap(),
+                shmem: Some(shmem),
                 tail: 0,
                 written: 0,
+                flusher: Flusher::default(),
                 last_consumer_heartbeat: (*spsc)
                     .consumer_heartbeat
                     .load(Ordering::Acquire),
@@ -96,53 +174,127 @@ impl<T: AnyBitPattern, const N: usize> Producer<ShmemMode, T, N> {
         };
     }
 
+    /// Like [Producer::join_or_create_shmem], but first reclaims the
+    /// channel if the process recorded as its producer has died. Fails
+    /// with [QueError::OwnerAlive] if it is still running.
+    ///
+    /// # Safety
+    /// The shared memory region must only ever be mapped as a
+    /// `Channel` with the same `T` and `N`.
+    pub unsafe fn take_over_shmem(
+        shmem_id: &str,
+        #[cfg(target_os = "linux")] config: impl Into<ShmemConfig>,
+    ) -> Result<Producer<ShmemMode, T, N, S>, QueError> {
+        #[cfg(not(target_os = "linux"))]
+        let config = ShmemConfig::default();
+        #[cfg(target_os = "linux")]
+        let config: ShmemConfig = config.into();
+
+        let buffer_size: i64 = config
+            .page_size()
+            .mem_size(core::mem::size_of::<
+                Channel<ShmemMode, S::Slot, N>,
+            >())
+            .try_into()
+            .map_err(|_| QueError::InvalidSize)?;
+        let shmem = config.open_or_create(shmem_id, buffer_size)?;
+
+        let spsc: *mut Channel<ShmemMode, S::Slot, N> =
+            shmem.get_mut_ptr().cast();
+        (*spsc).owners.producer.evict_dead()?;
+
+        let mut producer =
+            Self::join_or_initialize_in_(shmem.get_mut_ptr())?;
+        producer.shmem = Some(shmem);
+
+        Ok(producer)
+    }
+
     /// Initializes a channel backed by `buffer` and joins as a
     /// producer.
     ///
-    /// SAFETY:
+    /// # Safety
     /// This must point to a buffer of proper size and alignment.
     ///
     /// In LocalMode, must point to a region allocated by an Arc with the strong count not yet incremented!
     pub unsafe fn join_or_initialize_in(
         buffer: *mut u8,
-    ) -> Result<Producer<ShmemMode, T, N>, QueError> {
+    ) -> Result<Producer<ShmemMode, T, N, S>, QueError> {
         Self::join_or_initialize_in_(buffer)
     }
 
     /// Joins an existing channel backed by `buffer` as a producer.
     ///
-    /// SAFETY:
+    /// # Safety
     /// This must point to a buffer of proper size and alignment.
     ///
     /// In LocalMode, must point to a region allocated by an Arc with the strong count not yet incremented!
     pub unsafe fn join(
         buffer: *mut u8,
-    ) -> Result<Producer<ShmemMode, T, N>, QueError> {
+    ) -> Result<Producer<ShmemMode, T, N, S>, QueError> {
         Self::join_(buffer)
     }
+
+    /// Initializes a channel in `shmem`, e.g. one created with
+    /// [ShmemConfig::create_anonymous] or received with
+    /// [Shmem::recv], or joins it as a producer if it is initialized.
+    /// The endpoint owns the mapping.
+    ///
+    /// # Safety
+    /// The shared memory region must only ever be mapped as a
+    /// `Channel` with the same `T` and `N`.
+    pub unsafe fn from_shmem(
+        shmem: Shmem,
+    ) -> Result<Producer<ShmemMode, T, N, S>, QueError> {
+        if (shmem.size as usize)
+            < core::mem::size_of::<Channel<ShmemMode, S::Slot, N>>()
+        {
+            return Err(QueError::InvalidSize);
+        }
+
+        let mut producer =
+            Self::join_or_initialize_in_(shmem.get_mut_ptr())?;
+        producer.shmem = Some(shmem);
+
+        Ok(producer)
+    }
+
+    /// Unlinks the shared memory object when this endpoint is dropped,
+    /// if it created the object. Has no effect for endpoints joined
+    /// to an existing object or to a caller-provided buffer.
+    pub fn set_unlink_on_drop(&mut self, unlink: bool) {
+        if let Some(shmem) = self.shmem.as_mut() {
+            shmem.set_unlink_on_drop(unlink);
+        }
+    }
 }
 
-impl<M: ChannelMode<T>, T, const N: usize> Producer<M, T, N> {
+impl<M: ChannelMode<T>, T, const N: usize, S: Stamping<T>>
+    Producer<M, T, N, S>
+{
     pub const MODULO_MASK: usize = N - 1;
     pub(crate) unsafe fn join_(
         buffer: *mut u8,
-    ) -> Result<Producer<M, T, N>, QueError> {
+    ) -> Result<Producer<M, T, N, S>, QueError> {
         assert!(
             N > 0 && N.is_power_of_two(),
             "Capacity must be a power of two"
         );
-        assert!(buffer as usize % 128 == 0, "unaligned");
+        assert!((buffer as usize).is_multiple_of(128), "unaligned");
 
         // Zerocopy deserialize the SPSC
-        let spsc: *mut Channel<M, T, N> = buffer.cast();
+        let spsc: *mut Channel<M, S::Slot, N> = buffer.cast();
 
         let magic = (*spsc).magic.load(Ordering::Acquire);
         let capacity = (*spsc).capacity.load(Ordering::Acquire);
-        if magic == MAGIC {
+        if magic == S::MAGIC {
             if capacity != N {
                 return Err(QueError::IncorrectCapacity(capacity));
             }
 
+            (*spsc).check_fingerprint(0)?;
+            (*spsc).owners.producer.claim()?;
+
             if M::BACKED_BY_ARCC {
                 unsafe {
                     Arc::increment_strong_count(spsc);
@@ -152,8 +304,10 @@ impl<M: ChannelMode<T>, T, const N: usize> Producer<M, T, N> {
             // Successful join if magic and capacity is correct
             Ok(Producer {
                 spsc: NonNull::new(buffer.cast()).unwrap(),
+                shmem: None,
                 tail: (*spsc).tail.load(Ordering::Acquire),
                 written: 0,
+                flusher: Flusher::default(),
                 last_consumer_heartbeat: (*spsc)
                     .consumer_heartbeat
                     .load(Ordering::Acquire),
@@ -170,25 +324,28 @@ impl<M: ChannelMode<T>, T, const N: usize> Producer<M, T, N> {
 
     pub(crate) unsafe fn join_or_initialize_in_(
         buffer: *mut u8,
-    ) -> Result<Producer<M, T, N>, QueError> {
+    ) -> Result<Producer<M, T, N, S>, QueError> {
         assert!(
             N > 0 && N.is_power_of_two(),
             "Capacity must be a power of two"
         );
-        assert!(buffer as usize % 128 == 0, "unaligned");
+        assert!((buffer as usize).is_multiple_of(128), "unaligned");
 
-        let spsc: *mut Channel<M, T, N> = buffer.cast();
+        let spsc: *mut Channel<M, S::Slot, N> = buffer.cast();
 
         // Check magic
         let magic = (*spsc).magic.load(Ordering::Acquire);
         let capacity = (*spsc).capacity.load(Ordering::Acquire);
         #[rustfmt::skip]
-        return if magic == MAGIC {
+        return if magic == S::MAGIC {
             // Check capacity
             if capacity != N {
                 return Err(QueError::IncorrectCapacity(capacity));
             }
 
+            (*spsc).check_fingerprint(0)?;
+            (*spsc).owners.producer.claim()?;
+
             (*spsc)
                 .producer_heartbeat
                 .fetch_add(1, Ordering::Release);
@@ -203,13 +360,16 @@ impl<M: ChannelMode<T>, T, const N: usize> Producer<M, T, N> {
             // Successful join if magic and capacity is correct
             Ok(Producer {
                 spsc: NonNull::new(buffer.cast()).unwrap(),
+                shmem: None,
                 tail: (*spsc).tail.load(Ordering::Acquire),
                 written: 0,
+                flusher: Flusher::default(),
                 last_consumer_heartbeat: (*spsc)
                     .consumer_heartbeat
                     .load(Ordering::Acquire),
             })
         } else if magic == 0 {
+            (*spsc).owners.producer.claim()?;
             // When we initialize we must write this before a consumer joins
             // (for a consumer to join the capacity/magic must be written)
             (*spsc).magic.store(0, Ordering::Release);
@@ -217,7 +377,8 @@ impl<M: ChannelMode<T>, T, const N: usize> Producer<M, T, N> {
             (*spsc).producer_heartbeat.store(0, Ordering::Release);
             (*spsc).consumer_heartbeat.store(0, Ordering::Release);
             (*spsc).capacity.store(N, Ordering::Release);
-            (*spsc).magic.store(MAGIC, Ordering::Release);
+            (*spsc).store_fingerprint(0);
+            (*spsc).magic.store(S::MAGIC, Ordering::Release);
 
             if M::BACKED_BY_ARCC {
                 unsafe {
@@ -227,8 +388,10 @@ impl<M: ChannelMode<T>, T, const N: usize> Producer<M, T, N> {
 
             Ok(Producer {
                 spsc: NonNull::new(buffer.cast()).unwrap(),
+                shmem: None,
                 tail: 0,
                 written: 0,
+                flusher: Flusher::default(),
                 last_consumer_heartbeat: (*spsc)
                     .consumer_heartbeat
                     .load(Ordering::Acquire),
@@ -240,20 +403,47 @@ impl<M: ChannelMode<T>, T, const N: usize> Producer<M, T, N> {
     }
 
     /// Synchronizes the local tail with the atomic tail in the channel,
-    /// publishing newly written values.
+    /// publishing newly written values and waking parked consumers.
     #[inline(always)]
     pub fn sync(&mut self) {
         self.written = 0;
+        self.flusher.flushed();
         unsafe {
-            (*self.spsc.as_ptr())
-                .tail
-                .store(self.tail, Ordering::Release)
+            let spsc = &*self.spsc.as_ptr();
+            spsc.tail
+                .store(self.tail, Ordering::Release);
+            spsc.consumer_notify.notify();
         }
     }
 
     /// Write a new element to the channel.
     #[inline(always)]
     pub fn push(&mut self, value: T) {
+        self.push_slot(|slot, position| unsafe {
+            S::write(slot, position, value)
+        });
+    }
+
+    /// Constructs a new element in place: `init` is called on its slot
+    /// in the ring, so large elements are not built elsewhere and
+    /// copied in.
+    ///
+    /// # Safety
+    /// `init` must initialize the slot.
+    #[inline(always)]
+    pub unsafe fn push_with(
+        &mut self,
+        init: impl FnOnce(&mut MaybeUninit<T>),
+    ) {
+        self.push_slot(|slot, position| unsafe {
+            S::write_with(slot, position, init)
+        });
+    }
+
+    /// Writes the slot at the local tail with `write`, which is passed
+    /// the slot and its position.
+    #[inline(always)]
+    fn push_slot(&mut self, write: impl FnOnce(*mut S::Slot, usize)) {
         // Update tail if we've written past burst amount and haven't
         // updated shared atomic.
         if self.written == burst_amount::<N>() {
@@ -262,16 +452,60 @@ impl<M: ChannelMode<T>, T, const N: usize> Producer<M, T, N> {
 
         // Write value
         let index = self.tail & Self::MODULO_MASK;
-        unsafe {
-            *(*self.spsc.as_ptr())
+        let slot = unsafe {
+            (*self.spsc.as_ptr())
                 .buffer
                 .as_mut_ptr()
-                .add(index) = value;
+                .add(index)
         };
+        write(slot, self.tail);
 
         // Increment tail and written counter
         self.tail += 1;
         self.written += 1;
+
+        if self.flusher.on_write(self.written) {
+            self.sync();
+        }
+    }
+
+    /// Sets when pushed elements are published before a burst has
+    /// been written, for use right after joining. Defaults to
+    /// [FlushPolicy::Manual].
+    ///
+    /// Whatever the policy, [Producer::push] publishes before writing
+    /// past a burst of unpublished elements, which consumers rely on
+    /// to detect overrun.
+    pub fn with_flush_policy(mut self, policy: FlushPolicy) -> Self {
+        self.set_flush_policy(policy);
+        self
+    }
+
+    /// Changes the flush policy, see [Producer::with_flush_policy].
+    pub fn set_flush_policy(&mut self, policy: FlushPolicy) {
+        self.flusher = Flusher::new(policy);
+    }
+
+    /// Publishes pending elements if the flush policy says they are
+    /// due, e.g. once a [FlushPolicy::Linger] has elapsed. Returns
+    /// whether it published.
+    pub fn sync_if_due(&mut self) -> bool {
+        let due = self.flusher.is_due(self.written);
+        if due {
+            self.sync();
+        }
+
+        due
+    }
+
+    /// Returns the sequence number the next pushed element will get.
+    ///
+    /// Sequence numbers count every element pushed to the channel and
+    /// match those returned by [Consumer::pop_with_seq].
+    ///
+    /// [Consumer::pop_with_seq]: super::consumer::Consumer::pop_with_seq
+    pub fn next_seq(&self) -> u64 {
+        self.tail as u64
     }
 
     /// Increments the producer heartbeat.
@@ -306,11 +540,18 @@ impl<M: ChannelMode<T>, T, const N: usize> Producer<M, T, N> {
         }
     }
 
+    /// Checks whether the processes holding either end of the channel
+    /// are still running, see [Channel::inspect_liveness].
+    pub fn inspect_liveness(&self) -> Liveness {
+        unsafe { (*self.spsc.as_ptr()).inspect_liveness() }
+    }
+
     /// Returns pointer to inner padding.
     ///
     /// User is responsible for safe usage.
     ///
-    /// Can be used to store metadata (e.g. hash seed).
+    /// Unsynchronized: prefer [metadata](Self::metadata) for values
+    /// that change while the channel is in use.
     ///
     /// Byte array is 128 byte aligned.
     pub fn get_padding_ptr(&self) -> NonNull<[u8; 112]> {
@@ -321,10 +562,242 @@ impl<M: ChannelMode<T>, T, const N: usize> Producer<M, T, N> {
             .cast()
         }
     }
+
+    /// Returns a typed, versioned view of the metadata region (the
+    /// same bytes as [get_padding_ptr](Self::get_padding_ptr)).
+    ///
+    /// Fails to compile if `U` is larger than 112 bytes.
+    pub fn metadata<U: Pod>(&self) -> Metadata<'_, U> {
+        unsafe { Channel::metadata(self.spsc) }
+    }
+
+    /// Reserves `count` slots to write a burst of elements into, which
+    /// are published together by [Reservation::commit].
+    ///
+    /// Consumers detect overrun assuming the producer never has more
+    /// than a burst of unpublished elements, so `count` is at most
+    /// `N / 4` (or 1 for tiny channels) and elements pushed before are
+    /// published first if the reservation would exceed that. Fails with
+    /// [QueError::InvalidSize] for an empty or larger reservation.
+    #[inline(always)]
+    pub fn reserve(
+        &mut self,
+        count: usize,
+    ) -> Result<Reservation<'_, M, T, N, S>, QueError> {
+        if count == 0 || count > burst_amount::<N>() {
+            return Err(QueError::InvalidSize);
+        }
+
+        if self.written + count > burst_amount::<N>() {
+            self.sync();
+        }
+
+        Ok(Reservation {
+            start_tail: self.tail,
+            producer: self,
+            count,
+            written: 0,
+        })
+    }
 }
 
-impl<M: ChannelMode<T>, T, const N: usize> Drop for Producer<M, T, N> {
+/// Slots reserved in a headless channel, see [Producer::reserve].
+pub struct Reservation<
+    'a,
+    M: ChannelMode<T>,
+    T,
+    const N: usize,
+    S: Stamping<T> = Unstamped,
+> {
+    producer: &'a mut Producer<M, T, N, S>,
+    start_tail: usize,
+    count: usize,
+    written: usize,
+}
+
+impl<'a, M: ChannelMode<T>, T, const N: usize, S: Stamping<T>>
+    Reservation<'a, M, T, N, S>
+{
+    /// Returns the number of slots reserved
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Returns `true` if no slots were reserved
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Returns the number of values written so far
+    #[inline(always)]
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
+    /// Returns the number of slots remaining to be written
+    #[inline(always)]
+    pub fn remaining(&self) -> usize {
+        self.count - self.written
+    }
+
+    /// Write the next value in sequence
+    ///
+    /// # Panics
+    /// Panics if all reserved slots have been written
+    #[inline(always)]
+    pub fn write_next(&mut self, value: T) {
+        let (slot, position) = self.next_slot();
+        unsafe { S::write(slot, position, value) };
+        self.written += 1;
+    }
+
+    /// Constructs the next value in sequence in place, calling `init`
+    /// on its slot in the ring.
+    ///
+    /// # Safety
+    /// `init` must initialize the slot.
+    ///
+    /// # Panics
+    /// Panics if all reserved slots have been written
+    #[inline(always)]
+    pub unsafe fn write_with(
+        &mut self,
+        init: impl FnOnce(&mut MaybeUninit<T>),
+    ) {
+        let (slot, position) = self.next_slot();
+        S::write_with(slot, position, init);
+        self.written += 1;
+    }
+
+    /// Write all values from a slice
+    ///
+    /// # Panics
+    /// Panics if the slice is larger than the remaining space in the
+    /// reservation
+    #[inline(always)]
+    pub fn write_all(&mut self, values: &[T])
+    where
+        T: Copy,
+    {
+        let remaining = self.remaining();
+        if values.len() > remaining {
+            panic!(
+                "Attempted to write {} values with only {} slots remaining",
+                values.len(),
+                remaining
+            );
+        }
+
+        if S::VERIFIED {
+            // Every slot is stamped individually
+            for &value in values {
+                self.write_next(value);
+            }
+            return;
+        }
+
+        let start_index = (self.start_tail + self.written)
+            & Producer::<M, T, N, S>::MODULO_MASK;
+        let first_len = values.len().min(N - start_index);
+        unsafe {
+            // Unstamped slots are bare elements
+            let buffer_ptr = (*self.producer.spsc.as_ptr())
+                .buffer
+                .as_mut_ptr()
+                .cast::<T>();
+
+            // At most two contiguous runs
+            core::ptr::copy_nonoverlapping(
+                values.as_ptr(),
+                buffer_ptr.add(start_index),
+                first_len,
+            );
+            core::ptr::copy_nonoverlapping(
+                values.as_ptr().add(first_len),
+                buffer_ptr,
+                values.len() - first_len,
+            );
+        }
+
+        self.written += values.len();
+    }
+
+    /// Write values from an iterator
+    ///
+    /// Stops when the reservation is full or the iterator is exhausted.
+    /// Returns the number of values written.
+    #[inline(always)]
+    pub fn write_iter<I>(&mut self, iter: &mut I) -> usize
+    where
+        I: Iterator<Item = T>,
+    {
+        let start_written = self.written;
+
+        while self.written < self.count {
+            let Some(value) = iter.next() else {
+                break;
+            };
+            self.write_next(value);
+        }
+
+        self.written - start_written
+    }
+
+    /// Commit the reservation, publishing all written values with a
+    /// single store of the tail
+    ///
+    /// If not all reserved slots were written, only the written values
+    /// are published.
+    #[inline(always)]
+    pub fn commit(self) {
+        self.producer.tail += self.written;
+        self.producer.sync();
+    }
+
+    /// Cancel the reservation without publishing any values
+    #[inline(always)]
+    pub fn cancel(self) {}
+
+    /// Returns the next slot to write and its position.
+    #[inline(always)]
+    fn next_slot(&self) -> (*mut S::Slot, usize) {
+        if self.written >= self.count {
+            panic!(
+                "Attempted to write beyond reservation limit: written {} of {} slots",
+                self.written, self.count
+            );
+        }
+
+        let position = self.start_tail + self.written;
+        let slot = unsafe {
+            (*self.producer.spsc.as_ptr())
+                .buffer
+                .as_mut_ptr()
+                .add(position & Producer::<M, T, N, S>::MODULO_MASK)
+        };
+
+        (slot, position)
+    }
+}
+
+impl<M: ChannelMode<T>, T, const N: usize, S: Stamping<T>> Drop
+    for Producer<M, T, N, S>
+{
     fn drop(&mut self) {
+        // Publish what was written so it is not lost with us
+        if self.written > 0 {
+            self.sync();
+        }
+
+        unsafe {
+            (*self.spsc.as_ptr())
+                .owners
+                .producer
+                .release()
+        }
+
         // LocalMode is backed by arc
         if M::BACKED_BY_ARCC {
             unsafe { drop(Arc::from_raw(self.spsc.as_ptr())) }
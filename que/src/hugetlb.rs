@@ -0,0 +1,209 @@
+//! Discovery of hugetlbfs mounts and huge page pools.
+//!
+//! Mounts are read from `/proc/mounts` and pool counters from
+//! `/sys/kernel/mm/hugepages`.
+
+use std::{io, path::PathBuf};
+
+const MOUNTS: &str = "/proc/mounts";
+const MEMINFO: &str = "/proc/meminfo";
+const POOLS: &str = "/sys/kernel/mm/hugepages";
+
+/// A mounted hugetlbfs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HugetlbMount {
+    pub path: PathBuf,
+    /// Size in bytes of the pages backing files on this mount.
+    pub page_size: usize,
+}
+
+/// Counters of the huge page pool for one page size.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HugePagePool {
+    /// Size in bytes of the pages in this pool.
+    pub page_size: usize,
+    /// Pages allocated to the pool.
+    pub total: u64,
+    /// Pages not faulted in by any mapping.
+    pub free: u64,
+    /// Free pages promised to existing mappings.
+    pub reserved: u64,
+}
+
+impl HugePagePool {
+    /// Pages a new mapping can still reserve.
+    pub fn available(&self) -> u64 {
+        self.free.saturating_sub(self.reserved)
+    }
+}
+
+/// Lists the mounted hugetlbfs filesystems.
+pub fn mounts() -> io::Result<Vec<HugetlbMount>> {
+    let mounts = std::fs::read_to_string(MOUNTS)?;
+    Ok(parse_mounts(&mounts, default_page_size()))
+}
+
+/// Lists the huge page pools the kernel supports.
+pub fn pools() -> io::Result<Vec<HugePagePool>> {
+    let mut pools = Vec::new();
+    for entry in std::fs::read_dir(POOLS)? {
+        let name = entry?.file_name();
+        let Some(page_size) = name
+            .to_str()
+            .and_then(|name| name.strip_prefix("hugepages-"))
+            .and_then(parse_size)
+        else {
+            continue;
+        };
+        pools.push(pool(page_size)?);
+    }
+    pools.sort_by_key(|pool| pool.page_size);
+
+    Ok(pools)
+}
+
+/// Reads the pool of `page_size` byte huge pages.
+pub fn pool(page_size: usize) -> io::Result<HugePagePool> {
+    let dir = format!("{POOLS}/hugepages-{}kB", page_size >> 10);
+    let read = |counter: &str| -> io::Result<u64> {
+        std::fs::read_to_string(format!("{dir}/{counter}"))?
+            .trim()
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    };
+
+    Ok(HugePagePool {
+        page_size,
+        total: read("nr_hugepages")?,
+        free: read("free_hugepages")?,
+        reserved: read("resv_hugepages")?,
+    })
+}
+
+/// The page size of hugetlbfs mounts that do not specify one.
+fn default_page_size() -> Option<usize> {
+    let meminfo = std::fs::read_to_string(MEMINFO).ok()?;
+    meminfo.lines().find_map(|line| {
+        let size = line.strip_prefix("Hugepagesize:")?;
+        parse_size(&size.trim().replace(' ', ""))
+    })
+}
+
+fn parse_mounts(
+    mounts: &str,
+    default_page_size: Option<usize>,
+) -> Vec<HugetlbMount> {
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (_, path, fstype, options) = (
+                fields.next()?,
+                fields.next()?,
+                fields.next()?,
+                fields.next()?,
+            );
+            if fstype != "hugetlbfs" {
+                return None;
+            }
+
+            let page_size = options
+                .split(',')
+                .find_map(|option| option.strip_prefix("pagesize="))
+                .map_or(default_page_size, parse_size)?;
+
+            Some(HugetlbMount {
+                path: unescape(path).into(),
+                page_size,
+            })
+        })
+        .collect()
+}
+
+/// Parses sizes such as `2M`, `1G`, `1024M` or `2048kB` into bytes.
+fn parse_size(size: &str) -> Option<usize> {
+    let size = size.strip_suffix('B').unwrap_or(size);
+    let (digits, shift) = match size.as_bytes().last()? {
+        b'k' | b'K' => (&size[..size.len() - 1], 10),
+        b'm' | b'M' => (&size[..size.len() - 1], 20),
+        b'g' | b'G' => (&size[..size.len() - 1], 30),
+        _ => (size, 0),
+    };
+
+    digits
+        .parse::<usize>()
+        .ok()?
+        .checked_mul(1 << shift)
+}
+
+/// Undoes the octal escapes `/proc/mounts` uses for whitespace.
+fn unescape(path: &str) -> String {
+    let mut out = String::with_capacity(path.len());
+    let mut rest = path;
+    while let Some(i) = rest.find('\\') {
+        out.push_str(&rest[..i]);
+        let code = rest
+            .get(i + 1..i + 4)
+            .and_then(|code| u8::from_str_radix(code, 8).ok());
+        match code {
+            Some(byte) => {
+                out.push(byte as char);
+                rest = &rest[i + 4..];
+            }
+            None => {
+                out.push('\\');
+                rest = &rest[i + 1..];
+            }
+        }
+    }
+    out.push_str(rest);
+
+    out
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("2M"), Some(1 << 21));
+        assert_eq!(parse_size("1024M"), Some(1 << 30));
+        assert_eq!(parse_size("1G"), Some(1 << 30));
+        assert_eq!(parse_size("2048kB"), Some(1 << 21));
+        assert_eq!(parse_size("4096"), Some(4096));
+        assert_eq!(parse_size("huge"), None);
+    }
+
+    #[test]
+    fn test_parse_mounts() {
+        let mounts = "\
+proc /proc proc rw,nosuid,nodev,noexec,relatime 0 0
+hugetlbfs /dev/hugepages hugetlbfs rw,relatime,pagesize=2M 0 0
+none /mnt/huge\\040pages hugetlbfs rw,relatime 0 0
+none /mnt/gigantic hugetlbfs rw,relatime,pagesize=1024M 0 0
+tmpfs /dev/shm tmpfs rw,nosuid,nodev 0 0
+";
+
+        assert_eq!(
+            parse_mounts(mounts, Some(1 << 21)),
+            vec![
+                HugetlbMount {
+                    path: "/dev/hugepages".into(),
+                    page_size: 1 << 21,
+                },
+                HugetlbMount {
+                    path: "/mnt/huge pages".into(),
+                    page_size: 1 << 21,
+                },
+                HugetlbMount {
+                    path: "/mnt/gigantic".into(),
+                    page_size: 1 << 30,
+                },
+            ]
+        );
+
+        // Mounts of unknown page size are skipped
+        assert_eq!(parse_mounts(mounts, None).len(), 2);
+    }
+}
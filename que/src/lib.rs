@@ -1,18 +1,44 @@
-use std::marker::PhantomData;
+use std::{marker::PhantomData, ops::Deref, ptr::NonNull};
 
 use bytemuck::AnyBitPattern;
 
 mod atomic_compat;
+mod cursor;
+mod fingerprint;
+mod flush;
+mod metadata;
+mod owner;
+
+#[cfg(feature = "async")]
+pub mod async_adapter;
 
 use atomic_compat::{AtomicU64, AtomicUsize};
+use cursor::CursorTable;
+pub use cursor::MAX_CURSORS;
+use fingerprint::Fingerprint;
+pub use fingerprint::{TypeFingerprint, LAYOUT_VERSION};
+pub use flush::FlushPolicy;
+use metadata::MetadataSeq;
+pub use metadata::{Metadata, METADATA_SIZE};
+use owner::Owners;
+pub use owner::{Liveness, OwnerState};
 use padded_atomic::CachePaddedAtomicUsize;
+use wait::Notify;
 
+pub mod broadcast;
+pub mod dyn_channel;
+pub mod fd_passing;
 pub mod headless_spmc;
+#[cfg(target_os = "linux")]
+pub mod hugetlb;
 pub mod lossless;
+pub mod mpsc;
 pub mod padded_atomic;
 pub mod page_size;
+pub mod work_queue;
 
 pub mod shmem;
+mod wait;
 
 // pub(crate) mod utils;
 
@@ -29,10 +55,52 @@ pub struct Channel<M, T, const N: usize> {
     padding: [u8; 128 - 16],
     capacity: AtomicUsize,
     magic: AtomicU64,
+    /// Parked consumers wait here for the tail to advance.
+    consumer_notify: Notify,
+    /// Parked producers wait here for the head to advance.
+    producer_notify: Notify,
+    /// Element type the channel was created with.
+    fingerprint: Fingerprint,
+    /// Processes holding the producer and consumer ends.
+    owners: Owners,
+    /// Seqlock guarding typed writes to `padding`.
+    metadata_seq: MetadataSeq,
     buffer: [T; N],
     mode: PhantomData<M>,
 }
 
+/// Header of the channel kinds with several readers (work queue and
+/// broadcast): a [Channel] followed by the table of reader cursors,
+/// which single-reader channels do without.
+#[repr(C, align(128))]
+pub struct MultiChannel<M, T, const N: usize> {
+    pub(crate) channel: Channel<M, T, N>,
+    /// Reader cursors, after the ring buffer.
+    pub(crate) cursors: CursorTable,
+}
+
+impl<M, T, const N: usize> Deref for MultiChannel<M, T, N> {
+    type Target = Channel<M, T, N>;
+    fn deref(&self) -> &Channel<M, T, N> {
+        &self.channel
+    }
+}
+
+impl<M, T, const N: usize> MultiChannel<M, T, N> {
+    /// Like [Channel::loom_write_fresh_at], with no active cursors.
+    #[cfg(all(loom, test))]
+    pub(crate) unsafe fn loom_write_fresh_at(
+        ptr: *mut u8,
+        magic: u64,
+        buffer: [T; N],
+    ) {
+        Channel::<M, T, N>::loom_write_fresh_at(ptr, magic, buffer);
+        CursorTable::loom_write_fresh_at(core::ptr::addr_of_mut!(
+            (*ptr.cast::<Self>()).cursors
+        ));
+    }
+}
+
 mod private {
     pub trait Sealed {}
 }
@@ -66,19 +134,105 @@ impl<M, T, const N: usize> Channel<M, T, N> {
         T: bytemuck::Zeroable,
         M: ChannelMode<T>,
     {
+        Self::loom_write_fresh_at(
+            ptr,
+            MAGIC,
+            bytemuck::Zeroable::zeroed(),
+        );
+    }
+
+    /// Like [Channel::loom_write_fresh_empty_at], for channels whose
+    /// elements contain Loom atomics themselves.
+    #[cfg(all(loom, test))]
+    pub(crate) unsafe fn loom_write_fresh_at(
+        ptr: *mut u8,
+        magic: u64,
+        buffer: [T; N],
+    ) {
         assert!((ptr as usize).is_multiple_of(128), "unaligned");
         let ch = ptr.cast::<Self>();
-        core::ptr::write(ch, Self {
-            tail: CachePaddedAtomicUsize::new(0),
-            head: CachePaddedAtomicUsize::new(0),
-            producer_heartbeat: CachePaddedAtomicUsize::new(0),
-            consumer_heartbeat: CachePaddedAtomicUsize::new(0),
-            padding: [0; 128 - 16],
-            capacity: AtomicUsize::new(N),
-            magic: AtomicU64::new(MAGIC),
-            buffer: bytemuck::Zeroable::zeroed(),
-            mode: PhantomData,
-        });
+
+        // Written field by field: the whole channel does not fit on a
+        // Loom thread's stack
+        use core::ptr::{addr_of_mut, write};
+        write(addr_of_mut!((*ch).tail), CachePaddedAtomicUsize::new(0));
+        write(addr_of_mut!((*ch).head), CachePaddedAtomicUsize::new(0));
+        write(
+            addr_of_mut!((*ch).producer_heartbeat),
+            CachePaddedAtomicUsize::new(0),
+        );
+        write(
+            addr_of_mut!((*ch).consumer_heartbeat),
+            CachePaddedAtomicUsize::new(0),
+        );
+        write(addr_of_mut!((*ch).padding), [0; 128 - 16]);
+        write(addr_of_mut!((*ch).capacity), AtomicUsize::new(N));
+        write(addr_of_mut!((*ch).magic), AtomicU64::new(magic));
+        write(addr_of_mut!((*ch).consumer_notify), Notify::new());
+        write(addr_of_mut!((*ch).producer_notify), Notify::new());
+        write(
+            addr_of_mut!((*ch).fingerprint),
+            Fingerprint::new(TypeFingerprint::of::<T>(0)),
+        );
+        write(addr_of_mut!((*ch).owners), Owners::new());
+        write(addr_of_mut!((*ch).metadata_seq), MetadataSeq::new());
+        write(addr_of_mut!((*ch).buffer), buffer);
+    }
+
+    /// Records `T` and `type_hash` as the element type of a channel
+    /// being initialized, before its magic is published.
+    #[inline]
+    pub(crate) fn store_fingerprint(&self, type_hash: u64) {
+        self.fingerprint
+            .store(TypeFingerprint::of::<T>(type_hash));
+    }
+
+    /// Checks that a joined channel was created for `T` (and
+    /// `type_hash`, if both sides supplied one).
+    #[inline]
+    pub(crate) fn check_fingerprint(
+        &self,
+        type_hash: u64,
+    ) -> Result<(), error::QueError> {
+        self.fingerprint
+            .check(TypeFingerprint::of::<T>(type_hash))
+    }
+
+    /// Returns a typed view of the metadata region of the channel at
+    /// `channel`.
+    ///
+    /// # Safety
+    /// `channel` must point to a live channel for the returned
+    /// lifetime.
+    #[inline]
+    pub(crate) unsafe fn metadata<'a, U: bytemuck::Pod>(
+        channel: NonNull<Self>,
+    ) -> Metadata<'a, U> {
+        let ch = channel.as_ptr();
+        Metadata::new(
+            &*core::ptr::addr_of!((*ch).metadata_seq),
+            NonNull::new_unchecked(core::ptr::addr_of_mut!(
+                (*ch).padding
+            )),
+        )
+    }
+
+    /// Returns the element type fingerprint recorded in the header.
+    pub fn fingerprint(&self) -> TypeFingerprint {
+        self.fingerprint.load()
+    }
+
+    /// Checks whether the processes recorded as producer and consumer
+    /// are still running.
+    ///
+    /// Only the lossless, headless and runtime-sized endpoints record
+    /// themselves; the other channel kinds always report
+    /// [OwnerState::Vacant].
+    pub fn inspect_liveness(&self) -> Liveness {
+        Liveness {
+            producer: self.owners.producer.state(),
+            consumer: self.owners.consumer.state(),
+        }
     }
 
     #[rustfmt::skip]
@@ -91,6 +245,11 @@ impl<M, T, const N: usize> Channel<M, T, N> {
         println!("padding offset:            {}", core::mem::offset_of!(Self, padding));
         println!("capacity offset:           {}", core::mem::offset_of!(Self, capacity));
         println!("magic offset:              {}", core::mem::offset_of!(Self, magic));
+        println!("consumer_notify offset:    {}", core::mem::offset_of!(Self, consumer_notify));
+        println!("producer_notify offset:    {}", core::mem::offset_of!(Self, producer_notify));
+        println!("fingerprint offset:        {}", core::mem::offset_of!(Self, fingerprint));
+        println!("owners offset:             {}", core::mem::offset_of!(Self, owners));
+        println!("metadata_seq offset:       {}", core::mem::offset_of!(Self, metadata_seq));
         println!("buffer offset:             {}", core::mem::offset_of!(Self, buffer));
     }
 }
@@ -105,7 +264,7 @@ pub const MAGIC: u64 = u64::from_le_bytes(*b"TEMPORAL");
 unsafe impl<M, T, const N: usize> Sync for Channel<M, T, N> {}
 
 pub mod error {
-    use crate::shmem::ShmemError;
+    use crate::{shmem::ShmemError, TypeFingerprint};
 
     #[derive(Debug)]
     pub enum QueError {
@@ -121,12 +280,46 @@ pub mod error {
         /// Channel initialized with a different capacity
         IncorrectCapacity(usize),
 
+        /// Channel was created with a different element type
+        TypeMismatch {
+            expected: TypeFingerprint,
+            found: TypeFingerprint,
+        },
+
+        /// The end being joined is held by another running process
+        OwnerAlive { pid: u32 },
+
+        /// The end being joined was held by a process that died without
+        /// leaving; join with `take_over_shmem` to reclaim it
+        OwnerDead { pid: u32 },
+
         /// Shared Memory Error (e.g. invalid permissions, bad file
         /// descriptor, insufficient pre-allocatedpages)
         ShmemError(ShmemError),
 
         /// Only used for lossless spsc
         Full,
+
+        /// The sequence to join at is not in the ring: it is before the
+        /// shared head, already released to the producer, or after the
+        /// tail
+        SequenceUnavailable {
+            requested: u64,
+            head: u64,
+            tail: u64,
+        },
+
+        /// All [MAX_CURSORS](crate::MAX_CURSORS) reader slots of the
+        /// channel are taken
+        TooManyConsumers,
+
+        /// The subscriber was evicted by the producer for a stale
+        /// heartbeat and must join again
+        Evicted,
+
+        /// The async adapter could not spawn the helper thread that
+        /// parks on the channel
+        SpawnFailed(std::io::Error),
     }
 
     impl From<ShmemError> for QueError {
@@ -0,0 +1,380 @@
+//! Variable-length records on top of a lossless `u8` channel.
+//!
+//! Records are stored contiguously in the ring as an 8 byte header
+//! (`u32` length, `u32` flags) followed by the payload, padded to a
+//! multiple of 8 bytes. A record never wraps: if it does not fit before
+//! the end of the ring, the producer writes a padding record over the
+//! remaining bytes and starts the record at the beginning of the ring.
+
+use std::{
+    mem::MaybeUninit,
+    ops::{Deref, DerefMut},
+    sync::Arc,
+};
+
+use crate::{
+    atomic_compat::Ordering, error::QueError, Channel, ChannelMode,
+    LocalMode,
+};
+
+use super::{consumer::Consumer, producer::Producer};
+
+/// Size of the header in front of every record.
+pub const RECORD_HEADER: usize = 8;
+
+/// Records (header + payload) are padded to this alignment.
+const RECORD_ALIGN: usize = 8;
+
+/// Flag marking a record that only fills the end of the ring.
+const PADDING: u32 = 1;
+
+#[inline(always)]
+const fn record_size(len: usize) -> usize {
+    (RECORD_HEADER + len).next_multiple_of(RECORD_ALIGN)
+}
+
+pub fn bytes_pair<const N: usize>(
+) -> (BytesProducer<LocalMode, N>, BytesConsumer<LocalMode, N>) {
+    let arc_uninit = Arc::<Channel<LocalMode, u8, N>>::new_uninit();
+    let ptr: *mut MaybeUninit<Channel<LocalMode, u8, N>> =
+        Arc::into_raw(arc_uninit).cast_mut();
+
+    unsafe {
+        *ptr = core::mem::zeroed();
+    }
+
+    let producer = unsafe {
+        Producer::join_or_initialize_in_(ptr.cast()).unwrap()
+    };
+    let consumer = unsafe { Consumer::join(ptr.cast()).unwrap() };
+
+    unsafe {
+        Arc::decrement_strong_count(ptr);
+    }
+
+    (
+        BytesProducer::new(producer).unwrap(),
+        BytesConsumer::new(consumer).unwrap(),
+    )
+}
+
+/// Writes length-prefixed records into a lossless `u8` channel.
+pub struct BytesProducer<M: ChannelMode<u8>, const N: usize> {
+    inner: Producer<M, u8, N>,
+}
+
+impl<M: ChannelMode<u8>, const N: usize> BytesProducer<M, N> {
+    /// Largest payload that fits in a single record.
+    pub const MAX_LEN: usize = N - RECORD_HEADER;
+
+    /// Wraps a producer whose tail is on a record boundary.
+    ///
+    /// Returns [QueError::InvalidSize] if the producer already pushed a
+    /// number of bytes that is not a multiple of 8.
+    pub fn new(inner: Producer<M, u8, N>) -> Result<Self, QueError> {
+        assert!(
+            N >= 2 * RECORD_HEADER,
+            "Capacity must be at least 16 bytes"
+        );
+        if !inner.tail.is_multiple_of(RECORD_ALIGN) {
+            return Err(QueError::InvalidSize);
+        }
+        Ok(BytesProducer { inner })
+    }
+
+    pub fn into_inner(self) -> Producer<M, u8, N> {
+        self.inner
+    }
+
+    /// Reserves a record with a payload of `len` bytes. The payload is
+    /// published once the reservation is committed and synced.
+    ///
+    /// Returns [QueError::InvalidSize] if the record can never fit and
+    /// [QueError::Full] if there currently is not enough space.
+    ///
+    /// If the record does not fit before the end of the ring and there
+    /// is no room for it after the padding either, the padding is
+    /// published on its own before returning [QueError::Full]. Once the
+    /// consumer skips it, the retried record starts at the beginning of
+    /// the ring, so any record up to [Self::MAX_LEN] eventually fits.
+    pub fn reserve_bytes(
+        &mut self,
+        len: usize,
+    ) -> Result<BytesReservation<'_, M, N>, QueError> {
+        if len > Self::MAX_LEN || len > u32::MAX as usize {
+            return Err(QueError::InvalidSize);
+        }
+
+        let size = record_size(len);
+        let contiguous = N - (self.inner.tail & (N - 1));
+        let mut padding =
+            if size <= contiguous { 0 } else { contiguous };
+
+        let head = unsafe {
+            (*self.inner.spsc.as_ptr())
+                .head
+                .load(Ordering::Acquire)
+        };
+        let mut available_space = N - (self.inner.tail - head);
+        if padding != 0
+            && padding + size > available_space
+            && padding <= available_space
+        {
+            // Padding and record together may exceed the ring even when
+            // it is empty, so skip to the start of the ring on our own
+            // and let the consumer release the padding
+            self.write_header(
+                self.inner.tail,
+                (padding - RECORD_HEADER) as u32,
+                PADDING,
+            );
+            self.inner.commit_written(padding);
+            self.inner.sync();
+            available_space -= padding;
+            padding = 0;
+        }
+        if padding + size > available_space {
+            return Err(QueError::Full);
+        }
+
+        // The padding record lies past the published tail, so writing
+        // it now is harmless even if the reservation is cancelled.
+        if padding != 0 {
+            self.write_header(
+                self.inner.tail,
+                (padding - RECORD_HEADER) as u32,
+                PADDING,
+            );
+        }
+
+        Ok(BytesReservation {
+            start: self.inner.tail + padding,
+            padding,
+            len,
+            producer: self,
+        })
+    }
+
+    /// Copies `bytes` into a new record.
+    pub fn push_bytes(&mut self, bytes: &[u8]) -> Result<(), QueError> {
+        let mut reservation = self.reserve_bytes(bytes.len())?;
+        reservation.copy_from_slice(bytes);
+        reservation.commit();
+        Ok(())
+    }
+
+    /// Publishes all committed records.
+    #[inline(always)]
+    pub fn sync(&mut self) {
+        self.inner.sync();
+    }
+
+    #[inline(always)]
+    fn buffer_ptr(&self) -> *mut u8 {
+        unsafe {
+            (*self.inner.spsc.as_ptr())
+                .buffer
+                .as_mut_ptr()
+        }
+    }
+
+    #[inline(always)]
+    fn write_header(&mut self, position: usize, len: u32, flags: u32) {
+        let header = [len.to_ne_bytes(), flags.to_ne_bytes()];
+        unsafe {
+            core::ptr::write_unaligned(
+                self.buffer_ptr()
+                    .add(position & (N - 1))
+                    .cast::<[[u8; 4]; 2]>(),
+                header,
+            );
+        }
+    }
+}
+
+/// A record reserved in the ring. Derefs to its payload.
+pub struct BytesReservation<'a, M: ChannelMode<u8>, const N: usize> {
+    producer: &'a mut BytesProducer<M, N>,
+    /// Position of the record header
+    start: usize,
+    /// Bytes skipped at the end of the ring before `start`
+    padding: usize,
+    len: usize,
+}
+
+impl<'a, M: ChannelMode<u8>, const N: usize>
+    BytesReservation<'a, M, N>
+{
+    /// Commits the record. Like [super::producer::Reservation::commit],
+    /// this only publishes once a burst worth of bytes was written or
+    /// the inner producer's [FlushPolicy](crate::FlushPolicy) says so;
+    /// call [BytesProducer::sync] to publish immediately.
+    #[inline(always)]
+    pub fn commit(self) {
+        let size = self.padding + record_size(self.len);
+        self.producer
+            .write_header(self.start, self.len as u32, 0);
+
+        self.producer.inner.commit_written(size);
+    }
+
+    /// Cancel the reservation without publishing anything
+    #[inline(always)]
+    pub fn cancel(self) {}
+}
+
+impl<'a, M: ChannelMode<u8>, const N: usize> Deref
+    for BytesReservation<'a, M, N>
+{
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        unsafe {
+            core::slice::from_raw_parts(
+                self.producer
+                    .buffer_ptr()
+                    .add((self.start & (N - 1)) + RECORD_HEADER),
+                self.len,
+            )
+        }
+    }
+}
+
+impl<'a, M: ChannelMode<u8>, const N: usize> DerefMut
+    for BytesReservation<'a, M, N>
+{
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe {
+            core::slice::from_raw_parts_mut(
+                self.producer
+                    .buffer_ptr()
+                    .add((self.start & (N - 1)) + RECORD_HEADER),
+                self.len,
+            )
+        }
+    }
+}
+
+/// Reads length-prefixed records from a lossless `u8` channel.
+pub struct BytesConsumer<M: ChannelMode<u8>, const N: usize> {
+    inner: Consumer<M, u8, N>,
+}
+
+impl<M: ChannelMode<u8>, const N: usize> BytesConsumer<M, N> {
+    /// Wraps a consumer whose head is on a record boundary.
+    ///
+    /// Returns [QueError::InvalidSize] if the consumer already popped a
+    /// number of bytes that is not a multiple of 8.
+    pub fn new(inner: Consumer<M, u8, N>) -> Result<Self, QueError> {
+        assert!(
+            N >= 2 * RECORD_HEADER,
+            "Capacity must be at least 16 bytes"
+        );
+        if !inner.head.is_multiple_of(RECORD_ALIGN) {
+            return Err(QueError::InvalidSize);
+        }
+        Ok(BytesConsumer { inner })
+    }
+
+    pub fn into_inner(self) -> Consumer<M, u8, N> {
+        self.inner
+    }
+
+    /// Returns the next record without copying it out of the ring.
+    /// Returns `None` if the consumer is caught up.
+    ///
+    /// The record is released when the returned guard is dropped.
+    ///
+    /// Returns [QueError::CorruptionDetected] if the record header does
+    /// not describe a record within the ring and the published bytes.
+    pub fn pop_bytes(
+        &mut self,
+    ) -> Result<Option<Record<'_, M, N>>, QueError> {
+        loop {
+            let tail = unsafe {
+                (*self.inner.spsc.as_ptr())
+                    .tail
+                    .load(Ordering::Acquire)
+            };
+            if tail <= self.inner.head {
+                return Ok(None);
+            }
+            let published = tail - self.inner.head;
+
+            let offset = self.inner.head & (N - 1);
+            let [len, flags] = unsafe {
+                core::ptr::read_unaligned(
+                    self.buffer_ptr()
+                        .add(offset)
+                        .cast::<[[u8; 4]; 2]>(),
+                )
+            }
+            .map(u32::from_ne_bytes);
+            let len = len as usize;
+
+            // The header comes from shared memory: check it before
+            // trusting it with a slice
+            let contiguous = N - offset;
+            match flags {
+                PADDING
+                    if RECORD_HEADER + len == contiguous
+                        && contiguous <= published =>
+                {
+                    self.release(contiguous);
+                    continue;
+                }
+                0 if len <= contiguous - RECORD_HEADER
+                    && record_size(len) <= published => {}
+                _ => return Err(QueError::CorruptionDetected),
+            }
+
+            return Ok(Some(Record {
+                value: unsafe {
+                    core::slice::from_raw_parts(
+                        self.buffer_ptr()
+                            .add(offset + RECORD_HEADER),
+                        len,
+                    )
+                },
+                consumer: self,
+            }));
+        }
+    }
+
+    #[inline(always)]
+    fn buffer_ptr(&self) -> *const u8 {
+        unsafe {
+            (*self.inner.spsc.as_ptr())
+                .buffer
+                .as_ptr()
+        }
+    }
+
+    #[inline(always)]
+    fn release(&mut self, size: usize) {
+        self.inner.head += size;
+        self.inner.items_since_last_sync += size;
+        self.inner.maybe_sync();
+    }
+}
+
+/// A record borrowed from the ring. Derefs to its payload.
+pub struct Record<'a, M: ChannelMode<u8>, const N: usize> {
+    value: &'a [u8],
+    consumer: &'a mut BytesConsumer<M, N>,
+}
+
+impl<'a, M: ChannelMode<u8>, const N: usize> Deref
+    for Record<'a, M, N>
+{
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        self.value
+    }
+}
+
+impl<'a, M: ChannelMode<u8>, const N: usize> Drop for Record<'a, M, N> {
+    fn drop(&mut self) {
+        let size = record_size(self.value.len());
+        self.consumer.release(size);
+    }
+}
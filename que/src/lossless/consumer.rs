@@ -1,17 +1,44 @@
 use std::ops::{Deref, DerefMut};
-use std::{ptr::NonNull, sync::Arc};
+use std::{
+    path::Path,
+    ptr::NonNull,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use bytemuck::AnyBitPattern;
+use bytemuck::{AnyBitPattern, Pod};
 use derivative::Derivative;
 
 use crate::{
     atomic_compat::Ordering,
-    error::QueError, page_size::PageSize, shmem::Shmem, ChannelMode,
-    ShmemMode, MAGIC,
+    error::QueError,
+    page_size::PageSize,
+    shmem::{Shmem, ShmemConfig},
+    ChannelMode, Liveness, Metadata, ShmemMode, MAGIC,
 };
 
 use super::{burst_amount, Channel};
 
+/// Where a joining consumer starts reading.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum JoinPolicy {
+    /// Skip everything in the ring and read from the tail.
+    #[default]
+    Latest,
+    /// Continue from the head published by the previous consumer,
+    /// reading the backlog it left in the ring.
+    ///
+    /// The head is published every burst and on drop, so after a crash
+    /// the elements read since the last publish are read again. Use
+    /// [Consumer::checkpoint] to publish it explicitly.
+    ResumeFromSharedHead,
+    /// Read from the element with this sequence number, i.e. the
+    /// number of elements pushed before it. It must lie between the
+    /// shared head and the tail, or the join fails with
+    /// [QueError::SequenceUnavailable].
+    FromSequence(u64),
+}
+
 unsafe impl<M: ChannelMode<T>, T, const N: usize> Send
     for Consumer<M, T, N>
 {
@@ -19,38 +46,220 @@ unsafe impl<M: ChannelMode<T>, T, const N: usize> Send
 
 #[repr(C)]
 pub struct Consumer<M: ChannelMode<T>, T, const N: usize> {
-    spsc: NonNull<Channel<M, T, N>>,
-    head: usize,
-    items_since_last_sync: usize,
+    pub(super) spsc: NonNull<Channel<M, T, N>>,
+    pub(super) head: usize,
+    pub(super) items_since_last_sync: usize,
     consumer_index: usize,
     last_producer_heartbeat: usize,
+    /// Mapping backing the channel, if joined through shmem.
+    shmem: Option<Shmem>,
 }
 
 impl<T: AnyBitPattern, const N: usize> Consumer<ShmemMode, T, N> {
     /// Joins an existing channel back by shared memory as a consumer.
+    ///
+    /// Never creates or resizes the shmem: fails with
+    /// [ShmemError::NotFound](crate::shmem::ShmemError::NotFound) if no
+    /// producer has created it yet.
+    ///
+    /// # Safety
+    /// The shared memory region must only ever be mapped as a
+    /// `Channel` with the same `T` and `N`.
     pub unsafe fn join_shmem(
         shmem_id: &str,
-        #[cfg(target_os = "linux")] page_size: PageSize,
+        #[cfg(target_os = "linux")] config: impl Into<ShmemConfig>,
+    ) -> Result<Consumer<ShmemMode, T, N>, QueError> {
+        Self::join_shmem_with_type_hash(
+            shmem_id,
+            0,
+            #[cfg(target_os = "linux")]
+            config,
+        )
+    }
+
+    /// Like [Consumer::join_shmem], starting to read where `policy`
+    /// says.
+    ///
+    /// # Safety
+    /// The shared memory region must only ever be mapped as a
+    /// `Channel` with the same `T` and `N`.
+    pub unsafe fn join_shmem_with_policy(
+        shmem_id: &str,
+        policy: JoinPolicy,
+        #[cfg(target_os = "linux")] config: impl Into<ShmemConfig>,
     ) -> Result<Consumer<ShmemMode, T, N>, QueError> {
         #[cfg(not(target_os = "linux"))]
-        let page_size = PageSize::Standard;
+        let config = ShmemConfig::default();
+        #[cfg(target_os = "linux")]
+        let config: ShmemConfig = config.into();
+
+        let buffer_size: i64 = config
+            .page_size()
+            .mem_size(core::mem::size_of::<Channel<ShmemMode, T, N>>())
+            .try_into()
+            .map_err(|_| QueError::InvalidSize)?;
+        let shmem = config.open_existing(shmem_id, buffer_size)?;
+
+        let mut consumer =
+            Consumer::join_with_policy(shmem.get_mut_ptr(), policy)?;
+        consumer.shmem = Some(shmem);
+
+        Ok(consumer)
+    }
+
+    /// Like [Consumer::join_shmem], additionally checking `type_hash`
+    /// against the one recorded by the producer. A hash of 0 is not
+    /// checked.
+    ///
+    /// # Safety
+    /// The shared memory region must only ever be mapped as a
+    /// `Channel` with the same `T` and `N`.
+    pub unsafe fn join_shmem_with_type_hash(
+        shmem_id: &str,
+        type_hash: u64,
+        #[cfg(target_os = "linux")] config: impl Into<ShmemConfig>,
+    ) -> Result<Consumer<ShmemMode, T, N>, QueError> {
+        #[cfg(not(target_os = "linux"))]
+        let config = ShmemConfig::default();
+        #[cfg(target_os = "linux")]
+        let config: ShmemConfig = config.into();
 
         // Calculate buffer size.
         // If using huge pages, we must uplign to page size.
-        let buffer_size: i64 = page_size
+        let buffer_size: i64 = config
+            .page_size()
             .mem_size(core::mem::size_of::<Channel<ShmemMode, T, N>>())
             .try_into()
             .map_err(|_| QueError::InvalidSize)?;
 
         // Open shmem
-        let shmem = Shmem::open_or_create(
-            shmem_id,
-            buffer_size,
-            #[cfg(target_os = "linux")]
-            page_size,
+        let shmem = config.open_existing(shmem_id, buffer_size)?;
+
+        // Check the hash before joining moves the shared head
+        let spsc: *const Channel<ShmemMode, T, N> =
+            shmem.get_mut_ptr().cast();
+        if (*spsc).magic.load(Ordering::Acquire) == MAGIC {
+            (*spsc).check_fingerprint(type_hash)?;
+        }
+
+        let mut consumer = Consumer::join(shmem.get_mut_ptr())?;
+        consumer.shmem = Some(shmem);
+
+        Ok(consumer)
+    }
+
+    /// Like [Consumer::join_shmem], but first reclaims the channel if
+    /// the process recorded as its consumer has died. Fails with
+    /// [QueError::OwnerAlive] if it is still running.
+    ///
+    /// # Safety
+    /// The shared memory region must only ever be mapped as a
+    /// `Channel` with the same `T` and `N`.
+    pub unsafe fn take_over_shmem(
+        shmem_id: &str,
+        #[cfg(target_os = "linux")] config: impl Into<ShmemConfig>,
+    ) -> Result<Consumer<ShmemMode, T, N>, QueError> {
+        #[cfg(not(target_os = "linux"))]
+        let config = ShmemConfig::default();
+        #[cfg(target_os = "linux")]
+        let config: ShmemConfig = config.into();
+
+        let buffer_size: i64 = config
+            .page_size()
+            .mem_size(core::mem::size_of::<Channel<ShmemMode, T, N>>())
+            .try_into()
+            .map_err(|_| QueError::InvalidSize)?;
+        let shmem = config.open_existing(shmem_id, buffer_size)?;
+
+        let spsc: *mut Channel<ShmemMode, T, N> =
+            shmem.get_mut_ptr().cast();
+        (*spsc).owners.consumer.evict_dead()?;
+
+        let mut consumer = Consumer::join(shmem.get_mut_ptr())?;
+        consumer.shmem = Some(shmem);
+
+        Ok(consumer)
+    }
+
+    /// Joins the channel in `shmem` as a consumer, e.g. one received
+    /// with [Shmem::recv]. The endpoint owns the mapping.
+    ///
+    /// # Safety
+    /// The shared memory region must only ever be mapped as a
+    /// `Channel` with the same `T` and `N`.
+    pub unsafe fn from_shmem(
+        shmem: Shmem,
+    ) -> Result<Consumer<ShmemMode, T, N>, QueError> {
+        if (shmem.size as usize)
+            < core::mem::size_of::<Channel<ShmemMode, T, N>>()
+        {
+            return Err(QueError::InvalidSize);
+        }
+
+        let mut consumer = Self::join(shmem.get_mut_ptr())?;
+        consumer.shmem = Some(shmem);
+
+        Ok(consumer)
+    }
+
+    /// Joins a channel persisted in the file at `path`, see
+    /// [Producer::join_or_create_file](super::producer::Producer::join_or_create_file).
+    /// Unlike [Consumer::join_shmem], this resumes from the head
+    /// persisted by the last consumer, see
+    /// [JoinPolicy::ResumeFromSharedHead].
+    ///
+    /// The file is created by the producer; this fails with
+    /// [ShmemError::NotFound](crate::shmem::ShmemError::NotFound) if it
+    /// does not exist yet. Consumers that died holding the file are
+    /// replaced. Fails with [QueError::OwnerAlive] if one is still
+    /// running.
+    ///
+    /// # Safety
+    /// The file must only ever be mapped as a `Channel` with the same
+    /// `T` and `N`.
+    pub unsafe fn join_file(
+        path: impl AsRef<Path>,
+    ) -> Result<Consumer<ShmemMode, T, N>, QueError> {
+        let buffer_size: i64 = PageSize::Standard
+            .mem_size(core::mem::size_of::<Channel<ShmemMode, T, N>>())
+            .try_into()
+            .map_err(|_| QueError::InvalidSize)?;
+        let shmem = Shmem::open_existing_file(path, buffer_size)?;
+
+        let spsc: *mut Channel<ShmemMode, T, N> =
+            shmem.get_mut_ptr().cast();
+        (*spsc).owners.consumer.evict_dead()?;
+
+        let mut consumer = Consumer::join_with_policy(
+            shmem.get_mut_ptr(),
+            JoinPolicy::ResumeFromSharedHead,
         )?;
+        consumer.shmem = Some(shmem);
+
+        Ok(consumer)
+    }
+
+    /// Publishes the elements consumed so far and writes the channel
+    /// back to the file backing it, waiting for the write to complete.
+    /// A consumer resuming from the file starts after the last
+    /// checkpoint. Only publishes if the channel is not backed by a
+    /// file.
+    pub fn checkpoint(&mut self) -> Result<(), QueError> {
+        self.publish_head();
+        if let Some(shmem) = &self.shmem {
+            shmem.flush()?;
+        }
 
-        unsafe { Consumer::join(shmem.get_mut_ptr()) }
+        Ok(())
+    }
+
+    /// Unlinks the shared memory object when this endpoint is dropped,
+    /// if it created the object. Has no effect for endpoints joined
+    /// to an existing object or to a caller-provided buffer.
+    pub fn set_unlink_on_drop(&mut self, unlink: bool) {
+        if let Some(shmem) = self.shmem.as_mut() {
+            shmem.set_unlink_on_drop(unlink);
+        }
     }
 }
 
@@ -84,9 +293,9 @@ impl<'a, M: ChannelMode<T>, T, const N: usize> Drop
     for Element<'a, M, T, N>
 {
     fn drop(&mut self) {
-        (*self.consumer).head += 1;
-        (*self.consumer).items_since_last_sync += 1;
-        (*self.consumer).maybe_sync();
+        self.consumer.head += 1;
+        self.consumer.items_since_last_sync += 1;
+        self.consumer.maybe_sync();
     }
 }
 
@@ -112,19 +321,32 @@ impl<M: ChannelMode<T>, T, const N: usize> Consumer<M, T, N> {
     /// Joins an existing channel backed by `buffer`.
     ///
     ///
-    /// SAFETY:
+    /// # Safety
     /// This must point to a buffer of proper size and alignment.
     ///
     /// In LocalMode, must point to a region allocated by an Arc with the strong count already incremented!
     pub unsafe fn join(
         buffer: *mut u8,
     ) -> Result<Consumer<M, T, N>, QueError> {
-        let buffer = buffer;
+        Self::join_with_policy(buffer, JoinPolicy::Latest)
+    }
+
+    /// Joins an existing channel backed by `buffer`, starting to read
+    /// where `policy` says.
+    ///
+    /// # Safety
+    /// This must point to a buffer of proper size and alignment.
+    ///
+    /// In LocalMode, must point to a region allocated by an Arc with the strong count already incremented!
+    pub unsafe fn join_with_policy(
+        buffer: *mut u8,
+        policy: JoinPolicy,
+    ) -> Result<Consumer<M, T, N>, QueError> {
         assert!(
             N > 0 && N.is_power_of_two(),
             "Capacity must be a power of two"
         );
-        assert!(buffer as usize % 128 == 0, "unaligned");
+        assert!((buffer as usize).is_multiple_of(128), "unaligned");
 
         // Zerocopy deserialize the SPSC
         let spsc: *mut Channel<M, T, N> = buffer.cast();
@@ -138,11 +360,45 @@ impl<M: ChannelMode<T>, T, const N: usize> Consumer<M, T, N> {
                 return Err(QueError::IncorrectCapacity(capacity));
             }
 
-            // Assume spsc is empty upon joining
-            let new_head = (*spsc).tail.load(Ordering::Acquire);
-            (*spsc)
-                .head
-                .store(new_head, Ordering::Release);
+            (*spsc).check_fingerprint(0)?;
+
+            let tail = (*spsc).tail.load(Ordering::Acquire);
+            let head = (*spsc).head.load(Ordering::Acquire);
+            let new_head = match policy {
+                // Assume spsc is empty upon joining
+                JoinPolicy::Latest => tail,
+                JoinPolicy::ResumeFromSharedHead => {
+                    // A persisted head must lie within the ring
+                    if head > tail || tail - head > N {
+                        return Err(QueError::CorruptionDetected);
+                    }
+                    head
+                }
+                // Elements before the shared head may be overwritten
+                JoinPolicy::FromSequence(sequence) => {
+                    match usize::try_from(sequence) {
+                        Ok(sequence)
+                            if head <= sequence && sequence <= tail =>
+                        {
+                            sequence
+                        }
+                        _ => {
+                            return Err(QueError::SequenceUnavailable {
+                                requested: sequence,
+                                head: head as u64,
+                                tail: tail as u64,
+                            })
+                        }
+                    }
+                }
+            };
+
+            (*spsc).owners.consumer.claim()?;
+            if new_head != head {
+                (*spsc)
+                    .head
+                    .store(new_head, Ordering::Release);
+            }
 
             if M::BACKED_BY_ARCC {
                 unsafe {
@@ -153,6 +409,7 @@ impl<M: ChannelMode<T>, T, const N: usize> Consumer<M, T, N> {
             // Successful join if magic and capacity is correct
             Ok(Consumer {
                 spsc: NonNull::new_unchecked(buffer.cast()),
+                shmem: None,
                 head: new_head,
                 items_since_last_sync: 0,
                 consumer_index: 0,
@@ -196,7 +453,51 @@ impl<M: ChannelMode<T>, T, const N: usize> Consumer<M, T, N> {
         self.head += 1;
         self.items_since_last_sync += 1;
         self.maybe_sync();
-        return Some(value);
+        Some(value)
+    }
+
+    /// Like [Consumer::pop], additionally returning the element's
+    /// sequence number, its position in the stream of every element
+    /// pushed to the channel.
+    ///
+    /// This is the sequence [JoinPolicy::FromSequence] resumes from.
+    pub fn pop_with_seq(&mut self) -> Option<(u64, T)> {
+        let seq = self.head as u64;
+        self.pop().map(|value| (seq, value))
+    }
+
+    /// Reads the next element, waiting for the producer if the consumer
+    /// is caught up.
+    ///
+    /// Spins briefly and then parks on a futex in the channel header,
+    /// so this also works across processes.
+    pub fn pop_blocking(&mut self) -> T {
+        loop {
+            // Without a deadline we can only return with a value
+            if let Some(value) = self.pop_until(None) {
+                return value;
+            }
+        }
+    }
+
+    /// Reads the next element, waiting up to `timeout` for the producer.
+    /// Returns `None` if nothing was published in time.
+    pub fn pop_timeout(&mut self, timeout: Duration) -> Option<T> {
+        self.pop_until(Instant::now().checked_add(timeout))
+    }
+
+    fn pop_until(&mut self, deadline: Option<Instant>) -> Option<T> {
+        if let Some(value) = self.pop() {
+            return Some(value);
+        }
+
+        // Release everything we've read so far; the producer may be
+        // parked waiting on exactly this space.
+        self.publish_head();
+
+        let spsc = unsafe { &*self.spsc.as_ptr() };
+        spsc.consumer_notify
+            .wait_until(deadline, || self.pop())
     }
 
     /// Attempts to read the next element. Returns `None` if the
@@ -226,7 +527,89 @@ impl<M: ChannelMode<T>, T, const N: usize> Consumer<M, T, N> {
             consumer: self,
         };
 
-        return Some(element);
+        Some(element)
+    }
+
+    /// Reads up to `values.len()` elements into `values` with a single
+    /// tail load. Returns the number of elements read.
+    pub fn pop_batch(&mut self, values: &mut [T]) -> usize
+    where
+        T: Copy,
+    {
+        let (first, second) = self.read_available();
+        let first_len = first.len().min(values.len());
+        let second_len = second
+            .len()
+            .min(values.len() - first_len);
+
+        values[..first_len].copy_from_slice(&first[..first_len]);
+        values[first_len..first_len + second_len]
+            .copy_from_slice(&second[..second_len]);
+
+        // Both slices came from one tail load, so the count is already
+        // known to be published and release's second load is skipped.
+        self.advance_head(first_len + second_len);
+        first_len + second_len
+    }
+
+    /// Returns every published element not yet released, in order, as
+    /// up to two contiguous slices of the ring. The second slice is
+    /// empty unless the elements wrap around the end of the ring.
+    ///
+    /// The elements stay in the ring until passed to
+    /// [Consumer::release].
+    pub fn read_available(&self) -> (&[T], &[T]) {
+        let tail = unsafe {
+            (*self.spsc.as_ptr())
+                .tail
+                .load(Ordering::Acquire)
+        };
+        let available = tail.saturating_sub(self.head);
+        let start_index = self.head & Self::MODULO_MASK;
+        let first_len = available.min(N - start_index);
+
+        unsafe {
+            let buffer_ptr = (*self.spsc.as_ptr()).buffer.as_ptr();
+            (
+                core::slice::from_raw_parts(
+                    buffer_ptr.add(start_index),
+                    first_len,
+                ),
+                core::slice::from_raw_parts(
+                    buffer_ptr,
+                    available - first_len,
+                ),
+            )
+        }
+    }
+
+    /// Releases the first `count` elements returned by
+    /// [Consumer::read_available], handing their slots back to the
+    /// producer once a burst has been released.
+    ///
+    /// # Panics
+    /// Panics if fewer than `count` elements are published
+    pub fn release(&mut self, count: usize) {
+        let tail = unsafe {
+            (*self.spsc.as_ptr())
+                .tail
+                .load(Ordering::Acquire)
+        };
+        if count > tail - self.head {
+            panic!(
+                "Attempted to release {} elements with only {} available",
+                count,
+                tail - self.head
+            );
+        }
+
+        self.advance_head(count);
+    }
+
+    fn advance_head(&mut self, count: usize) {
+        self.head += count;
+        self.items_since_last_sync += count;
+        self.maybe_sync();
     }
 
     /// Increments the consumer heartbeat.
@@ -261,11 +644,18 @@ impl<M: ChannelMode<T>, T, const N: usize> Consumer<M, T, N> {
         }
     }
 
+    /// Checks whether the processes holding either end of the channel
+    /// are still running, see [Channel::inspect_liveness].
+    pub fn inspect_liveness(&self) -> Liveness {
+        unsafe { (*self.spsc.as_ptr()).inspect_liveness() }
+    }
+
     /// Returns pointer to inner padding.
     ///
     /// User is responsible for safe usage.
     ///
-    /// Can be used to store metadata (e.g. hash seed).
+    /// Unsynchronized: prefer [metadata](Self::metadata) for values
+    /// that change while the channel is in use.
     ///
     /// Byte array is 128 byte aligned.
     pub fn get_padding_ptr(&self) -> NonNull<[u8; 112]> {
@@ -277,23 +667,57 @@ impl<M: ChannelMode<T>, T, const N: usize> Consumer<M, T, N> {
         }
     }
 
+    /// Returns a typed, versioned view of the metadata region (the
+    /// same bytes as [get_padding_ptr](Self::get_padding_ptr)).
+    ///
+    /// Fails to compile if `U` is larger than 112 bytes.
+    pub fn metadata<U: Pod>(&self) -> Metadata<'_, U> {
+        unsafe { Channel::metadata(self.spsc) }
+    }
+
+    /// Returns the shared channel header.
+    #[cfg(feature = "async")]
     #[inline(always)]
-    fn maybe_sync(&mut self) {
+    pub(crate) fn channel(&self) -> &Channel<M, T, N> {
+        unsafe { self.spsc.as_ref() }
+    }
+
+    #[inline(always)]
+    pub(super) fn maybe_sync(&mut self) {
         let do_sync = self.items_since_last_sync >= burst_amount::<N>();
 
         if do_sync {
-            self.items_since_last_sync = 0;
-            unsafe {
-                (*self.spsc.as_ptr())
-                    .head
-                    .store(self.head, Ordering::Release);
-            }
+            self.publish_head();
+        }
+    }
+
+    /// Publishes the local head, waking a parked producer.
+    #[inline(always)]
+    pub(crate) fn publish_head(&mut self) {
+        self.items_since_last_sync = 0;
+        unsafe {
+            let spsc = &*self.spsc.as_ptr();
+            spsc.head
+                .store(self.head, Ordering::Release);
+            spsc.producer_notify.notify();
         }
     }
 }
 
 impl<M: ChannelMode<T>, T, const N: usize> Drop for Consumer<M, T, N> {
     fn drop(&mut self) {
+        // Leave the head where we stopped for a resuming consumer
+        if self.items_since_last_sync > 0 {
+            self.publish_head();
+        }
+
+        unsafe {
+            (*self.spsc.as_ptr())
+                .owners
+                .consumer
+                .release()
+        }
+
         // LocalMode is backed by arc
         if M::BACKED_BY_ARCC {
             unsafe { drop(Arc::from_raw(self.spsc.as_ptr())) }
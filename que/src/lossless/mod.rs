@@ -1,3 +1,4 @@
+pub mod bytes;
 pub mod consumer;
 pub mod producer;
 
@@ -53,11 +54,21 @@ mod tests {
     use producer::Producer;
 
     use super::*;
-    use crate::LocalMode;
+    use consumer::{Consumer, JoinPolicy};
+
+    use crate::{
+        error::QueError,
+        flush::LINGER_CHECK_INTERVAL,
+        page_size::PageSize,
+        shmem::{cleanup_shmem, ShmemError},
+        FlushPolicy, Liveness, LocalMode, OwnerState, ShmemMode,
+        TypeFingerprint,
+    };
 
     use std::{
         ptr::NonNull,
         sync::atomic::{AtomicU64, Ordering},
+        time::Duration,
     };
 
     #[test]
@@ -73,6 +84,137 @@ mod tests {
         assert_eq!(consumer.pop(), Some(70));
     }
 
+    #[test]
+    fn test_pop_with_seq() {
+        let (mut producer, mut consumer) = lossless_pair::<u64, 4>();
+        assert_eq!(producer.next_seq(), 0);
+
+        // Sequence numbers keep counting as the ring wraps
+        for round in 0..3 {
+            for i in 0..4 {
+                producer.push(round * 4 + i).unwrap();
+            }
+            assert_eq!(producer.next_seq(), round * 4 + 4);
+            producer.sync();
+            for i in 0..4 {
+                let seq = round * 4 + i;
+                assert_eq!(consumer.pop_with_seq(), Some((seq, seq)));
+            }
+        }
+        assert_eq!(consumer.pop_with_seq(), None);
+    }
+
+    #[test]
+    fn test_flush_policy() {
+        let (producer, mut consumer) = lossless_pair::<u64, 16>();
+        let mut producer =
+            producer.with_flush_policy(FlushPolicy::EveryN(2));
+        producer.push(0).unwrap();
+        assert_eq!(consumer.pop(), None);
+        producer.push(1).unwrap();
+        assert_eq!(consumer.pop(), Some(0));
+        assert_eq!(consumer.pop(), Some(1));
+
+        producer.set_flush_policy(FlushPolicy::Immediate);
+        producer.push(2).unwrap();
+        assert_eq!(consumer.pop(), Some(2));
+
+        // Linger is checked every few pushes and when asked
+        producer.set_flush_policy(FlushPolicy::Linger(
+            Duration::from_millis(20),
+        ));
+        producer.push(3).unwrap();
+        assert!(!producer.sync_if_due());
+        assert_eq!(consumer.pop(), None);
+        std::thread::sleep(Duration::from_millis(25));
+        assert!(producer.sync_if_due());
+        assert_eq!(consumer.pop(), Some(3));
+        producer.push(4).unwrap();
+        std::thread::sleep(Duration::from_millis(25));
+        let last = 4 + LINGER_CHECK_INTERVAL as u64;
+        for i in 5..last {
+            producer.push(i).unwrap();
+        }
+        assert_eq!(consumer.pop(), None);
+        producer.push(last).unwrap();
+        assert_eq!(
+            std::iter::from_fn(|| consumer.pop()).collect::<Vec<_>>(),
+            (4..=last).collect::<Vec<_>>()
+        );
+
+        // Reservations still publish a burst under a manual policy
+        producer.set_flush_policy(FlushPolicy::Manual);
+        let mut reservation = producer.reserve(4).unwrap();
+        reservation.write_all(&[6, 7, 8, 9]);
+        reservation.commit();
+        assert_eq!(consumer.pop(), Some(6));
+
+        // Anything unpublished is published on drop
+        producer.push(10).unwrap();
+        assert!(!producer.sync_if_due());
+        drop(producer);
+        let mut values = [0; 4];
+        assert_eq!(consumer.pop_batch(&mut values), 4);
+        assert_eq!(values, [7, 8, 9, 10]);
+    }
+
+    #[test]
+    fn test_push_with_in_place() {
+        let (mut producer, mut consumer) =
+            lossless_pair::<[u64; 4], 8>();
+
+        unsafe {
+            producer
+                .push_with(|slot| {
+                    let words = slot.as_mut_ptr().cast::<u64>();
+                    for i in 0..4 {
+                        words.add(i).write(i as u64);
+                    }
+                })
+                .unwrap();
+        }
+        producer.sync();
+        assert_eq!(consumer.pop(), Some([0, 1, 2, 3]));
+
+        // Move the tail so the next reservation wraps the ring
+        for i in 1..6 {
+            producer.push([i; 4]).unwrap();
+        }
+        producer.sync();
+        for _ in 1..6 {
+            consumer.pop().unwrap();
+        }
+
+        let mut reservation = producer.reserve(4).unwrap();
+        let (first, second) = reservation.slots_mut();
+        assert_eq!((first.len(), second.len()), (2, 2));
+        first[0].write([6; 4]);
+        first[1].write([7; 4]);
+        second[0].write([8; 4]);
+        unsafe {
+            reservation.assume_written(3);
+            reservation.write_with(|slot| {
+                slot.write([9; 4]);
+            });
+        }
+        assert_eq!(reservation.remaining(), 0);
+        assert_eq!(reservation.slots_mut().0.len(), 0);
+        reservation.commit();
+        producer.sync();
+        for i in 6..10 {
+            assert_eq!(consumer.pop(), Some([i; 4]));
+        }
+
+        // A full channel is reported without calling the initializer
+        for i in 0..8 {
+            producer.push([i; 4]).unwrap();
+        }
+        assert!(matches!(
+            unsafe { producer.push_with(|_| unreachable!()) },
+            Err(QueError::Full)
+        ));
+    }
+
     #[test]
     fn test_push_pop_zerocopy() {
         let (mut producer, mut consumer) = lossless_pair::<u64, 16>();
@@ -193,6 +335,882 @@ mod tests {
         assert_eq!(read.join().unwrap(), metadata);
     }
 
+    #[test]
+    fn test_typed_metadata() {
+        #[derive(
+            Clone,
+            Copy,
+            Debug,
+            PartialEq,
+            Eq,
+            bytemuck::Pod,
+            bytemuck::Zeroable,
+        )]
+        #[repr(C)]
+        struct Config {
+            seed: u64,
+            limit: u32,
+            flags: u32,
+        }
+
+        let (producer, consumer) = lossless_pair::<u64, 4>();
+        let written = producer.metadata::<Config>();
+        let read = consumer.metadata::<Config>();
+
+        // Never written: zeroes at version 0
+        assert_eq!(read.version(), 0);
+        assert_eq!(read.load().1, bytemuck::Zeroable::zeroed());
+        assert!(read.load_if_changed(0).is_none());
+
+        let config = Config {
+            seed: 0xdead_beef,
+            limit: 64,
+            flags: 1,
+        };
+        assert_eq!(written.store(&config), 1);
+        assert_eq!(read.load(), (1, config));
+        assert_eq!(read.load_if_changed(0), Some((1, config)));
+        assert!(read.load_if_changed(1).is_none());
+
+        // Same bytes as the raw padding
+        let seed = unsafe {
+            *consumer
+                .get_padding_ptr()
+                .cast::<u64>()
+                .as_ptr()
+        };
+        assert_eq!(seed, 0xdead_beef);
+
+        // Either end may write
+        let updated = Config {
+            limit: 128,
+            ..config
+        };
+        assert_eq!(consumer.metadata().store(&updated), 2);
+        assert_eq!(written.get(), updated);
+        assert_eq!(written.version(), 2);
+    }
+
+    #[test]
+    fn test_typed_metadata_never_torn() {
+        const WRITES: u64 = 100_000;
+        let (producer, consumer) = lossless_pair::<u64, 4>();
+
+        let read = std::thread::spawn(move || {
+            let metadata = consumer.metadata::<[u64; 14]>();
+            let mut seen = 0;
+            while seen < WRITES {
+                if let Some((version, value)) =
+                    metadata.load_if_changed(seen)
+                {
+                    assert!(version > seen);
+                    assert!(value.iter().all(|&v| v == value[0]));
+                    assert_eq!(value[0], version);
+                    seen = version;
+                }
+            }
+        });
+
+        let metadata = producer.metadata::<[u64; 14]>();
+        for i in 1..=WRITES {
+            assert_eq!(metadata.store(&[i; 14]), i);
+        }
+        read.join().unwrap();
+    }
+
+    #[test]
+    fn test_type_mismatch() {
+        #[repr(C, align(128))]
+        struct Aligned([u8; 16384]);
+        let mut buffer = Box::new(Aligned([0; 16384]));
+        let ptr = buffer.0.as_mut_ptr();
+
+        let _producer = unsafe {
+            Producer::<ShmemMode, u64, 16>::join_or_initialize_in(ptr)
+                .unwrap()
+        };
+
+        // Same capacity, different element size
+        let err = unsafe { Consumer::<ShmemMode, u32, 16>::join(ptr) }
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err,
+            QueError::TypeMismatch { expected, found }
+                if expected == TypeFingerprint::of::<u32>(0)
+                    && found == TypeFingerprint::of::<u64>(0)
+        ));
+
+        // Same size, different alignment
+        assert!(matches!(
+            unsafe { Consumer::<ShmemMode, [u32; 2], 16>::join(ptr) },
+            Err(QueError::TypeMismatch { .. })
+        ));
+
+        assert!(unsafe { Consumer::<ShmemMode, u64, 16>::join(ptr) }
+            .is_ok());
+    }
+
+    #[test]
+    fn test_pop_batch() {
+        let (mut producer, mut consumer) = lossless_pair::<u64, 8>();
+        let mut values = [0; 8];
+
+        assert_eq!(consumer.pop_batch(&mut values), 0);
+
+        // Wrap around the end of the ring
+        for i in 0..6 {
+            producer.push(i).unwrap();
+        }
+        producer.sync();
+        assert_eq!(consumer.pop_batch(&mut values), 6);
+        for i in 6..12 {
+            producer.push(i).unwrap();
+        }
+        producer.sync();
+
+        // Limited by the output
+        assert_eq!(consumer.pop_batch(&mut values[..4]), 4);
+        assert_eq!(values[..4], [6, 7, 8, 9]);
+        assert_eq!(consumer.pop_batch(&mut values), 2);
+        assert_eq!(values[..2], [10, 11]);
+        assert_eq!(consumer.pop(), None);
+    }
+
+    #[test]
+    fn test_read_available_release() {
+        let (mut producer, mut consumer) = lossless_pair::<u64, 8>();
+
+        assert_eq!(consumer.read_available(), (&[][..], &[][..]));
+
+        for i in 0..6 {
+            producer.push(i).unwrap();
+        }
+        producer.sync();
+        assert_eq!(
+            consumer.read_available(),
+            (&[0, 1, 2, 3, 4, 5][..], &[][..])
+        );
+        consumer.release(6);
+
+        for i in 6..12 {
+            producer.push(i).unwrap();
+        }
+        producer.sync();
+        assert_eq!(
+            consumer.read_available(),
+            (&[6, 7][..], &[8, 9, 10, 11][..])
+        );
+
+        // Partially released elements are not read again
+        consumer.release(3);
+        assert_eq!(
+            consumer.read_available(),
+            (&[9, 10, 11][..], &[][..])
+        );
+        assert_eq!(consumer.pop(), Some(9));
+
+        // Released slots go back to the producer
+        for i in 12..17 {
+            producer.push(i).unwrap();
+        }
+        assert!(producer.push(17).is_err());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_release_beyond_available() {
+        let (mut producer, mut consumer) = lossless_pair::<u64, 8>();
+
+        producer.push(0).unwrap();
+        producer.sync();
+        consumer.release(2);
+    }
+
+    #[test]
+    fn test_join_policy() {
+        #[repr(C, align(128))]
+        struct Aligned([u8; 16384]);
+        let mut buffer = Box::new(Aligned([0; 16384]));
+        let ptr = buffer.0.as_mut_ptr();
+
+        let mut producer = unsafe {
+            Producer::<ShmemMode, u64, 16>::join_or_initialize_in(ptr)
+                .unwrap()
+        };
+        let join = |policy| unsafe {
+            Consumer::<ShmemMode, u64, 16>::join_with_policy(
+                ptr, policy,
+            )
+        };
+
+        let mut consumer = join(JoinPolicy::Latest).unwrap();
+        for i in 0..8 {
+            producer.push(i).unwrap();
+        }
+        producer.sync();
+        assert_eq!(consumer.pop(), Some(0));
+        assert_eq!(consumer.pop(), Some(1));
+        drop(consumer);
+
+        // The backlog is still there for a resuming consumer
+        let mut consumer =
+            join(JoinPolicy::ResumeFromSharedHead).unwrap();
+        assert_eq!(consumer.pop(), Some(2));
+        drop(consumer);
+
+        // Released elements and future ones cannot be joined at
+        for sequence in [2, 9] {
+            assert!(matches!(
+                join(JoinPolicy::FromSequence(sequence)),
+                Err(QueError::SequenceUnavailable {
+                    requested,
+                    head: 3,
+                    tail: 8,
+                }) if requested == sequence
+            ));
+        }
+        let mut consumer = join(JoinPolicy::FromSequence(6)).unwrap();
+        assert_eq!(consumer.pop(), Some(6));
+        drop(consumer);
+
+        // Joining at the latest drops the rest of the backlog
+        let mut consumer = join(JoinPolicy::default()).unwrap();
+        assert_eq!(consumer.pop(), None);
+    }
+
+    #[test]
+    fn test_type_hash_mismatch() {
+        let id = format!("que_type_hash_{}", std::process::id());
+        let _producer = unsafe {
+            Producer::<ShmemMode, u64, 16>::join_or_create_shmem_with_type_hash(
+                &id,
+                0xfeed,
+                #[cfg(target_os = "linux")]
+                PageSize::Standard,
+            )
+            .unwrap()
+        };
+
+        let join = |type_hash| unsafe {
+            Consumer::<ShmemMode, u64, 16>::join_shmem_with_type_hash(
+                &id,
+                type_hash,
+                #[cfg(target_os = "linux")]
+                PageSize::Standard,
+            )
+        };
+        assert!(matches!(
+            join(0xbeef),
+            Err(QueError::TypeMismatch { .. })
+        ));
+        assert!(join(0xfeed).is_ok());
+        // No hash on the consumer side is not checked
+        assert!(join(0).is_ok());
+
+        cleanup_shmem(
+            &id,
+            core::mem::size_of::<Channel<ShmemMode, u64, 16>>() as i64,
+            #[cfg(target_os = "linux")]
+            PageSize::Standard,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_owners_recorded() {
+        let (producer, consumer) = lossless_pair::<u64, 16>();
+        let pid = std::process::id();
+
+        assert_eq!(
+            consumer.inspect_liveness(),
+            Liveness {
+                producer: OwnerState::Alive { pid },
+                consumer: OwnerState::Alive { pid },
+            }
+        );
+
+        drop(producer);
+        assert_eq!(
+            consumer.inspect_liveness().producer,
+            OwnerState::Vacant
+        );
+    }
+
+    #[test]
+    fn test_take_over_dead_producer() {
+        let id = format!("que_take_over_{}", std::process::id());
+        let join = || unsafe {
+            Producer::<ShmemMode, u64, 16>::join_or_create_shmem(
+                &id,
+                #[cfg(target_os = "linux")]
+                PageSize::Standard,
+            )
+        };
+        let take_over = || unsafe {
+            Producer::<ShmemMode, u64, 16>::take_over_shmem(
+                &id,
+                #[cfg(target_os = "linux")]
+                PageSize::Standard,
+            )
+        };
+
+        // Crash the producer: it never leaves, and its process is gone
+        let producer = join().unwrap();
+        let mut child = std::process::Command::new("true")
+            .spawn()
+            .unwrap();
+        let dead = child.id();
+        child.wait().unwrap();
+        let channel = unsafe { &*producer.spsc.as_ptr() };
+        channel.owners.producer.record(dead, 0);
+        core::mem::forget(producer);
+
+        assert_eq!(
+            channel.inspect_liveness().producer,
+            OwnerState::Dead { pid: dead }
+        );
+        assert!(matches!(
+            join(),
+            Err(QueError::OwnerDead { pid }) if pid == dead
+        ));
+
+        // A live owner is never taken over
+        channel.owners.producer.record(1, 0);
+        assert!(matches!(
+            take_over(),
+            Err(QueError::OwnerAlive { pid: 1 })
+        ));
+
+        channel.owners.producer.record(dead, 0);
+        let _producer = take_over().unwrap();
+        assert_eq!(
+            channel.inspect_liveness().producer,
+            OwnerState::Alive {
+                pid: std::process::id()
+            }
+        );
+
+        cleanup_shmem(
+            &id,
+            core::mem::size_of::<Channel<ShmemMode, u64, 16>>() as i64,
+            #[cfg(target_os = "linux")]
+            PageSize::Standard,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_take_over_abandoned_claim() {
+        let id = format!("que_abandoned_claim_{}", std::process::id());
+        let take_over = || unsafe {
+            Producer::<ShmemMode, u64, 16>::take_over_shmem(
+                &id,
+                #[cfg(target_os = "linux")]
+                PageSize::Standard,
+            )
+        };
+
+        let producer = unsafe {
+            Producer::<ShmemMode, u64, 16>::join_or_create_shmem(
+                &id,
+                #[cfg(target_os = "linux")]
+                PageSize::Standard,
+            )
+            .unwrap()
+        };
+        let channel = unsafe { &*producer.spsc.as_ptr() };
+        core::mem::forget(producer);
+
+        // The claimant died between marking the entry and recording
+        // itself
+        let mut child = std::process::Command::new("true")
+            .spawn()
+            .unwrap();
+        let dead = child.id();
+        child.wait().unwrap();
+        channel
+            .owners
+            .producer
+            .record_claiming(dead);
+        assert_eq!(
+            channel.inspect_liveness().producer,
+            OwnerState::Dead { pid: dead }
+        );
+        drop(take_over().unwrap());
+
+        // A claim that never finishes is abandoned after a timeout, even
+        // if its PID is running
+        channel
+            .owners
+            .producer
+            .record_claiming(1);
+        assert_eq!(
+            channel.inspect_liveness().producer,
+            OwnerState::Dead { pid: 1 }
+        );
+        let _producer = take_over().unwrap();
+        assert_eq!(
+            channel.inspect_liveness().producer,
+            OwnerState::Alive {
+                pid: std::process::id()
+            }
+        );
+
+        cleanup_shmem(
+            &id,
+            core::mem::size_of::<Channel<ShmemMode, u64, 16>>() as i64,
+            #[cfg(target_os = "linux")]
+            PageSize::Standard,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_shmem_create_vs_join() {
+        let id = format!("que_create_vs_join_{}", std::process::id());
+
+        // A consumer never creates the channel
+        assert!(matches!(
+            unsafe {
+                Consumer::<ShmemMode, u64, 16>::join_shmem(
+                    &id,
+                    #[cfg(target_os = "linux")]
+                    PageSize::Standard,
+                )
+            },
+            Err(QueError::ShmemError(ShmemError::NotFound))
+        ));
+
+        let producer = unsafe {
+            Producer::<ShmemMode, u64, 16>::create_shmem(
+                &id,
+                #[cfg(target_os = "linux")]
+                PageSize::Standard,
+            )
+            .unwrap()
+        };
+        drop(producer);
+        assert!(matches!(
+            unsafe {
+                Producer::<ShmemMode, u64, 16>::create_shmem(
+                    &id,
+                    #[cfg(target_os = "linux")]
+                    PageSize::Standard,
+                )
+            },
+            Err(QueError::ShmemError(ShmemError::AlreadyExists))
+        ));
+
+        // A live segment of another size is not resized
+        assert!(matches!(
+            unsafe {
+                Producer::<ShmemMode, u64, 4096>::join_or_create_shmem(
+                    &id,
+                    #[cfg(target_os = "linux")]
+                    PageSize::Standard,
+                )
+            },
+            Err(QueError::ShmemError(ShmemError::SizeMismatch { .. }))
+        ));
+
+        assert!(unsafe {
+            Consumer::<ShmemMode, u64, 16>::join_shmem(
+                &id,
+                #[cfg(target_os = "linux")]
+                PageSize::Standard,
+            )
+        }
+        .is_ok());
+
+        cleanup_shmem(
+            &id,
+            0,
+            #[cfg(target_os = "linux")]
+            PageSize::Standard,
+        )
+        .unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_anonymous_shmem_fd_passing() {
+        use std::os::unix::net::UnixStream;
+
+        use crate::shmem::{Shmem, ShmemConfig};
+
+        let size = PageSize::Standard.mem_size(core::mem::size_of::<
+            Channel<ShmemMode, u64, 16>,
+        >());
+        let shmem = ShmemConfig::new(PageSize::Standard)
+            .create_anonymous("que_anonymous", size as i64)
+            .unwrap();
+        assert!(shmem.is_creator());
+
+        let (supervisor, child) = UnixStream::pair().unwrap();
+        shmem.send(&supervisor).unwrap();
+        let mut producer = unsafe {
+            Producer::<ShmemMode, u64, 16>::from_shmem(shmem).unwrap()
+        };
+
+        // Received as a fresh fd and mapping
+        let mut consumer = unsafe {
+            Consumer::<ShmemMode, u64, 16>::from_shmem(
+                Shmem::recv(&child).unwrap(),
+            )
+            .unwrap()
+        };
+
+        producer.push(69).unwrap();
+        producer.sync();
+        assert_eq!(consumer.pop(), Some(69));
+
+        // Nothing left to receive once the peer is gone
+        drop(supervisor);
+        assert!(matches!(
+            Shmem::recv(&child),
+            Err(ShmemError::NoFdReceived)
+        ));
+    }
+
+    #[test]
+    fn test_file_backed_resume() {
+        let path = std::env::temp_dir()
+            .join(format!("que_journal_{}", std::process::id()));
+
+        // The consumer never creates the file
+        assert!(matches!(
+            unsafe { Consumer::<ShmemMode, u64, 16>::join_file(&path) },
+            Err(QueError::ShmemError(ShmemError::NotFound))
+        ));
+        assert!(!path.exists());
+
+        let mut producer = unsafe {
+            Producer::<ShmemMode, u64, 16>::join_or_create_file(&path)
+                .unwrap()
+        };
+        let mut consumer = unsafe {
+            Consumer::<ShmemMode, u64, 16>::join_file(&path).unwrap()
+        };
+        for i in 0..10 {
+            producer.push(i).unwrap();
+        }
+        producer.checkpoint().unwrap();
+        assert_eq!(consumer.pop(), Some(0));
+        assert_eq!(consumer.pop(), Some(1));
+        consumer.checkpoint().unwrap();
+        assert_eq!(consumer.pop(), Some(2));
+
+        // A crashed consumer never published its last read
+        core::mem::forget(consumer);
+        drop(producer);
+
+        // Restart both ends on the file
+        let mut producer = unsafe {
+            Producer::<ShmemMode, u64, 16>::join_or_create_file(&path)
+                .unwrap()
+        };
+        let mut consumer = unsafe {
+            Consumer::<ShmemMode, u64, 16>::join_file(&path).unwrap()
+        };
+        producer.push(10).unwrap();
+        producer.sync();
+        assert_eq!(
+            std::iter::from_fn(|| consumer.pop()).collect::<Vec<_>>(),
+            (2..=10).collect::<Vec<_>>()
+        );
+
+        // A cleanly dropped consumer resumes exactly
+        drop(consumer);
+        producer.push(11).unwrap();
+        producer.sync();
+        let mut consumer = unsafe {
+            Consumer::<ShmemMode, u64, 16>::join_file(&path).unwrap()
+        };
+        assert_eq!(consumer.pop(), Some(11));
+        assert_eq!(consumer.pop(), None);
+
+        // Other shapes are rejected by size
+        assert!(matches!(
+            unsafe {
+                Consumer::<ShmemMode, u64, 4096>::join_file(&path)
+            },
+            Err(QueError::ShmemError(ShmemError::SizeMismatch { .. }))
+        ));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_shmem_unlink_on_drop() {
+        let id = format!("que_unlink_on_drop_{}", std::process::id());
+
+        // Repeated open and drop does not leak mappings or descriptors
+        for _ in 0..1000 {
+            let producer = unsafe {
+                Producer::<ShmemMode, u64, 16>::join_or_create_shmem(
+                    &id,
+                    #[cfg(target_os = "linux")]
+                    PageSize::Standard,
+                )
+                .unwrap()
+            };
+            producer.beat();
+        }
+
+        // Joining endpoints never unlink
+        let mut consumer = unsafe {
+            Consumer::<ShmemMode, u64, 16>::join_shmem(
+                &id,
+                #[cfg(target_os = "linux")]
+                PageSize::Standard,
+            )
+            .unwrap()
+        };
+        consumer.set_unlink_on_drop(true);
+        drop(consumer);
+        cleanup_shmem(
+            &id,
+            0,
+            #[cfg(target_os = "linux")]
+            PageSize::Standard,
+        )
+        .unwrap();
+
+        let mut producer = unsafe {
+            Producer::<ShmemMode, u64, 16>::create_shmem(
+                &id,
+                #[cfg(target_os = "linux")]
+                PageSize::Standard,
+            )
+            .unwrap()
+        };
+        producer.set_unlink_on_drop(true);
+        drop(producer);
+        assert!(matches!(
+            unsafe {
+                Consumer::<ShmemMode, u64, 16>::join_shmem(
+                    &id,
+                    #[cfg(target_os = "linux")]
+                    PageSize::Standard,
+                )
+            },
+            Err(QueError::ShmemError(ShmemError::NotFound))
+        ));
+    }
+
+    #[test]
+    fn test_pop_timeout_empty() {
+        let (_producer, mut consumer) = lossless_pair::<u64, 16>();
+
+        assert_eq!(
+            consumer.pop_timeout(Duration::from_millis(10)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_pop_blocking_wakes_on_sync() {
+        let (mut producer, mut consumer) = lossless_pair::<u64, 16>();
+
+        let read = std::thread::spawn(move || {
+            (0..100)
+                .map(|_| consumer.pop_blocking())
+                .collect::<Vec<_>>()
+        });
+
+        for i in 0..100 {
+            producer.push_blocking(i);
+            producer.sync();
+        }
+
+        assert_eq!(read.join().unwrap(), (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_push_timeout_full() {
+        let (mut producer, mut consumer) = lossless_pair::<u64, 4>();
+
+        for i in 0..4 {
+            producer.push(i).unwrap();
+        }
+        assert!(matches!(
+            producer.push_timeout(4, Duration::from_millis(10)),
+            Err(QueError::Full)
+        ));
+
+        // push_timeout publishes before waiting
+        assert_eq!(consumer.pop(), Some(0));
+        assert!(producer
+            .push_timeout(4, Duration::from_millis(10))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_push_blocking_wakes_on_pop() {
+        let (mut producer, mut consumer) = lossless_pair::<u64, 4>();
+
+        let write = std::thread::spawn(move || {
+            for i in 0..100 {
+                producer.push_blocking(i);
+            }
+            producer.sync();
+        });
+
+        let read: Vec<u64> = (0..100)
+            .map(|_| consumer.pop_blocking())
+            .collect();
+        write.join().unwrap();
+        assert_eq!(read, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_bytes_push_pop() {
+        let (mut producer, mut consumer) = bytes::bytes_pair::<256>();
+
+        producer.push_bytes(b"hello").unwrap();
+        let mut reservation = producer.reserve_bytes(3).unwrap();
+        reservation.copy_from_slice(b"abc");
+        reservation.commit();
+        producer.push_bytes(b"").unwrap();
+        assert!(consumer.pop_bytes().unwrap().is_none());
+
+        producer.sync();
+        assert_eq!(
+            consumer.pop_bytes().unwrap().as_deref(),
+            Some(&b"hello"[..])
+        );
+        assert_eq!(
+            consumer.pop_bytes().unwrap().as_deref(),
+            Some(&b"abc"[..])
+        );
+        assert_eq!(
+            consumer.pop_bytes().unwrap().as_deref(),
+            Some(&b""[..])
+        );
+        assert!(consumer.pop_bytes().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_bytes_wraparound_padding() {
+        let (mut producer, mut consumer) = bytes::bytes_pair::<64>();
+
+        // 8 + 30 -> 40 bytes, leaving 24 bytes before the end
+        producer.push_bytes(&[1; 30]).unwrap();
+        producer.sync();
+        assert_eq!(
+            consumer.pop_bytes().unwrap().as_deref(),
+            Some(&[1; 30][..])
+        );
+
+        // 8 + 20 -> 32 bytes does not fit in the remaining 24 and wraps
+        producer.push_bytes(&[2; 20]).unwrap();
+        producer.sync();
+        assert_eq!(
+            consumer.pop_bytes().unwrap().as_deref(),
+            Some(&[2; 20][..])
+        );
+        assert!(consumer.pop_bytes().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_bytes_large_record_after_wrap() {
+        let (mut producer, mut consumer) = bytes::bytes_pair::<64>();
+
+        // Leave the tail 24 bytes into the ring, then drain it
+        producer.push_bytes(&[1; 16]).unwrap();
+        producer.sync();
+        drop(consumer.pop_bytes().unwrap());
+
+        // 8 + 40 -> 48 bytes does not fit in the 40 before the end, and
+        // 40 + 48 is more than the whole ring
+        assert!(matches!(
+            producer.push_bytes(&[2; 40]),
+            Err(QueError::Full)
+        ));
+
+        // The padding was published on its own; once it is skipped the
+        // record fits from the start of the ring
+        assert!(consumer.pop_bytes().unwrap().is_none());
+        producer.push_bytes(&[2; 40]).unwrap();
+        producer.sync();
+        assert_eq!(
+            consumer.pop_bytes().unwrap().as_deref(),
+            Some(&[2; 40][..])
+        );
+        assert!(consumer.pop_bytes().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_bytes_full() {
+        let (mut producer, mut consumer) = bytes::bytes_pair::<64>();
+
+        assert!(matches!(
+            producer.reserve_bytes(57),
+            Err(QueError::InvalidSize)
+        ));
+        producer.push_bytes(&[1; 48]).unwrap();
+        assert!(matches!(
+            producer.push_bytes(&[2; 8]),
+            Err(QueError::Full)
+        ));
+
+        producer.sync();
+        drop(consumer.pop_bytes().unwrap());
+        producer.push_bytes(&[2; 8]).unwrap();
+        producer.sync();
+        assert_eq!(
+            consumer.pop_bytes().unwrap().as_deref(),
+            Some(&[2; 8][..])
+        );
+    }
+
+    #[test]
+    fn test_bytes_rejects_unaligned_endpoints() {
+        let (mut producer, mut consumer) = lossless_pair::<u8, 64>();
+        producer.push(1).unwrap();
+        producer.sync();
+        assert_eq!(consumer.pop(), Some(1));
+
+        assert!(matches!(
+            bytes::BytesProducer::new(producer),
+            Err(QueError::InvalidSize)
+        ));
+        assert!(matches!(
+            bytes::BytesConsumer::new(consumer),
+            Err(QueError::InvalidSize)
+        ));
+    }
+
+    #[test]
+    fn test_bytes_corrupt_header() {
+        let header = |len: u32, flags: u32| {
+            let mut header = [0; 8];
+            header[..4].copy_from_slice(&len.to_ne_bytes());
+            header[4..].copy_from_slice(&flags.to_ne_bytes());
+            header
+        };
+
+        // Length past the end of the ring, length past the published
+        // bytes, padding not reaching the end, unknown flags
+        for bad in
+            [header(1000, 0), header(16, 0), header(8, 1), header(0, 2)]
+        {
+            let (mut producer, consumer) = lossless_pair::<u8, 64>();
+            for byte in bad {
+                producer.push(byte).unwrap();
+            }
+            producer.sync();
+
+            let mut consumer =
+                bytes::BytesConsumer::new(consumer).unwrap();
+            assert!(matches!(
+                consumer.pop_bytes(),
+                Err(QueError::CorruptionDetected)
+            ));
+        }
+    }
+
     #[test]
     fn test_reserve_write_all_single_slice() {
         let (mut producer, mut consumer) = lossless_pair::<u8, 8>();
@@ -1,22 +1,34 @@
-use std::{ptr::NonNull, sync::Arc};
+use std::{
+    mem::MaybeUninit,
+    path::Path,
+    ptr::NonNull,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use bytemuck::AnyBitPattern;
+use bytemuck::{AnyBitPattern, Pod};
 
 use crate::{
     atomic_compat::Ordering,
-    error::QueError, page_size::PageSize, shmem::Shmem, ChannelMode,
-    ShmemMode, MAGIC,
+    error::QueError,
+    flush::Flusher,
+    page_size::PageSize,
+    shmem::{Shmem, ShmemConfig},
+    ChannelMode, FlushPolicy, Liveness, Metadata, ShmemMode, MAGIC,
 };
 
 use super::{burst_amount, Channel};
 
 #[repr(C, align(128))]
 pub struct Producer<M: ChannelMode<T>, T, const N: usize> {
-    spsc: NonNull<Channel<M, T, N>>,
-    tail: usize,
+    pub(super) spsc: NonNull<Channel<M, T, N>>,
+    pub(super) tail: usize,
     /// Number of elements written since last sync
-    written: usize,
+    pub(super) written: usize,
     last_consumer_heartbeat: usize,
+    flusher: Flusher,
+    /// Mapping backing the channel, if joined through shmem.
+    shmem: Option<Shmem>,
 }
 
 unsafe impl<M: ChannelMode<T>, T, const N: usize> Send
@@ -27,27 +39,80 @@ unsafe impl<M: ChannelMode<T>, T, const N: usize> Send
 impl<T: AnyBitPattern, const N: usize> Producer<ShmemMode, T, N> {
     /// Joins or creates a channel backed by shared memory as a
     /// producer.
+    ///
+    /// # Safety
+    /// The shared memory region must only ever be mapped as a
+    /// `Channel` with the same `T` and `N`.
     pub unsafe fn join_or_create_shmem(
         shmem_id: &str,
-        #[cfg(target_os = "linux")] page_size: PageSize,
+        #[cfg(target_os = "linux")] config: impl Into<ShmemConfig>,
+    ) -> Result<Producer<ShmemMode, T, N>, QueError> {
+        Self::join_or_create_shmem_with_type_hash(
+            shmem_id,
+            0,
+            #[cfg(target_os = "linux")]
+            config,
+        )
+    }
+
+    /// Creates a channel backed by shared memory and joins it as the
+    /// producer. Fails with
+    /// [ShmemError::AlreadyExists](crate::shmem::ShmemError::AlreadyExists)
+    /// if the shmem exists.
+    ///
+    /// # Safety
+    /// The shared memory region must only ever be mapped as a
+    /// `Channel` with the same `T` and `N`.
+    pub unsafe fn create_shmem(
+        shmem_id: &str,
+        #[cfg(target_os = "linux")] config: impl Into<ShmemConfig>,
+    ) -> Result<Producer<ShmemMode, T, N>, QueError> {
+        #[cfg(not(target_os = "linux"))]
+        let config = ShmemConfig::default();
+        #[cfg(target_os = "linux")]
+        let config: ShmemConfig = config.into();
+
+        let buffer_size: i64 = config
+            .page_size()
+            .mem_size(core::mem::size_of::<Channel<ShmemMode, T, N>>())
+            .try_into()
+            .map_err(|_| QueError::InvalidSize)?;
+        let shmem = config.create_exclusive(shmem_id, buffer_size)?;
+
+        let mut producer =
+            Self::join_or_initialize_in_(shmem.get_mut_ptr())?;
+        producer.shmem = Some(shmem);
+
+        Ok(producer)
+    }
+
+    /// Like [Producer::join_or_create_shmem], additionally recording
+    /// `type_hash` when creating the channel and checking it when
+    /// joining. A hash of 0 is not checked.
+    ///
+    /// # Safety
+    /// The shared memory region must only ever be mapped as a
+    /// `Channel` with the same `T` and `N`.
+    pub unsafe fn join_or_create_shmem_with_type_hash(
+        shmem_id: &str,
+        type_hash: u64,
+        #[cfg(target_os = "linux")] config: impl Into<ShmemConfig>,
     ) -> Result<Producer<ShmemMode, T, N>, QueError> {
         #[cfg(not(target_os = "linux"))]
-        let page_size = PageSize::Standard;
+        let config = ShmemConfig::default();
+        #[cfg(target_os = "linux")]
+        let config: ShmemConfig = config.into();
 
         // Calculate buffer size.
         // If using huge pages, we must uplign to page size.
-        let buffer_size: i64 = page_size
+        let buffer_size: i64 = config
+            .page_size()
             .mem_size(core::mem::size_of::<Channel<ShmemMode, T, N>>())
             .try_into()
             .map_err(|_| QueError::InvalidSize)?;
 
         // Open or create shmem
-        let shmem = Shmem::open_or_create(
-            shmem_id,
-            buffer_size,
-            #[cfg(target_os = "linux")]
-            page_size,
-        )?;
+        let shmem = config.open_or_create(shmem_id, buffer_size)?;
 
         // Zerocopy deserialize the SPSC
         let spsc: *mut Channel<ShmemMode, T, N> =
@@ -63,26 +128,35 @@ impl<T: AnyBitPattern, const N: usize> Producer<ShmemMode, T, N> {
                 return Err(QueError::IncorrectCapacity(capacity));
             }
 
+            (*spsc).check_fingerprint(type_hash)?;
+            (*spsc).owners.producer.claim()?;
+
             // Successful join if magic and capacity is correct
             Ok(Producer {
                 spsc: NonNull::new(shmem.get_mut_ptr().cast()).unwrap(),
+                shmem: Some(shmem),
                 tail: (*spsc).tail.load(Ordering::Acquire),
                 written: 0,
+                flusher: Flusher::default(),
                 last_consumer_heartbeat: (*spsc)
                     .consumer_heartbeat
                     .load(Ordering::Acquire),
             })
         } else if magic == 0 {
+            (*spsc).owners.producer.claim()?;
             (*spsc).tail.store(0, Ordering::Release);
             (*spsc).producer_heartbeat.store(0, Ordering::Release);
             (*spsc).consumer_heartbeat.store(0, Ordering::Release);
             (*spsc).capacity.store(N, Ordering::Release);
+            (*spsc).store_fingerprint(type_hash);
             (*spsc).magic.store(MAGIC, Ordering::Release);
 
             Ok(Producer {
                 spsc: NonNull::new(shmem.get_mut_ptr().cast()).unwrap(),
+                shmem: Some(shmem),
                 tail: 0,
                 written: 0,
+                flusher: Flusher::default(),
                 last_consumer_heartbeat: (*spsc)
                     .consumer_heartbeat
                     .load(Ordering::Acquire),
@@ -93,10 +167,44 @@ impl<T: AnyBitPattern, const N: usize> Producer<ShmemMode, T, N> {
         };
     }
 
+    /// Like [Producer::join_or_create_shmem], but first reclaims the
+    /// channel if the process recorded as its producer has died. Fails
+    /// with [QueError::OwnerAlive] if it is still running.
+    ///
+    /// # Safety
+    /// The shared memory region must only ever be mapped as a
+    /// `Channel` with the same `T` and `N`.
+    pub unsafe fn take_over_shmem(
+        shmem_id: &str,
+        #[cfg(target_os = "linux")] config: impl Into<ShmemConfig>,
+    ) -> Result<Producer<ShmemMode, T, N>, QueError> {
+        #[cfg(not(target_os = "linux"))]
+        let config = ShmemConfig::default();
+        #[cfg(target_os = "linux")]
+        let config: ShmemConfig = config.into();
+
+        let buffer_size: i64 = config
+            .page_size()
+            .mem_size(core::mem::size_of::<Channel<ShmemMode, T, N>>())
+            .try_into()
+            .map_err(|_| QueError::InvalidSize)?;
+        let shmem = config.open_or_create(shmem_id, buffer_size)?;
+
+        let spsc: *mut Channel<ShmemMode, T, N> =
+            shmem.get_mut_ptr().cast();
+        (*spsc).owners.producer.evict_dead()?;
+
+        let mut producer =
+            Self::join_or_initialize_in_(shmem.get_mut_ptr())?;
+        producer.shmem = Some(shmem);
+
+        Ok(producer)
+    }
+
     /// Initializes a channel backed by `buffer` and joins as a
     /// producer.
     ///
-    /// SAFETY:
+    /// # Safety
     /// This must point to a buffer of proper size and alignment.
     pub unsafe fn join_or_initialize_in(
         buffer: *mut u8,
@@ -106,13 +214,89 @@ impl<T: AnyBitPattern, const N: usize> Producer<ShmemMode, T, N> {
 
     /// Joins an existing channel backed by `buffer` as a producer.
     ///
-    /// SAFETY:
+    /// # Safety
     /// This must point to a buffer of proper size and alignment.
     pub unsafe fn join(
         buffer: *mut u8,
     ) -> Result<Producer<ShmemMode, T, N>, QueError> {
         Self::join_(buffer)
     }
+
+    /// Initializes a channel in `shmem`, e.g. one created with
+    /// [ShmemConfig::create_anonymous] or received with
+    /// [Shmem::recv], or joins it as a producer if it is initialized.
+    /// The endpoint owns the mapping.
+    ///
+    /// # Safety
+    /// The shared memory region must only ever be mapped as a
+    /// `Channel` with the same `T` and `N`.
+    pub unsafe fn from_shmem(
+        shmem: Shmem,
+    ) -> Result<Producer<ShmemMode, T, N>, QueError> {
+        if (shmem.size as usize)
+            < core::mem::size_of::<Channel<ShmemMode, T, N>>()
+        {
+            return Err(QueError::InvalidSize);
+        }
+
+        let mut producer =
+            Self::join_or_initialize_in_(shmem.get_mut_ptr())?;
+        producer.shmem = Some(shmem);
+
+        Ok(producer)
+    }
+
+    /// Joins or creates a channel persisted in the file at `path`, see
+    /// [Shmem::open_or_create_file]. A producer restarting on the file
+    /// continues from the persisted tail.
+    ///
+    /// Producers that died holding the file are replaced, since picking
+    /// up after a crash or reboot is what the file is for. Fails with
+    /// [QueError::OwnerAlive] if one is still running.
+    ///
+    /// # Safety
+    /// The file must only ever be mapped as a `Channel` with the same
+    /// `T` and `N`.
+    pub unsafe fn join_or_create_file(
+        path: impl AsRef<Path>,
+    ) -> Result<Producer<ShmemMode, T, N>, QueError> {
+        let buffer_size: i64 = PageSize::Standard
+            .mem_size(core::mem::size_of::<Channel<ShmemMode, T, N>>())
+            .try_into()
+            .map_err(|_| QueError::InvalidSize)?;
+        let shmem = Shmem::open_or_create_file(path, buffer_size)?;
+
+        let spsc: *mut Channel<ShmemMode, T, N> =
+            shmem.get_mut_ptr().cast();
+        (*spsc).owners.producer.evict_dead()?;
+
+        let mut producer =
+            Self::join_or_initialize_in_(shmem.get_mut_ptr())?;
+        producer.shmem = Some(shmem);
+
+        Ok(producer)
+    }
+
+    /// Publishes everything pushed so far and writes the channel back
+    /// to the file backing it, waiting for the write to complete. Only
+    /// publishes if the channel is not backed by a file.
+    pub fn checkpoint(&mut self) -> Result<(), QueError> {
+        self.sync();
+        if let Some(shmem) = &self.shmem {
+            shmem.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Unlinks the shared memory object when this endpoint is dropped,
+    /// if it created the object. Has no effect for endpoints joined
+    /// to an existing object or to a caller-provided buffer.
+    pub fn set_unlink_on_drop(&mut self, unlink: bool) {
+        if let Some(shmem) = self.shmem.as_mut() {
+            shmem.set_unlink_on_drop(unlink);
+        }
+    }
 }
 
 impl<M: ChannelMode<T>, T, const N: usize> Producer<M, T, N> {
@@ -125,7 +309,7 @@ impl<M: ChannelMode<T>, T, const N: usize> Producer<M, T, N> {
             N > 0 && N.is_power_of_two(),
             "Capacity must be a power of two"
         );
-        assert!(buffer as usize % 128 == 0, "unaligned");
+        assert!((buffer as usize).is_multiple_of(128), "unaligned");
 
         // Zerocopy deserialize the SPSC
         let spsc: *mut Channel<M, T, N> = buffer.cast();
@@ -140,6 +324,9 @@ impl<M: ChannelMode<T>, T, const N: usize> Producer<M, T, N> {
                 return Err(QueError::IncorrectCapacity(capacity));
             }
 
+            (*spsc).check_fingerprint(0)?;
+            (*spsc).owners.producer.claim()?;
+
             (*spsc)
                 .producer_heartbeat
                 .fetch_add(1, Ordering::Release);
@@ -153,17 +340,21 @@ impl<M: ChannelMode<T>, T, const N: usize> Producer<M, T, N> {
             // Successful join if magic and capacity is correct
             Ok(Producer {
                 spsc: NonNull::new(buffer.cast()).unwrap(),
+                shmem: None,
                 tail: (*spsc).tail.load(Ordering::Acquire),
                 written: 0,
+                flusher: Flusher::default(),
                 last_consumer_heartbeat: (*spsc)
                     .consumer_heartbeat
                     .load(Ordering::Acquire),
             })
         } else if magic == 0 {
+            (*spsc).owners.producer.claim()?;
             (*spsc).tail.store(0, Ordering::Release);
             (*spsc).consumer_heartbeat.store(0, Ordering::Release);
             (*spsc).producer_heartbeat.store(0, Ordering::Release);
             (*spsc).capacity.store(N, Ordering::Release);
+            (*spsc).store_fingerprint(0);
             (*spsc).magic.store(MAGIC, Ordering::Release);
 
             if M::BACKED_BY_ARCC {
@@ -174,8 +365,10 @@ impl<M: ChannelMode<T>, T, const N: usize> Producer<M, T, N> {
 
             Ok(Producer {
                 spsc: NonNull::new(buffer.cast()).unwrap(),
+                shmem: None,
                 tail: 0,
                 written: 0,
+                flusher: Flusher::default(),
                 last_consumer_heartbeat: (*spsc)
                     .consumer_heartbeat
                     .load(Ordering::Acquire),
@@ -193,7 +386,7 @@ impl<M: ChannelMode<T>, T, const N: usize> Producer<M, T, N> {
             N > 0 && N.is_power_of_two(),
             "Capacity must be a power of two"
         );
-        assert!(buffer as usize % 128 == 0, "unaligned");
+        assert!((buffer as usize).is_multiple_of(128), "unaligned");
 
         // Zerocopy deserialize the SPSC
         let spsc: *mut Channel<M, T, N> = buffer.cast();
@@ -205,6 +398,9 @@ impl<M: ChannelMode<T>, T, const N: usize> Producer<M, T, N> {
                 return Err(QueError::IncorrectCapacity(capacity));
             }
 
+            (*spsc).check_fingerprint(0)?;
+            (*spsc).owners.producer.claim()?;
+
             if M::BACKED_BY_ARCC {
                 unsafe {
                     Arc::increment_strong_count(spsc);
@@ -214,8 +410,10 @@ impl<M: ChannelMode<T>, T, const N: usize> Producer<M, T, N> {
             // Successful join if magic and capacity is correct
             Ok(Producer {
                 spsc: NonNull::new(buffer.cast()).unwrap(),
+                shmem: None,
                 tail: (*spsc).tail.load(Ordering::Acquire),
                 written: 0,
+                flusher: Flusher::default(),
                 last_consumer_heartbeat: (*spsc)
                     .consumer_heartbeat
                     .load(Ordering::Acquire),
@@ -234,37 +432,179 @@ impl<M: ChannelMode<T>, T, const N: usize> Producer<M, T, N> {
     /// [QueError::Full].
     #[inline(always)]
     pub fn push(&mut self, value: T) -> Result<(), QueError> {
-        // Check if full
-        let is_full = self.tail
+        if self.is_full() {
+            return Err(QueError::Full);
+        }
+
+        self.write_unchecked(value);
+
+        Ok(())
+    }
+
+    /// Constructs a new element in place: `init` is called on its slot
+    /// in the ring, so large elements are not built elsewhere and
+    /// copied in. If full, returns [QueError::Full] without calling
+    /// `init`.
+    ///
+    /// # Safety
+    /// `init` must initialize the slot.
+    #[inline(always)]
+    pub unsafe fn push_with(
+        &mut self,
+        init: impl FnOnce(&mut MaybeUninit<T>),
+    ) -> Result<(), QueError> {
+        if self.is_full() {
+            return Err(QueError::Full);
+        }
+
+        self.write_with_unchecked(init);
+
+        Ok(())
+    }
+
+    /// Writes a new element to the channel, waiting for the consumer to
+    /// make room if the channel is full.
+    ///
+    /// Spins briefly and then parks on a futex in the channel header,
+    /// so this also works across processes. Like [Producer::push], the
+    /// value is not published until [Producer::sync].
+    pub fn push_blocking(&mut self, value: T) {
+        // Without a deadline we can only return once there is room
+        let _ = self.push_until(value, None);
+    }
+
+    /// Writes a new element to the channel, waiting up to `timeout` for
+    /// the consumer to make room. If the channel is still full after
+    /// `timeout`, returns [QueError::Full].
+    pub fn push_timeout(
+        &mut self,
+        value: T,
+        timeout: Duration,
+    ) -> Result<(), QueError> {
+        self.push_until(value, Instant::now().checked_add(timeout))
+    }
+
+    fn push_until(
+        &mut self,
+        value: T,
+        deadline: Option<Instant>,
+    ) -> Result<(), QueError> {
+        if self.is_full() {
+            // Publish what we have so far; the consumer may be parked
+            // waiting on exactly these values.
+            self.sync();
+
+            let spsc = unsafe { &*self.spsc.as_ptr() };
+            spsc.producer_notify
+                .wait_until(deadline, || {
+                    (!self.is_full()).then_some(())
+                })
+                .ok_or(QueError::Full)?;
+        }
+
+        self.write_unchecked(value);
+
+        Ok(())
+    }
+
+    #[inline(always)]
+    pub(crate) fn is_full(&self) -> bool {
+        self.tail
             == unsafe {
                 (*self.spsc.as_ptr())
                     .head
                     .load(Ordering::Acquire)
-            } + N;
-        if is_full {
-            return Err(QueError::Full);
+            } + N
+    }
+
+    /// Writes `value` at the local tail. Caller must check capacity.
+    #[inline(always)]
+    fn write_unchecked(&mut self, value: T) {
+        unsafe {
+            self.write_with_unchecked(|slot| {
+                slot.write(value);
+            })
         }
+    }
 
-        // Write value if not full
+    /// Initializes the slot at the local tail with `init`. Caller must
+    /// check capacity.
+    #[inline(always)]
+    unsafe fn write_with_unchecked(
+        &mut self,
+        init: impl FnOnce(&mut MaybeUninit<T>),
+    ) {
         let index = self.tail & Self::MODULO_MASK;
-        unsafe {
-            *(*self.spsc.as_ptr())
-                .buffer
-                .as_mut_ptr()
-                .add(index) = value;
-        };
+        init(&mut *self.slot_ptr(index));
 
         // Increment tail and written counter
         self.tail += 1;
         self.written += 1;
 
-        // // Update tail if we've written past burst amount and haven't
-        // // updated shared atomic.
-        // if self.written == burst_amount::<N>() {
-        //     self.sync();
-        // }
+        if self.flusher.on_write(self.written) {
+            self.sync();
+        }
+    }
 
-        Ok(())
+    /// Publishes `count` elements written past the local tail, syncing
+    /// once a burst is unpublished or when the flush policy says so.
+    #[inline(always)]
+    pub(super) fn commit_written(&mut self, count: usize) {
+        self.tail += count;
+        self.written += count;
+
+        if self.written >= burst_amount::<N>()
+            || self.flusher.on_write(self.written)
+        {
+            self.sync();
+        }
+    }
+
+    /// Returns the slot at `index` of the ring.
+    #[inline(always)]
+    fn slot_ptr(&self, index: usize) -> *mut MaybeUninit<T> {
+        unsafe {
+            (*self.spsc.as_ptr())
+                .buffer
+                .as_mut_ptr()
+                .add(index)
+                .cast()
+        }
+    }
+
+    /// Sets when pushed elements are published without a call to
+    /// [Producer::sync], for use right after joining. Defaults to
+    /// [FlushPolicy::Manual].
+    pub fn with_flush_policy(mut self, policy: FlushPolicy) -> Self {
+        self.set_flush_policy(policy);
+        self
+    }
+
+    /// Changes the flush policy, see [Producer::with_flush_policy].
+    pub fn set_flush_policy(&mut self, policy: FlushPolicy) {
+        self.flusher = Flusher::new(policy);
+    }
+
+    /// Publishes pending elements if the flush policy says they are
+    /// due, e.g. once a [FlushPolicy::Linger] has elapsed. Returns
+    /// whether it published.
+    pub fn sync_if_due(&mut self) -> bool {
+        let due = self.flusher.is_due(self.written);
+        if due {
+            self.sync();
+        }
+
+        due
+    }
+
+    /// Returns the sequence number the next pushed element will get.
+    ///
+    /// Sequence numbers count every element pushed to the channel and
+    /// match those returned by [Consumer::pop_with_seq].
+    ///
+    /// [Consumer::pop_with_seq]: super::consumer::Consumer::pop_with_seq
+    pub fn next_seq(&self) -> u64 {
+        self.tail as u64
     }
 
     /// Increments the producer heartbeat.
@@ -281,14 +621,16 @@ impl<M: ChannelMode<T>, T, const N: usize> Producer<M, T, N> {
     }
 
     /// Synchronizes the local tail with the atomic tail in the channel,
-    /// publishing newly written values.
+    /// publishing newly written values and waking a parked consumer.
     #[inline(always)]
     pub fn sync(&mut self) {
         self.written = 0;
+        self.flusher.flushed();
         unsafe {
-            (*self.spsc.as_ptr())
-                .tail
-                .store(self.tail, Ordering::Release)
+            let spsc = &*self.spsc.as_ptr();
+            spsc.tail
+                .store(self.tail, Ordering::Release);
+            spsc.consumer_notify.notify();
         }
     }
 
@@ -311,11 +653,18 @@ impl<M: ChannelMode<T>, T, const N: usize> Producer<M, T, N> {
         }
     }
 
+    /// Checks whether the processes holding either end of the channel
+    /// are still running, see [Channel::inspect_liveness].
+    pub fn inspect_liveness(&self) -> Liveness {
+        unsafe { (*self.spsc.as_ptr()).inspect_liveness() }
+    }
+
     /// Returns pointer to inner padding.
     ///
     /// User is responsible for safe usage.
     ///
-    /// Can be used to store metadata (e.g. hash seed).
+    /// Unsynchronized: prefer [metadata](Self::metadata) for values
+    /// that change while the channel is in use.
     ///
     /// Byte array is 128 byte aligned.
     pub fn get_padding_ptr(&self) -> NonNull<[u8; 112]> {
@@ -327,6 +676,21 @@ impl<M: ChannelMode<T>, T, const N: usize> Producer<M, T, N> {
         }
     }
 
+    /// Returns a typed, versioned view of the metadata region (the
+    /// same bytes as [get_padding_ptr](Self::get_padding_ptr)).
+    ///
+    /// Fails to compile if `U` is larger than 112 bytes.
+    pub fn metadata<U: Pod>(&self) -> Metadata<'_, U> {
+        unsafe { Channel::metadata(self.spsc) }
+    }
+
+    /// Returns the shared channel header.
+    #[cfg(feature = "async")]
+    #[inline(always)]
+    pub(crate) fn channel(&self) -> &Channel<M, T, N> {
+        unsafe { self.spsc.as_ref() }
+    }
+
     /// Reserves space for multiple elements. Returns a reservation that must be
     /// committed to publish the values.
     #[inline(always)]
@@ -382,6 +746,12 @@ impl<'a, M: ChannelMode<T>, T, const N: usize>
         self.count
     }
 
+    /// Returns `true` if no slots were reserved
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
     /// Returns the number of values written so far
     #[inline(always)]
     pub fn written(&self) -> usize {
@@ -400,6 +770,26 @@ impl<'a, M: ChannelMode<T>, T, const N: usize>
     /// Panics if all reserved slots have been written
     #[inline(always)]
     pub fn write_next(&mut self, value: T) {
+        unsafe {
+            self.write_with(|slot| {
+                slot.write(value);
+            })
+        }
+    }
+
+    /// Constructs the next value in sequence in place, calling `init`
+    /// on its slot in the ring.
+    ///
+    /// # Safety
+    /// `init` must initialize the slot.
+    ///
+    /// # Panics
+    /// Panics if all reserved slots have been written
+    #[inline(always)]
+    pub unsafe fn write_with(
+        &mut self,
+        init: impl FnOnce(&mut MaybeUninit<T>),
+    ) {
         if self.written >= self.count {
             panic!("Attempted to write beyond reservation limit: written {} of {} slots", 
                    self.written, self.count);
@@ -407,14 +797,58 @@ impl<'a, M: ChannelMode<T>, T, const N: usize>
 
         let index = (self.start_tail + self.written)
             & Producer::<M, T, N>::MODULO_MASK;
+        init(&mut *self.producer.slot_ptr(index));
+
+        self.written += 1;
+    }
+
+    /// Returns the reserved slots not yet written, in order, as up to
+    /// two slices. The second slice is empty unless the slots wrap
+    /// around the end of the ring.
+    ///
+    /// Slots initialized through these slices are marked written with
+    /// [Reservation::assume_written].
+    pub fn slots_mut(
+        &mut self,
+    ) -> (&mut [MaybeUninit<T>], &mut [MaybeUninit<T>]) {
+        let remaining = self.remaining();
+        let start_index = (self.start_tail + self.written)
+            & Producer::<M, T, N>::MODULO_MASK;
+        let first_len = remaining.min(N - start_index);
+
         unsafe {
-            *(*self.producer.spsc.as_ptr())
-                .buffer
-                .as_mut_ptr()
-                .add(index) = value;
+            (
+                core::slice::from_raw_parts_mut(
+                    self.producer.slot_ptr(start_index),
+                    first_len,
+                ),
+                core::slice::from_raw_parts_mut(
+                    self.producer.slot_ptr(0),
+                    remaining - first_len,
+                ),
+            )
         }
+    }
 
-        self.written += 1;
+    /// Marks the first `count` slots returned by
+    /// [Reservation::slots_mut] as written.
+    ///
+    /// # Safety
+    /// Those slots must have been initialized.
+    ///
+    /// # Panics
+    /// Panics if fewer than `count` slots remain
+    #[inline(always)]
+    pub unsafe fn assume_written(&mut self, count: usize) {
+        if count > self.remaining() {
+            panic!(
+                "Attempted to mark {} slots written with only {} remaining",
+                count,
+                self.remaining()
+            );
+        }
+
+        self.written += count;
     }
 
     /// Write all values from a slice
@@ -499,18 +933,14 @@ impl<'a, M: ChannelMode<T>, T, const N: usize>
     /// Commit the reservation, publishing all written values
     ///
     /// If not all reserved slots were written, only the written values are published.
+    /// They are synced once a burst is unpublished or when the flush policy says so.
     #[inline(always)]
     pub fn commit(mut self) {
         self.committed = true;
 
         // Only advance tail by the amount actually written
-        self.producer.tail += self.written;
-        self.producer.written += self.written;
-
-        // Sync if we've written enough
-        if self.producer.written >= burst_amount::<N>() {
-            self.producer.sync();
-        }
+        self.producer
+            .commit_written(self.written);
     }
 
     /// Cancel the reservation without publishing any values
@@ -522,6 +952,18 @@ impl<'a, M: ChannelMode<T>, T, const N: usize>
 
 impl<M: ChannelMode<T>, T, const N: usize> Drop for Producer<M, T, N> {
     fn drop(&mut self) {
+        // Publish what was written so it is not lost with us
+        if self.written > 0 {
+            self.sync();
+        }
+
+        unsafe {
+            (*self.spsc.as_ptr())
+                .owners
+                .producer
+                .release()
+        }
+
         // LocalMode is backed by arc
         if M::BACKED_BY_ARCC {
             unsafe { drop(Arc::from_raw(self.spsc.as_ptr())) }
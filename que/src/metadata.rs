@@ -0,0 +1,172 @@
+//! Typed, versioned user metadata stored in the channel header.
+//!
+//! The 112 bytes of padding at offset 512 hold a single [Pod] value
+//! guarded by a seqlock in its own header line. The sequence word is
+//! odd while a write is in progress; every completed write bumps the
+//! version (half the sequence) by one, so readers can tell when the
+//! value changed and retry reads that raced with a write.
+
+use core::{hint::spin_loop, marker::PhantomData, mem::MaybeUninit};
+use std::ptr::NonNull;
+
+use bytemuck::Pod;
+
+use crate::atomic_compat::{fence, AtomicU64, Ordering};
+
+/// Number of bytes available to a [Metadata] value.
+pub const METADATA_SIZE: usize = 112;
+
+/// Header storage for the metadata seqlock.
+#[derive(Default)]
+#[repr(C, align(128))]
+pub(crate) struct MetadataSeq {
+    /// Twice the version, plus one while a write is in progress.
+    seq: AtomicU64,
+}
+
+impl MetadataSeq {
+    #[cfg(all(loom, test))]
+    pub(crate) fn new() -> Self {
+        MetadataSeq {
+            seq: AtomicU64::new(0),
+        }
+    }
+}
+
+/// A typed view of the metadata region of a channel, borrowed from
+/// one of its endpoints.
+///
+/// Any endpoint may write; concurrent writers are serialized by the
+/// seqlock. A process that dies mid-write leaves the region locked and
+/// readers spinning, so writes are best kept to one side (usually the
+/// producer).
+pub struct Metadata<'a, U> {
+    seq: &'a AtomicU64,
+    data: NonNull<[u8; METADATA_SIZE]>,
+    value: PhantomData<U>,
+}
+
+impl<'a, U: Pod> Metadata<'a, U> {
+    /// Fails to compile for types that do not fit in the region.
+    const FITS: () = assert!(
+        core::mem::size_of::<U>() <= METADATA_SIZE,
+        "metadata type does not fit in the 112 byte region"
+    );
+
+    /// # Safety
+    /// `seq` and `data` must come from the same live channel header.
+    pub(crate) unsafe fn new(
+        seq: &'a MetadataSeq,
+        data: NonNull<[u8; METADATA_SIZE]>,
+    ) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::FITS;
+        Metadata {
+            seq: &seq.seq,
+            data,
+            value: PhantomData,
+        }
+    }
+
+    /// Returns the version of the last completed write. 0 if the
+    /// region was never written through this API.
+    #[inline]
+    pub fn version(&self) -> u64 {
+        self.seq.load(Ordering::Acquire) / 2
+    }
+
+    /// Reads the value and the version it was written at, retrying
+    /// while a write is in progress.
+    ///
+    /// A region that was never written reads as all zeroes at version
+    /// 0.
+    pub fn load(&self) -> (u64, U) {
+        loop {
+            if let Some(loaded) = self.try_load() {
+                return loaded;
+            }
+            spin_loop();
+        }
+    }
+
+    /// Reads the value, retrying while a write is in progress.
+    #[inline]
+    pub fn get(&self) -> U {
+        self.load().1
+    }
+
+    /// Reads the value if it was written after version `seen`.
+    pub fn load_if_changed(&self, seen: u64) -> Option<(u64, U)> {
+        loop {
+            if self.version() == seen {
+                return None;
+            }
+            if let Some(loaded) = self.try_load() {
+                return (loaded.0 != seen).then_some(loaded);
+            }
+            spin_loop();
+        }
+    }
+
+    /// Reads the value once, or returns `None` if a write was in
+    /// progress or completed during the read.
+    pub fn try_load(&self) -> Option<(u64, U)> {
+        let before = self.seq.load(Ordering::Acquire);
+        if !before.is_multiple_of(2) {
+            return None;
+        }
+
+        // May race with a writer; only kept if the sequence did not
+        // move
+        let value = unsafe {
+            core::ptr::read_volatile(
+                self.data
+                    .as_ptr()
+                    .cast::<MaybeUninit<U>>(),
+            )
+        };
+
+        fence(Ordering::Acquire);
+        if self.seq.load(Ordering::Relaxed) != before {
+            return None;
+        }
+        Some((before / 2, unsafe { value.assume_init() }))
+    }
+
+    /// Writes `value` and returns its version.
+    pub fn store(&self, value: &U) -> u64 {
+        let before = self.lock();
+        fence(Ordering::Release);
+        unsafe {
+            core::ptr::write_volatile(
+                self.data.as_ptr().cast::<U>(),
+                *value,
+            );
+        }
+        self.seq
+            .store(before + 2, Ordering::Release);
+        before / 2 + 1
+    }
+
+    /// Makes the sequence odd, waiting out other writers. Returns the
+    /// (even) sequence it started from.
+    fn lock(&self) -> u64 {
+        loop {
+            let before = self.seq.load(Ordering::Relaxed);
+            if before.is_multiple_of(2)
+                && self
+                    .seq
+                    .compare_exchange_weak(
+                        before,
+                        before + 1,
+                        Ordering::Acquire,
+                        Ordering::Relaxed,
+                    )
+                    .is_ok()
+            {
+                return before;
+            }
+            spin_loop();
+        }
+    }
+}
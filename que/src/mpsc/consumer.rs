@@ -0,0 +1,279 @@
+use std::{
+    ptr::{addr_of, NonNull},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use bytemuck::{AnyBitPattern, Pod};
+
+use crate::{
+    atomic_compat::Ordering,
+    error::QueError,
+    shmem::{Shmem, ShmemConfig},
+    Channel, ChannelMode, Metadata, ShmemMode,
+};
+
+use super::{burst_amount, Slot, MAGIC};
+
+unsafe impl<M: ChannelMode<T>, T, const N: usize> Send
+    for Consumer<M, T, N>
+{
+}
+
+/// The single consumer of an MPSC channel.
+#[repr(C)]
+pub struct Consumer<M: ChannelMode<T>, T, const N: usize> {
+    spsc: NonNull<Channel<M, Slot<T>, N>>,
+    head: usize,
+    items_since_last_sync: usize,
+    last_producer_heartbeat: usize,
+    /// Mapping backing the channel, if joined through shmem.
+    shmem: Option<Shmem>,
+}
+
+impl<T: AnyBitPattern, const N: usize> Consumer<ShmemMode, T, N> {
+    /// Joins an existing MPSC channel backed by shared memory as a
+    /// consumer.
+    ///
+    /// # Safety
+    /// The shared memory region must only ever be mapped as an MPSC
+    /// `Channel` with the same `T` and `N`.
+    pub unsafe fn join_shmem(
+        shmem_id: &str,
+        #[cfg(target_os = "linux")] config: impl Into<ShmemConfig>,
+    ) -> Result<Consumer<ShmemMode, T, N>, QueError> {
+        #[cfg(not(target_os = "linux"))]
+        let config = ShmemConfig::default();
+        #[cfg(target_os = "linux")]
+        let config: ShmemConfig = config.into();
+
+        // Calculate buffer size.
+        // If using huge pages, we must uplign to page size.
+        let buffer_size: i64 = config
+            .page_size()
+            .mem_size(core::mem::size_of::<
+                Channel<ShmemMode, Slot<T>, N>,
+            >())
+            .try_into()
+            .map_err(|_| QueError::InvalidSize)?;
+
+        // Open shmem
+        let shmem = config.open_existing(shmem_id, buffer_size)?;
+
+        let mut consumer = Consumer::join(shmem.get_mut_ptr())?;
+        consumer.shmem = Some(shmem);
+
+        Ok(consumer)
+    }
+
+    /// Unlinks the shared memory object when this endpoint is dropped,
+    /// if it created the object. Has no effect for endpoints joined
+    /// to an existing object or to a caller-provided buffer.
+    pub fn set_unlink_on_drop(&mut self, unlink: bool) {
+        if let Some(shmem) = self.shmem.as_mut() {
+            shmem.set_unlink_on_drop(unlink);
+        }
+    }
+}
+
+impl<M: ChannelMode<T>, T, const N: usize> Consumer<M, T, N> {
+    const MODULO_MASK: usize = N - 1;
+
+    /// Joins an existing MPSC channel backed by `buffer`.
+    ///
+    /// # Safety
+    /// This must point to a buffer of proper size and alignment.
+    ///
+    /// In LocalMode, must point to a region allocated by an Arc with the strong count already incremented!
+    pub unsafe fn join(
+        buffer: *mut u8,
+    ) -> Result<Consumer<M, T, N>, QueError> {
+        assert!(
+            N > 0 && N.is_power_of_two(),
+            "Capacity must be a power of two"
+        );
+        assert!((buffer as usize).is_multiple_of(128), "unaligned");
+
+        let spsc: *mut Channel<M, Slot<T>, N> = buffer.cast();
+
+        // Check magic
+        let magic = (*spsc).magic.load(Ordering::Acquire);
+        let capacity = (*spsc).capacity.load(Ordering::Acquire);
+        if magic == MAGIC {
+            // Check capacity
+            if capacity != N {
+                return Err(QueError::IncorrectCapacity(capacity));
+            }
+
+            (*spsc).check_fingerprint(0)?;
+
+            // Assume channel is empty upon joining
+            let new_head = (*spsc).tail.load(Ordering::Acquire);
+            (*spsc)
+                .head
+                .store(new_head, Ordering::Release);
+
+            if M::BACKED_BY_ARCC {
+                unsafe {
+                    Arc::increment_strong_count(spsc);
+                }
+            }
+
+            // Successful join if magic and capacity is correct
+            Ok(Consumer {
+                spsc: NonNull::new_unchecked(spsc),
+                shmem: None,
+                head: new_head,
+                items_since_last_sync: 0,
+                last_producer_heartbeat: (*spsc)
+                    .producer_heartbeat
+                    .load(Ordering::Acquire),
+            })
+        } else if magic == 0 {
+            // Technically could be corrupted but uninitialized
+            // is most likely explanation
+            Err(QueError::Uninitialized)
+        } else {
+            // Magic is not MAGIC and not zero
+            Err(QueError::CorruptionDetected)
+        }
+    }
+
+    /// Attempts to read the next element. Returns `None` if the next
+    /// position has not been published yet, even if later positions
+    /// were.
+    pub fn pop(&mut self) -> Option<T> {
+        let value = unsafe {
+            let slot = (*self.spsc.as_ptr())
+                .buffer
+                .as_ptr()
+                .add(self.head & Self::MODULO_MASK);
+            if (*slot).seq.load(Ordering::Acquire) != self.head + 1 {
+                return None;
+            }
+            core::ptr::read(addr_of!((*slot).value))
+        };
+
+        self.head += 1;
+        self.items_since_last_sync += 1;
+        self.maybe_sync();
+        Some(value)
+    }
+
+    /// Reads the next element, waiting for a producer if the consumer
+    /// is caught up.
+    ///
+    /// Spins briefly and then parks on a futex in the channel header,
+    /// so this also works across processes.
+    pub fn pop_blocking(&mut self) -> T {
+        loop {
+            // Without a deadline we can only return with a value
+            if let Some(value) = self.pop_until(None) {
+                return value;
+            }
+        }
+    }
+
+    /// Reads the next element, waiting up to `timeout` for a producer.
+    /// Returns `None` if nothing was published in time.
+    pub fn pop_timeout(&mut self, timeout: Duration) -> Option<T> {
+        self.pop_until(Instant::now().checked_add(timeout))
+    }
+
+    fn pop_until(&mut self, deadline: Option<Instant>) -> Option<T> {
+        if let Some(value) = self.pop() {
+            return Some(value);
+        }
+
+        // Release everything we've read so far; producers may be
+        // parked waiting on exactly this space.
+        self.publish_head();
+
+        let spsc = unsafe { &*self.spsc.as_ptr() };
+        spsc.consumer_notify
+            .wait_until(deadline, || self.pop())
+    }
+
+    /// Increments the consumer heartbeat.
+    ///
+    /// Can be read by producers to see that the consumer is still
+    /// online if done periodically.
+    pub fn beat(&self) {
+        unsafe {
+            (*self.spsc.as_ptr())
+                .consumer_heartbeat
+                .fetch_add(1, Ordering::Release);
+        }
+    }
+
+    /// Checks if any producer has incremented the heartbeat since last
+    /// called. Can be used by the consumer to see if producers are
+    /// still online if done periodically.
+    pub fn producer_heartbeat(&mut self) -> bool {
+        let heartbeat = unsafe {
+            (*self.spsc.as_ptr())
+                .producer_heartbeat
+                .load(Ordering::Acquire)
+        };
+
+        if heartbeat != self.last_producer_heartbeat {
+            self.last_producer_heartbeat = heartbeat;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns pointer to inner padding.
+    ///
+    /// User is responsible for safe usage.
+    ///
+    /// Unsynchronized: prefer [metadata](Self::metadata) for values
+    /// that change while the channel is in use.
+    ///
+    /// Byte array is 128 byte aligned.
+    pub fn get_padding_ptr(&self) -> NonNull<[u8; 112]> {
+        unsafe {
+            NonNull::new_unchecked(
+                self.spsc.cast::<u8>().as_ptr().add(512),
+            )
+            .cast()
+        }
+    }
+
+    /// Returns a typed, versioned view of the metadata region (the
+    /// same bytes as [get_padding_ptr](Self::get_padding_ptr)).
+    ///
+    /// Fails to compile if `U` is larger than 112 bytes.
+    pub fn metadata<U: Pod>(&self) -> Metadata<'_, U> {
+        unsafe { Channel::metadata(self.spsc) }
+    }
+
+    #[inline(always)]
+    fn maybe_sync(&mut self) {
+        if self.items_since_last_sync >= burst_amount::<N>() {
+            self.publish_head();
+        }
+    }
+
+    /// Publishes the local head, waking parked producers.
+    #[inline(always)]
+    fn publish_head(&mut self) {
+        self.items_since_last_sync = 0;
+        unsafe {
+            let spsc = &*self.spsc.as_ptr();
+            spsc.head
+                .store(self.head, Ordering::Release);
+            spsc.producer_notify.notify();
+        }
+    }
+}
+
+impl<M: ChannelMode<T>, T, const N: usize> Drop for Consumer<M, T, N> {
+    fn drop(&mut self) {
+        // LocalMode is backed by arc
+        if M::BACKED_BY_ARCC {
+            unsafe { drop(Arc::from_raw(self.spsc.as_ptr())) }
+        }
+    }
+}
@@ -0,0 +1,159 @@
+//! Loom permutation tests for the MPSC queue (`ShmemMode`, stack buffer).
+//!
+//! See `lossless::loom_tests` for why `LocalMode` is not used here.
+//!
+//! ```text
+//! cargo test -p que --features loom mpsc::loom_tests -- --test-threads=1
+//! ```
+
+use std::mem::size_of;
+
+use loom::thread;
+
+use super::{consumer::Consumer, producer::Producer, Slot, MAGIC};
+use crate::{Channel, ShmemMode};
+
+macro_rules! channel_storage {
+    ($name:ident, $n:literal) => {
+        #[repr(C, align(128))]
+        struct $name([u8; size_of::<Channel<ShmemMode, Slot<u64>, $n>>()]);
+
+        impl $name {
+            fn new_zeroed() -> Self {
+                Self([0; size_of::<Channel<ShmemMode, Slot<u64>, $n>>()])
+            }
+
+            fn prepare(&mut self) -> *mut u8 {
+                let ptr = self.0.as_mut_ptr();
+                unsafe {
+                    Channel::<ShmemMode, Slot<u64>, $n>::loom_write_fresh_at(
+                        ptr,
+                        MAGIC,
+                        core::array::from_fn(|_| Slot::new()),
+                    );
+                }
+                ptr
+            }
+        }
+    };
+}
+
+channel_storage!(ChannelStorage2, 2);
+channel_storage!(ChannelStorage4, 4);
+
+fn drain_all<const N: usize>(
+    consumer: &mut Consumer<ShmemMode, u64, N>,
+    expected_len: usize,
+) -> Vec<u64> {
+    let mut out = Vec::with_capacity(expected_len);
+    while out.len() < expected_len {
+        if let Some(v) = consumer.pop() {
+            out.push(v);
+        } else {
+            thread::yield_now();
+        }
+    }
+    out
+}
+
+fn push_retry<const N: usize>(
+    producer: &mut Producer<ShmemMode, u64, N>,
+    value: u64,
+) {
+    while producer.push(value).is_err() {
+        thread::yield_now();
+    }
+}
+
+/// All of `0..len` arrived and `0` arrived before `1`.
+fn assert_merged(got: &[u64], len: u64) {
+    let mut sorted = got.to_vec();
+    sorted.sort_unstable();
+    assert_eq!(sorted, (0..len).collect::<Vec<_>>());
+
+    let position = |x| got.iter().position(|&v| v == x);
+    assert!(position(0) < position(1));
+}
+
+#[test]
+fn loom_mpsc_two_producers() {
+    loom::model(|| {
+        let mut storage = ChannelStorage4::new_zeroed();
+        let ptr = storage.prepare();
+
+        let mut producer_a = unsafe {
+            Producer::<ShmemMode, u64, 4>::join(ptr).unwrap()
+        };
+        let mut producer_b = unsafe {
+            Producer::<ShmemMode, u64, 4>::join(ptr).unwrap()
+        };
+        let mut consumer = unsafe {
+            Consumer::<ShmemMode, u64, 4>::join(ptr).unwrap()
+        };
+
+        let a = thread::spawn(move || {
+            for i in 0u64..2 {
+                push_retry(&mut producer_a, i);
+            }
+        });
+        let b = thread::spawn(move || {
+            push_retry(&mut producer_b, 2);
+        });
+
+        let got = drain_all(&mut consumer, 3);
+        assert_merged(&got, 3);
+
+        a.join().unwrap();
+        b.join().unwrap();
+    });
+}
+
+#[test]
+fn loom_mpsc_wraparound() {
+    loom::model(|| {
+        let mut storage = ChannelStorage2::new_zeroed();
+        let ptr = storage.prepare();
+
+        let mut producer_a = unsafe {
+            Producer::<ShmemMode, u64, 2>::join(ptr).unwrap()
+        };
+        let mut producer_b = unsafe {
+            Producer::<ShmemMode, u64, 2>::join(ptr).unwrap()
+        };
+        let mut consumer = unsafe {
+            Consumer::<ShmemMode, u64, 2>::join(ptr).unwrap()
+        };
+
+        producer_a.push(0).unwrap();
+
+        // Position 2 reuses the slot of position 0
+        let b = thread::spawn(move || {
+            push_retry(&mut producer_b, 1);
+            push_retry(&mut producer_b, 2);
+        });
+
+        let got = drain_all(&mut consumer, 3);
+        assert_eq!(got, vec![0, 1, 2]);
+
+        b.join().unwrap();
+    });
+}
+
+#[test]
+fn loom_mpsc_no_false_some_on_empty() {
+    loom::model(|| {
+        let mut storage = ChannelStorage4::new_zeroed();
+        let ptr = storage.prepare();
+
+        let _producer = unsafe {
+            Producer::<ShmemMode, u64, 4>::join(ptr).unwrap()
+        };
+        let mut consumer = unsafe {
+            Consumer::<ShmemMode, u64, 4>::join(ptr).unwrap()
+        };
+
+        for _ in 0..4 {
+            assert_eq!(consumer.pop(), None);
+        }
+    });
+}
@@ -0,0 +1,203 @@
+//! Multi-producer single-consumer lossless channel.
+//!
+//! Shares the [Channel] header with the other channel kinds, but the
+//! shared `tail` is the next position to be claimed rather than the
+//! last published one. Producers claim a position by advancing `tail`
+//! atomically and publish it by stamping the slot's sequence number, so
+//! a slow producer only holds up the consumer at its own slot.
+
+pub mod consumer;
+pub mod producer;
+
+use std::{mem::MaybeUninit, sync::Arc};
+
+use consumer::Consumer;
+use producer::Producer;
+
+use crate::{atomic_compat::AtomicUsize, Channel, LocalMode};
+
+/// Marks a channel initialized as MPSC. Differs from [crate::MAGIC]
+/// since the buffer holds [Slot]s rather than bare elements.
+pub const MAGIC: u64 = u64::from_le_bytes(*b"TEMPMPSC");
+
+/// A buffer element tagged with the position it was published at.
+#[repr(C)]
+pub struct Slot<T> {
+    /// `position + 1` once the value for `position` is written. Zero
+    /// (never published) is a valid initial state.
+    seq: AtomicUsize,
+    value: T,
+}
+
+impl<T> Slot<T> {
+    #[cfg(all(loom, test))]
+    pub(crate) fn new() -> Self
+    where
+        T: bytemuck::Zeroable,
+    {
+        Slot {
+            seq: AtomicUsize::new(0),
+            value: T::zeroed(),
+        }
+    }
+}
+
+pub fn mpsc_pair<T: Send, const N: usize>(
+) -> (Producer<LocalMode, T, N>, Consumer<LocalMode, T, N>) {
+    let arc_uninit =
+        Arc::<Channel<LocalMode, Slot<T>, N>>::new_uninit();
+    let ptr: *mut MaybeUninit<Channel<LocalMode, Slot<T>, N>> =
+        Arc::into_raw(arc_uninit).cast_mut();
+
+    unsafe {
+        *ptr = core::mem::zeroed();
+    }
+
+    let producer = unsafe {
+        Producer::join_or_initialize_in_(ptr.cast()).unwrap()
+    };
+    let consumer = unsafe { Consumer::join(ptr.cast()).unwrap() };
+
+    unsafe {
+        Arc::decrement_strong_count(ptr);
+    }
+
+    (producer, consumer)
+}
+
+const fn burst_amount<const N: usize>() -> usize {
+    // Consumer releases up to 1/4 of the buffer at a time
+    const BURST_DENOM: usize = 4;
+
+    let x = N / BURST_DENOM;
+
+    if x == 0 {
+        1
+    } else {
+        x
+    }
+}
+
+// Not run with `feature = "loom"`, see `lossless::tests`. Loom coverage
+// is in `loom_tests` (`ShmemMode`, stack buffer, `loom::thread`).
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use crate::error::QueError;
+
+    #[test]
+    fn test_push_pop_multiple() {
+        let (mut producer, mut consumer) = mpsc_pair::<u64, 16>();
+        assert_eq!(consumer.pop(), None);
+
+        // Published on push, no sync needed
+        producer.push(69).unwrap();
+        producer.push(70).unwrap();
+        assert_eq!(consumer.pop(), Some(69));
+        assert_eq!(consumer.pop(), Some(70));
+        assert_eq!(consumer.pop(), None);
+    }
+
+    #[test]
+    fn test_push_pop_full() {
+        let (mut producer, mut consumer) = mpsc_pair::<u64, 4>();
+        let mut producer2 = producer.clone();
+
+        producer.push(1).unwrap();
+        producer2.push(2).unwrap();
+        producer.push(3).unwrap();
+        producer2.push(4).unwrap();
+        assert!(matches!(producer.push(5), Err(QueError::Full)));
+        assert!(matches!(producer2.push(5), Err(QueError::Full)));
+
+        assert_eq!(consumer.pop(), Some(1));
+        producer2.push(5).unwrap();
+
+        assert_eq!(consumer.pop(), Some(2));
+        assert_eq!(consumer.pop(), Some(3));
+        assert_eq!(consumer.pop(), Some(4));
+        assert_eq!(consumer.pop(), Some(5));
+        assert_eq!(consumer.pop(), None);
+    }
+
+    #[test]
+    fn test_push_never_waits_on_stopped_consumer() {
+        const PRODUCERS: usize = 4;
+
+        let (producer, consumer) = mpsc_pair::<u64, 16>();
+
+        // Producers race for the last slots; whoever loses must see
+        // `Full` rather than park on a consumer that never pops
+        let handles: Vec<_> = (0..PRODUCERS)
+            .map(|_| {
+                let mut producer = producer.clone();
+                std::thread::spawn(move || {
+                    let mut pushed = 0;
+                    while producer.push(0).is_ok() {
+                        pushed += 1;
+                    }
+                    pushed
+                })
+            })
+            .collect();
+
+        let pushed: usize = handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .sum();
+        assert_eq!(pushed, 16);
+        drop(consumer);
+    }
+
+    #[test]
+    fn test_many_producers() {
+        const PRODUCERS: u64 = 4;
+        const PER_PRODUCER: u64 = 10_000;
+
+        let (producer, mut consumer) = mpsc_pair::<(u64, u64), 64>();
+
+        let handles: Vec<_> = (0..PRODUCERS)
+            .map(|id| {
+                let mut producer = producer.clone();
+                std::thread::spawn(move || {
+                    for i in 0..PER_PRODUCER {
+                        while producer.push((id, i)).is_err() {
+                            std::hint::spin_loop();
+                        }
+                    }
+                })
+            })
+            .collect();
+        drop(producer);
+
+        // Each producer's values arrive in order
+        let mut next = [0; PRODUCERS as usize];
+        let mut received = 0;
+        while received < PRODUCERS * PER_PRODUCER {
+            if let Some((id, i)) = consumer.pop() {
+                assert_eq!(next[id as usize], i);
+                next[id as usize] += 1;
+                received += 1;
+            }
+        }
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(consumer.pop(), None);
+    }
+
+    #[test]
+    fn test_detect_offline_consumer() {
+        let (mut producer, consumer) = mpsc_pair::<u64, 4>();
+        assert!(!producer.consumer_heartbeat());
+
+        consumer.beat();
+        assert!(producer.consumer_heartbeat());
+
+        assert!(!producer.consumer_heartbeat());
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests;
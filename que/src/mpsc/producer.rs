@@ -0,0 +1,392 @@
+use std::{
+    ptr::{addr_of_mut, NonNull},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use bytemuck::{AnyBitPattern, Pod};
+
+use crate::{
+    atomic_compat::Ordering,
+    error::QueError,
+    shmem::{Shmem, ShmemConfig},
+    Channel, ChannelMode, LocalMode, Metadata, ShmemMode,
+};
+
+use super::{Slot, MAGIC};
+
+/// One of possibly many producers of an MPSC channel.
+///
+/// Unlike [crate::lossless::producer::Producer], every push is
+/// published immediately: the value becomes visible to the consumer as
+/// soon as its slot is stamped, so there is no `sync`.
+#[repr(C, align(128))]
+pub struct Producer<M: ChannelMode<T>, T, const N: usize> {
+    spsc: NonNull<Channel<M, Slot<T>, N>>,
+    /// Last head loaded from the channel. Only reloaded when the channel
+    /// appears full, to stay off the consumer's cache line.
+    head: usize,
+    last_consumer_heartbeat: usize,
+    /// Mapping backing the channel, if joined through shmem.
+    shmem: Option<Shmem>,
+}
+
+unsafe impl<M: ChannelMode<T>, T, const N: usize> Send
+    for Producer<M, T, N>
+{
+}
+
+impl<T: AnyBitPattern, const N: usize> Producer<ShmemMode, T, N> {
+    /// Joins or creates an MPSC channel backed by shared memory as a
+    /// producer. Any number of producers may join the same channel.
+    ///
+    /// # Safety
+    /// The shared memory region must only ever be mapped as an MPSC
+    /// `Channel` with the same `T` and `N`.
+    pub unsafe fn join_or_create_shmem(
+        shmem_id: &str,
+        #[cfg(target_os = "linux")] config: impl Into<ShmemConfig>,
+    ) -> Result<Producer<ShmemMode, T, N>, QueError> {
+        #[cfg(not(target_os = "linux"))]
+        let config = ShmemConfig::default();
+        #[cfg(target_os = "linux")]
+        let config: ShmemConfig = config.into();
+
+        // Calculate buffer size.
+        // If using huge pages, we must uplign to page size.
+        let buffer_size: i64 = config
+            .page_size()
+            .mem_size(core::mem::size_of::<
+                Channel<ShmemMode, Slot<T>, N>,
+            >())
+            .try_into()
+            .map_err(|_| QueError::InvalidSize)?;
+
+        // Open or create shmem
+        let shmem = config.open_or_create(shmem_id, buffer_size)?;
+
+        let mut producer =
+            Self::join_or_initialize_in_(shmem.get_mut_ptr())?;
+        producer.shmem = Some(shmem);
+
+        Ok(producer)
+    }
+
+    /// Initializes an MPSC channel backed by `buffer` and joins as a
+    /// producer.
+    ///
+    /// # Safety
+    /// This must point to a buffer of proper size and alignment.
+    pub unsafe fn join_or_initialize_in(
+        buffer: *mut u8,
+    ) -> Result<Producer<ShmemMode, T, N>, QueError> {
+        Self::join_or_initialize_in_(buffer)
+    }
+
+    /// Joins an existing MPSC channel backed by `buffer` as a producer.
+    ///
+    /// # Safety
+    /// This must point to a buffer of proper size and alignment.
+    pub unsafe fn join(
+        buffer: *mut u8,
+    ) -> Result<Producer<ShmemMode, T, N>, QueError> {
+        Self::join_(buffer)
+    }
+
+    /// Unlinks the shared memory object when this endpoint is dropped,
+    /// if it created the object. Has no effect for endpoints joined
+    /// to an existing object or to a caller-provided buffer.
+    pub fn set_unlink_on_drop(&mut self, unlink: bool) {
+        if let Some(shmem) = self.shmem.as_mut() {
+            shmem.set_unlink_on_drop(unlink);
+        }
+    }
+}
+
+impl<M: ChannelMode<T>, T, const N: usize> Producer<M, T, N> {
+    pub const MODULO_MASK: usize = N - 1;
+
+    pub(crate) unsafe fn join_or_initialize_in_(
+        buffer: *mut u8,
+    ) -> Result<Producer<M, T, N>, QueError> {
+        assert!(
+            N > 0 && N.is_power_of_two(),
+            "Capacity must be a power of two"
+        );
+        assert!((buffer as usize).is_multiple_of(128), "unaligned");
+
+        let spsc: *mut Channel<M, Slot<T>, N> = buffer.cast();
+
+        // Check magic
+        let magic = (*spsc).magic.load(Ordering::Acquire);
+        if magic == MAGIC {
+            (*spsc)
+                .producer_heartbeat
+                .fetch_add(1, Ordering::Release);
+            Self::join_(buffer)
+        } else if magic == 0 {
+            // Slot sequence numbers are valid when zeroed
+            (*spsc).tail.store(0, Ordering::Release);
+            (*spsc).head.store(0, Ordering::Release);
+            (*spsc)
+                .consumer_heartbeat
+                .store(0, Ordering::Release);
+            (*spsc)
+                .producer_heartbeat
+                .store(0, Ordering::Release);
+            (*spsc)
+                .capacity
+                .store(N, Ordering::Release);
+            (*spsc).store_fingerprint(0);
+            (*spsc)
+                .magic
+                .store(MAGIC, Ordering::Release);
+            Self::join_(buffer)
+        } else {
+            // Magic is not MAGIC and not zero
+            Err(QueError::CorruptionDetected)
+        }
+    }
+
+    pub(crate) unsafe fn join_(
+        buffer: *mut u8,
+    ) -> Result<Producer<M, T, N>, QueError> {
+        assert!(
+            N > 0 && N.is_power_of_two(),
+            "Capacity must be a power of two"
+        );
+        assert!((buffer as usize).is_multiple_of(128), "unaligned");
+
+        let spsc: *mut Channel<M, Slot<T>, N> = buffer.cast();
+
+        let magic = (*spsc).magic.load(Ordering::Acquire);
+        let capacity = (*spsc).capacity.load(Ordering::Acquire);
+        if magic == MAGIC {
+            if capacity != N {
+                return Err(QueError::IncorrectCapacity(capacity));
+            }
+
+            (*spsc).check_fingerprint(0)?;
+
+            if M::BACKED_BY_ARCC {
+                unsafe {
+                    Arc::increment_strong_count(spsc);
+                }
+            }
+
+            // Successful join if magic and capacity is correct
+            Ok(Producer {
+                spsc: NonNull::new(spsc).unwrap(),
+                shmem: None,
+                head: (*spsc).head.load(Ordering::Acquire),
+                last_consumer_heartbeat: (*spsc)
+                    .consumer_heartbeat
+                    .load(Ordering::Acquire),
+            })
+        } else if magic == 0 {
+            // Technically could be corrupted but uninitialized
+            // is most likely explanation
+            Err(QueError::Uninitialized)
+        } else {
+            // Magic is not MAGIC and not zero
+            Err(QueError::CorruptionDetected)
+        }
+    }
+
+    /// Attempts to write a new element to the channel. If full, returns
+    /// [QueError::Full].
+    ///
+    /// The position is claimed with a compare-exchange on the shared
+    /// tail that only succeeds while the slot is free, so this never
+    /// waits on the consumer.
+    #[inline(always)]
+    pub fn push(&mut self, value: T) -> Result<(), QueError> {
+        let position = self.try_claim().ok_or(QueError::Full)?;
+        self.write(position, value);
+
+        Ok(())
+    }
+
+    /// Writes a new element to the channel, waiting for the consumer to
+    /// make room if the channel is full.
+    ///
+    /// Spins briefly and then parks on a futex in the channel header,
+    /// so this also works across processes.
+    pub fn push_blocking(&mut self, value: T) {
+        let position = self.claim_blocking();
+        self.write(position, value);
+    }
+
+    /// Writes a new element to the channel, waiting up to `timeout` for
+    /// the consumer to make room. If the channel is still full after
+    /// `timeout`, returns [QueError::Full].
+    pub fn push_timeout(
+        &mut self,
+        value: T,
+        timeout: Duration,
+    ) -> Result<(), QueError> {
+        let position = match self.try_claim() {
+            Some(position) => position,
+            None => {
+                let deadline = Instant::now().checked_add(timeout);
+                let spsc = unsafe { &*self.spsc.as_ptr() };
+                spsc.producer_notify
+                    .wait_until(deadline, || self.try_claim())
+                    .ok_or(QueError::Full)?
+            }
+        };
+        self.write(position, value);
+
+        Ok(())
+    }
+
+    /// Claims the next position if its slot is free, or returns `None`
+    /// if the channel is full.
+    #[inline(always)]
+    fn try_claim(&mut self) -> Option<usize> {
+        let spsc = unsafe { &*self.spsc.as_ptr() };
+        let mut tail = spsc.tail.load(Ordering::Relaxed);
+        loop {
+            if tail >= self.head + N {
+                self.head = spsc.head.load(Ordering::Acquire);
+                if tail >= self.head + N {
+                    return None;
+                }
+            }
+
+            match spsc.tail.compare_exchange_weak(
+                tail,
+                tail + 1,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Some(tail),
+                Err(current) => tail = current,
+            }
+        }
+    }
+
+    /// Claims the next position unconditionally, waiting for the
+    /// consumer to release the previous lap of its slot.
+    #[inline(always)]
+    fn claim_blocking(&mut self) -> usize {
+        let spsc = unsafe { &*self.spsc.as_ptr() };
+        let position = spsc
+            .tail
+            .fetch_add(1, Ordering::Relaxed);
+
+        if position >= self.head + N {
+            self.head = spsc.head.load(Ordering::Acquire);
+            if position >= self.head + N {
+                self.head = spsc
+                    .producer_notify
+                    .wait_until(None, || {
+                        let head = spsc.head.load(Ordering::Acquire);
+                        (position < head + N).then_some(head)
+                    })
+                    .unwrap();
+            }
+        }
+
+        position
+    }
+
+    /// Writes `value` to the claimed `position` and publishes it.
+    #[inline(always)]
+    fn write(&mut self, position: usize, value: T) {
+        unsafe {
+            let slot = (*self.spsc.as_ptr())
+                .buffer
+                .as_mut_ptr()
+                .add(position & Self::MODULO_MASK);
+            core::ptr::write(addr_of_mut!((*slot).value), value);
+            (*slot)
+                .seq
+                .store(position + 1, Ordering::Release);
+        }
+
+        unsafe { &*self.spsc.as_ptr() }
+            .consumer_notify
+            .notify();
+    }
+
+    /// Increments the producer heartbeat, which is shared by all
+    /// producers.
+    ///
+    /// Can be read by the consumer to see that a producer is still
+    /// online if done periodically.
+    pub fn beat(&self) {
+        unsafe {
+            (*self.spsc.as_ptr())
+                .producer_heartbeat
+                .fetch_add(1, Ordering::Release);
+        }
+    }
+
+    /// Checks if a consumer has incremented its heartbeat since last
+    /// called. Can be used by the producer to see if the consumer is
+    /// still online if done periodically.
+    pub fn consumer_heartbeat(&mut self) -> bool {
+        let heartbeat = unsafe {
+            (*self.spsc.as_ptr())
+                .consumer_heartbeat
+                .load(Ordering::Acquire)
+        };
+
+        if heartbeat != self.last_consumer_heartbeat {
+            self.last_consumer_heartbeat = heartbeat;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns pointer to inner padding.
+    ///
+    /// User is responsible for safe usage.
+    ///
+    /// Unsynchronized: prefer [metadata](Self::metadata) for values
+    /// that change while the channel is in use.
+    ///
+    /// Byte array is 128 byte aligned.
+    pub fn get_padding_ptr(&self) -> NonNull<[u8; 112]> {
+        unsafe {
+            NonNull::new_unchecked(
+                self.spsc.cast::<u8>().as_ptr().add(512),
+            )
+            .cast()
+        }
+    }
+
+    /// Returns a typed, versioned view of the metadata region (the
+    /// same bytes as [get_padding_ptr](Self::get_padding_ptr)).
+    ///
+    /// Fails to compile if `U` is larger than 112 bytes.
+    pub fn metadata<U: Pod>(&self) -> Metadata<'_, U> {
+        unsafe { Channel::metadata(self.spsc) }
+    }
+}
+
+impl<T: Send, const N: usize> Clone for Producer<LocalMode, T, N> {
+    fn clone(&self) -> Self {
+        unsafe {
+            Arc::increment_strong_count(self.spsc.as_ptr());
+        }
+
+        Producer {
+            spsc: self.spsc,
+            shmem: None,
+            head: self.head,
+            last_consumer_heartbeat: self.last_consumer_heartbeat,
+        }
+    }
+}
+
+impl<M: ChannelMode<T>, T, const N: usize> Drop for Producer<M, T, N> {
+    fn drop(&mut self) {
+        // LocalMode is backed by arc
+        if M::BACKED_BY_ARCC {
+            unsafe { drop(Arc::from_raw(self.spsc.as_ptr())) }
+        }
+    }
+}
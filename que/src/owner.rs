@@ -0,0 +1,315 @@
+//! Processes owning the producer and consumer ends of a channel,
+//! recorded in the header so that the owner of a shared-memory channel
+//! can be found (and found dead) by other processes.
+//!
+//! An owner is identified by its PID and its start time from
+//! `/proc/<pid>/stat`, so that a recycled PID is not mistaken for the
+//! process that joined.
+
+use std::{
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    atomic_compat::{AtomicU64, Ordering},
+    error::QueError,
+};
+
+/// State of one end of a channel, see [Channel::inspect_liveness].
+///
+/// [Channel::inspect_liveness]: crate::Channel::inspect_liveness
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OwnerState {
+    /// No process holds this end.
+    Vacant,
+    /// Held by a running process. Off Linux, where there is no `/proc`
+    /// to check, every recorded owner is reported alive.
+    Alive { pid: u32 },
+    /// Held by a process that has exited (or whose PID was recycled).
+    Dead { pid: u32 },
+}
+
+/// Owners of both ends of a channel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Liveness {
+    pub producer: OwnerState,
+    pub consumer: OwnerState,
+}
+
+#[repr(C, align(128))]
+pub(crate) struct Owners {
+    pub(crate) producer: Owner,
+    pub(crate) consumer: Owner,
+}
+
+#[repr(C)]
+pub(crate) struct Owner {
+    /// PID of the owning process, 0 if vacant. While a process records
+    /// itself, its PID with [Owner::CLAIMING] set.
+    pid: AtomicU64,
+    /// Start time of the owning process, 0 if unknown.
+    start_time: AtomicU64,
+}
+
+impl Owners {
+    #[cfg(all(loom, test))]
+    pub(crate) fn new() -> Self {
+        Self {
+            producer: Owner::new(),
+            consumer: Owner::new(),
+        }
+    }
+}
+
+/// A recorded owner, read once any claim in progress has settled.
+enum Recorded {
+    Vacant,
+    Owner {
+        pid: u64,
+        start_time: u64,
+    },
+    /// A claim whose process exited (or stalled for
+    /// [Owner::CLAIM_TIMEOUT]) before it finished recording itself.
+    Abandoned {
+        raw: u64,
+    },
+}
+
+impl Owner {
+    /// Set in `pid` while a process is recording itself.
+    const CLAIMING: u64 = 1 << 63;
+
+    /// How long a claim may stay in progress before it is treated as
+    /// abandoned, even if its PID is still running (it may have been
+    /// recycled).
+    const CLAIM_TIMEOUT: Duration = Duration::from_millis(100);
+
+    #[cfg(all(loom, test))]
+    fn new() -> Self {
+        Self {
+            pid: AtomicU64::new(0),
+            start_time: AtomicU64::new(0),
+        }
+    }
+
+    /// Records this process as the owner. Joining again from the owning
+    /// process succeeds; otherwise a recorded owner fails with
+    /// [QueError::OwnerAlive] or [QueError::OwnerDead], the latter of
+    /// which can be cleared with [Owner::evict_dead].
+    pub(crate) fn claim(&self) -> Result<(), QueError> {
+        let (pid, start_time) = current();
+        loop {
+            match self.load() {
+                Recorded::Vacant => {
+                    if self
+                        .pid
+                        .compare_exchange(
+                            0,
+                            Self::CLAIMING | pid,
+                            Ordering::Acquire,
+                            Ordering::Relaxed,
+                        )
+                        .is_err()
+                    {
+                        continue;
+                    }
+                    self.start_time
+                        .store(start_time, Ordering::Relaxed);
+
+                    // Fails if the claim was evicted as abandoned in
+                    // the meantime
+                    if self
+                        .pid
+                        .compare_exchange(
+                            Self::CLAIMING | pid,
+                            pid,
+                            Ordering::Release,
+                            Ordering::Relaxed,
+                        )
+                        .is_ok()
+                    {
+                        return Ok(());
+                    }
+                }
+                Recorded::Owner {
+                    pid: owner,
+                    start_time: owner_start,
+                } if owner == pid && owner_start == start_time => {
+                    return Ok(())
+                }
+                Recorded::Owner {
+                    pid: owner,
+                    start_time: owner_start,
+                } => {
+                    let pid = owner as u32;
+                    return Err(if is_alive(owner, owner_start) {
+                        QueError::OwnerAlive { pid }
+                    } else {
+                        QueError::OwnerDead { pid }
+                    });
+                }
+                Recorded::Abandoned { raw } => {
+                    return Err(QueError::OwnerDead {
+                        pid: (raw & !Self::CLAIMING) as u32,
+                    })
+                }
+            }
+        }
+    }
+
+    /// Clears the owner if this process holds it.
+    pub(crate) fn release(&self) {
+        let (pid, _) = current();
+        let _ = self.pid.compare_exchange(
+            pid,
+            0,
+            Ordering::Release,
+            Ordering::Relaxed,
+        );
+    }
+
+    /// Clears a dead owner so the channel can be claimed again. Fails
+    /// with [QueError::OwnerAlive] if the owner is still running.
+    pub(crate) fn evict_dead(&self) -> Result<(), QueError> {
+        loop {
+            let owner = match self.load() {
+                Recorded::Vacant => return Ok(()),
+                Recorded::Owner {
+                    pid: owner,
+                    start_time: owner_start,
+                } => {
+                    if is_alive(owner, owner_start) {
+                        let (pid, start_time) = current();
+                        if owner == pid && owner_start == start_time {
+                            return Ok(());
+                        }
+                        return Err(QueError::OwnerAlive {
+                            pid: owner as u32,
+                        });
+                    }
+                    owner
+                }
+                Recorded::Abandoned { raw } => raw,
+            };
+            if self
+                .pid
+                .compare_exchange(
+                    owner,
+                    0,
+                    Ordering::AcqRel,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+            {
+                return Ok(());
+            }
+        }
+    }
+
+    pub(crate) fn state(&self) -> OwnerState {
+        match self.load() {
+            Recorded::Vacant => OwnerState::Vacant,
+            Recorded::Owner { pid, start_time } => {
+                if is_alive(pid, start_time) {
+                    OwnerState::Alive { pid: pid as u32 }
+                } else {
+                    OwnerState::Dead { pid: pid as u32 }
+                }
+            }
+            Recorded::Abandoned { raw } => OwnerState::Dead {
+                pid: (raw & !Self::CLAIMING) as u32,
+            },
+        }
+    }
+
+    /// Overwrites the owner, e.g. with a process that has exited.
+    #[cfg(all(test, not(loom)))]
+    pub(crate) fn record(&self, pid: u32, start_time: u64) {
+        self.start_time
+            .store(start_time, Ordering::Relaxed);
+        self.pid
+            .store(pid as u64, Ordering::Release);
+    }
+
+    /// Leaves a claim by `pid` in progress, as if it died while
+    /// recording itself.
+    #[cfg(all(test, not(loom)))]
+    pub(crate) fn record_claiming(&self, pid: u32) {
+        self.pid
+            .store(Self::CLAIMING | pid as u64, Ordering::Release);
+    }
+
+    /// Returns the recorded owner, waiting for a claim in progress to
+    /// finish. A claim is abandoned once its process is gone or after
+    /// [Owner::CLAIM_TIMEOUT].
+    fn load(&self) -> Recorded {
+        let mut deadline = None;
+        loop {
+            let raw = self.pid.load(Ordering::Acquire);
+            if raw == 0 {
+                return Recorded::Vacant;
+            }
+            if raw & Self::CLAIMING == 0 {
+                return Recorded::Owner {
+                    pid: raw,
+                    start_time: self.start_time.load(Ordering::Relaxed),
+                };
+            }
+
+            let deadline = *deadline.get_or_insert_with(|| {
+                Instant::now() + Self::CLAIM_TIMEOUT
+            });
+            if !is_alive(raw & !Self::CLAIMING, 0)
+                || Instant::now() >= deadline
+            {
+                return Recorded::Abandoned { raw };
+            }
+            std::thread::yield_now();
+        }
+    }
+}
+
+/// PID and start time of this process.
+fn current() -> (u64, u64) {
+    static START_TIME: OnceLock<u64> = OnceLock::new();
+
+    let pid = std::process::id();
+    (
+        pid as u64,
+        *START_TIME.get_or_init(|| start_time(pid).unwrap_or(0)),
+    )
+}
+
+fn is_alive(pid: u64, recorded_start_time: u64) -> bool {
+    if cfg!(not(target_os = "linux")) {
+        return true;
+    }
+
+    match start_time(pid as u32) {
+        // Start time 0 was recorded by a writer that could not read it
+        Some(start_time) => {
+            recorded_start_time == 0
+                || start_time == recorded_start_time
+        }
+        None => false,
+    }
+}
+
+/// Reads the start time of `pid` (field 22 of `/proc/<pid>/stat`), or
+/// `None` if there is no such running process.
+fn start_time(pid: u32) -> Option<u64> {
+    let stat =
+        std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+
+    // Fields after the command name, which may contain spaces, start
+    // at the state (field 3)
+    let mut fields = stat
+        .rsplit_once(')')?
+        .1
+        .split_whitespace();
+    if matches!(fields.next()?, "Z" | "X") {
+        return None;
+    }
+    fields.nth(18)?.parse().ok()
+}
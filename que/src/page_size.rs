@@ -44,6 +44,17 @@ impl PageSize {
         }
     }
 
+    /// Returns the size of one page in bytes
+    pub fn bytes(&self) -> usize {
+        match self {
+            PageSize::Standard => PageSize::standard(),
+            #[cfg(target_os = "linux")]
+            PageSize::Huge => Self::HUGE,
+            #[cfg(target_os = "linux")]
+            PageSize::Gigantic => Self::GIGANTIC,
+        }
+    }
+
     /// Returns `true` if [PageSize::Huge]
     #[inline(always)]
     pub fn is_huge(&self) -> bool {
@@ -1,16 +1,23 @@
 use std::{
     ffi::{c_void, CString},
     num::NonZeroUsize,
-    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    os::{
+        fd::{
+            AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd,
+        },
+        unix::{ffi::OsStrExt, net::UnixStream},
+    },
+    path::{Path, PathBuf},
     ptr::NonNull,
+    time::{Duration, Instant},
 };
 
 use nix::{
     errno::Errno,
     fcntl::{open, OFlag},
-    libc::{c_char, munmap, shm_unlink, unlink, S_IRUSR, S_IWUSR},
+    libc::{munmap, shm_unlink, unlink, S_IRUSR, S_IWUSR},
     sys::{
-        mman::{shm_open, MapFlags, ProtFlags},
+        mman::{msync, shm_open, MapFlags, MsFlags, ProtFlags},
         stat::Mode,
     },
     unistd::ftruncate,
@@ -18,77 +25,332 @@ use nix::{
 
 use crate::page_size::PageSize;
 
-#[derive(Clone)]
-pub struct Shmem {
-    pub id: String,
-    pub(crate) size: i64,
-    pub fd: i32,
-    addr: NonNull<()>,
+/// How long to wait for an object created by another process to be
+/// sized. The creator may have died before sizing it.
+const SIZE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Where and how shared memory objects are created and mapped.
+///
+/// Objects on standard pages live in POSIX shm. Objects on huge or
+/// gigantic pages are files on a hugetlbfs mount, by default
+/// `/mnt/hugepages/` and `/mnt/gigantic/` as set up by
+/// `mount_huge_and_gigantic.sh`.
+///
+/// A [PageSize] converts into the default config for that page size.
+#[derive(Clone, Debug)]
+pub struct ShmemConfig {
     page_size: PageSize,
+    huge_mount: PathBuf,
+    gigantic_mount: PathBuf,
+    check_free_pages: bool,
 }
 
-impl Shmem {
+impl Default for ShmemConfig {
+    fn default() -> Self {
+        ShmemConfig::new(PageSize::Standard)
+    }
+}
+
+impl From<PageSize> for ShmemConfig {
+    fn from(page_size: PageSize) -> Self {
+        ShmemConfig::new(page_size)
+    }
+}
+
+impl ShmemConfig {
+    pub fn new(page_size: PageSize) -> ShmemConfig {
+        ShmemConfig {
+            page_size,
+            huge_mount: PathBuf::from("/mnt/hugepages"),
+            gigantic_mount: PathBuf::from("/mnt/gigantic"),
+            check_free_pages: true,
+        }
+    }
+
+    /// Sets the hugetlbfs mount used for [PageSize::Huge].
+    pub fn huge_mount(mut self, path: impl Into<PathBuf>) -> Self {
+        self.huge_mount = path.into();
+        self
+    }
+
+    /// Sets the hugetlbfs mount used for [PageSize::Gigantic].
+    pub fn gigantic_mount(mut self, path: impl Into<PathBuf>) -> Self {
+        self.gigantic_mount = path.into();
+        self
+    }
+
+    /// Whether to check the huge page pool before creating an object,
+    /// failing with [ShmemError::InsufficientHugePages] instead of a
+    /// bare `ENOMEM` from `mmap`. On by default.
+    pub fn check_free_pages(mut self, check: bool) -> Self {
+        self.check_free_pages = check;
+        self
+    }
+
+    /// Replaces the huge and gigantic mounts with hugetlbfs mounts
+    /// found in `/proc/mounts`, keeping the configured path for a page
+    /// size with no mount. Fails with [ShmemError::NoHugetlbfsMount]
+    /// if the selected page size has none.
+    #[cfg(target_os = "linux")]
+    pub fn discover(mut self) -> Result<Self, ShmemError> {
+        let mounts =
+            crate::hugetlb::mounts().map_err(ShmemError::from)?;
+        let find = |page_size: usize| {
+            mounts
+                .iter()
+                .find(|mount| mount.page_size == page_size)
+                .map(|mount| mount.path.clone())
+        };
+
+        if let Some(path) = find(PageSize::HUGE) {
+            self.huge_mount = path;
+        }
+        if let Some(path) = find(PageSize::GIGANTIC) {
+            self.gigantic_mount = path;
+        }
+        if self.mount().is_some()
+            && find(self.page_size.bytes()).is_none()
+        {
+            return Err(ShmemError::NoHugetlbfsMount {
+                page_size: self.page_size.bytes(),
+            });
+        }
+
+        Ok(self)
+    }
+
+    pub fn page_size(&self) -> PageSize {
+        self.page_size
+    }
+
+    /// The hugetlbfs mount objects are created on, or `None` for
+    /// standard pages.
+    pub fn mount(&self) -> Option<&Path> {
+        if self.page_size.is_gigantic() {
+            Some(&self.gigantic_mount)
+        } else if self.page_size.is_huge() {
+            Some(&self.huge_mount)
+        } else {
+            None
+        }
+    }
+
     /// Open or create a shmem
     ///
-    /// NOTE:
-    /// If using huge pages, expected at path /mnt/hugepages/ or
-    /// /mnt/gigantic/
+    /// An existing shmem is never resized: if its size differs from
+    /// `uplined_size`, this fails with [ShmemError::SizeMismatch]. An
+    /// empty shmem that is not sized within a second (its creator may
+    /// have died) fails the same way.
     pub fn open_or_create(
+        &self,
         id: &str,
         uplined_size: i64,
-        #[cfg(target_os = "linux")] page_size: PageSize,
     ) -> Result<Shmem, ShmemError> {
-        #[cfg(not(target_os = "linux"))]
-        let page_size = PageSize::Standard;
+        let mut deadline = None;
+        loop {
+            match self.create_exclusive(id, uplined_size) {
+                Err(ShmemError::AlreadyExists) => {}
+                res => return res,
+            }
+
+            match self.open_existing(id, uplined_size) {
+                // Unlinked since we tried to create it
+                Err(ShmemError::NotFound) => {}
+                // Created but not sized yet by another process
+                Err(e @ ShmemError::SizeMismatch { found: 0, .. }) => {
+                    if Instant::now()
+                        >= *deadline.get_or_insert_with(|| {
+                            Instant::now() + SIZE_TIMEOUT
+                        })
+                    {
+                        return Err(e);
+                    }
+                    std::thread::yield_now()
+                }
+                res => return res,
+            }
+        }
+    }
+
+    /// Creates a shmem of `uplined_size` bytes, failing with
+    /// [ShmemError::AlreadyExists] if it exists.
+    pub fn create_exclusive(
+        &self,
+        id: &str,
+        uplined_size: i64,
+    ) -> Result<Shmem, ShmemError> {
+        #[cfg(target_os = "linux")]
+        if self.check_free_pages && self.mount().is_some() {
+            self.check_pool(uplined_size)?;
+        }
+
+        let fd = self.open_fd(
+            id,
+            OFlag::O_RDWR | OFlag::O_CREAT | OFlag::O_EXCL,
+        )?;
+
+        // Do not leave an empty object behind for others to join
+        let shmem = ftruncate(&fd, uplined_size)
+            .map_err(ShmemError::from)
+            .and_then(|()| self.map(id, fd, uplined_size));
+        match shmem {
+            Ok(mut shmem) => {
+                shmem.created = true;
+                Ok(shmem)
+            }
+            Err(e) => {
+                self.remove(id);
+                Err(e)
+            }
+        }
+    }
+
+    /// Opens an existing shmem, failing with [ShmemError::NotFound] if
+    /// it does not exist and with [ShmemError::SizeMismatch] if it is
+    /// not `uplined_size` bytes.
+    pub fn open_existing(
+        &self,
+        id: &str,
+        uplined_size: i64,
+    ) -> Result<Shmem, ShmemError> {
+        let fd = self.open_fd(id, OFlag::O_RDWR)?;
+        let found = nix::sys::stat::fstat(fd.as_raw_fd())?.st_size;
+        if found != uplined_size {
+            return Err(ShmemError::SizeMismatch {
+                expected: uplined_size,
+                found,
+            });
+        }
 
+        self.map(id, fd, uplined_size)
+    }
+
+    /// Opens an existing shmem and maps it whole, taking the size from
+    /// the object itself.
+    pub(crate) fn open_existing_whole(
+        &self,
+        id: &str,
+    ) -> Result<Shmem, ShmemError> {
+        let fd = self.open_fd(id, OFlag::O_RDWR)?;
+        let size = nix::sys::stat::fstat(fd.as_raw_fd())?.st_size;
+        if size <= 0 {
+            return Err(ShmemError::SizeMismatch {
+                expected: 0,
+                found: size,
+            });
+        }
+
+        self.map(id, fd, size)
+    }
+
+    /// Creates an anonymous shmem of `uplined_size` bytes with
+    /// `memfd_create`. It has no name in any filesystem and is freed
+    /// once every mapping and fd is gone. Share it with other processes
+    /// with [Shmem::send].
+    ///
+    /// `name` only labels the fd in `/proc/<pid>/fd`.
+    #[cfg(target_os = "linux")]
+    pub fn create_anonymous(
+        &self,
+        name: &str,
+        uplined_size: i64,
+    ) -> Result<Shmem, ShmemError> {
+        use nix::sys::memfd::{memfd_create, MemFdCreateFlag};
+
+        if self.check_free_pages && self.mount().is_some() {
+            self.check_pool(uplined_size)?;
+        }
+
+        let mut flags = MemFdCreateFlag::MFD_CLOEXEC;
+        if self.page_size.is_huge() {
+            flags |= MemFdCreateFlag::MFD_HUGETLB
+                | MemFdCreateFlag::MFD_HUGE_2MB;
+        }
+        if self.page_size.is_gigantic() {
+            flags |= MemFdCreateFlag::MFD_HUGETLB
+                | MemFdCreateFlag::MFD_HUGE_1GB;
+        }
+
+        let c_name = CString::new(name).unwrap();
+        let fd = memfd_create(&c_name, flags)?;
+        ftruncate(&fd, uplined_size)?;
+
+        let mut shmem = self.map(name, fd, uplined_size)?;
+        shmem.backing = Backing::Anonymous;
+        shmem.created = true;
+        Ok(shmem)
+    }
+
+    /// Fails if the pool cannot back `size` more bytes.
+    #[cfg(target_os = "linux")]
+    fn check_pool(&self, size: i64) -> Result<(), ShmemError> {
+        let page_size = self.page_size.bytes();
+        let pool = crate::hugetlb::pool(page_size)
+            .map_err(ShmemError::from)?;
+        let required = (size as u64).div_ceil(page_size as u64);
+        if pool.available() < required {
+            return Err(ShmemError::InsufficientHugePages {
+                page_size,
+                required,
+                available: pool.available(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Removes the object `id` after failing to set it up.
+    fn remove(&self, id: &str) {
+        match self.mount() {
+            Some(mount) => {
+                let _ = std::fs::remove_file(mount.join(id));
+            }
+            None => {
+                let path = CString::new(id).unwrap();
+                unsafe { shm_unlink(path.as_ptr()) };
+            }
+        }
+    }
+
+    fn open_fd(
+        &self,
+        id: &str,
+        flags: OFlag,
+    ) -> Result<OwnedFd, ShmemError> {
         // Open with read + write privileges
         let mode = Mode::from_bits(S_IRUSR | S_IWUSR).unwrap();
-        let fd = if page_size.is_gigantic() {
-            // Huge pages expected at path /mnt/gigantic/...
-            let path = format!("/mnt/gigantic/{}", id);
-            let fd = open::<str>(
-                &path,
-                OFlag::O_RDWR | OFlag::O_CREAT,
-                mode,
-            )?;
-
-            unsafe { OwnedFd::from_raw_fd(fd) }
-        } else if page_size.is_huge() {
-            // Huge pages expected at path /mnt/hugepages/...
-            let path = format!("/mnt/hugepages/{}", id);
-            let fd = open::<str>(
-                &path,
-                OFlag::O_RDWR | OFlag::O_CREAT,
-                mode,
-            )?;
-
-            unsafe { OwnedFd::from_raw_fd(fd) }
-        } else {
+        let fd = match self.mount() {
+            // Huge pages are files on a hugetlbfs mount
+            Some(mount) => {
+                let fd = open(&mount.join(id), flags, mode)?;
+
+                unsafe { OwnedFd::from_raw_fd(fd) }
+            }
             // Default back to shm
-            // let path = format!("/dev/shm/{id}");
-            let path = CString::new(id).unwrap();
-
-            let fd = shm_open(
-                path.as_c_str(),
-                OFlag::O_RDWR | OFlag::O_CREAT,
-                mode,
-            )?;
-            let stat = nix::sys::stat::fstat(fd.as_raw_fd())?;
-            if stat.st_size != uplined_size {
-                ftruncate(&fd, uplined_size)?;
-            }
-            fd
+            None => {
+                let path = CString::new(id).unwrap();
+                shm_open(path.as_c_str(), flags, mode)?
+            }
         };
 
+        Ok(fd)
+    }
+
+    fn map(
+        &self,
+        id: &str,
+        fd: OwnedFd,
+        size: i64,
+    ) -> Result<Shmem, ShmemError> {
         // Add huge pages if specified
         #[cfg_attr(not(target_os = "linux"), allow(unused_mut))]
         let mut map_flags = MapFlags::MAP_SHARED;
         #[cfg(target_os = "linux")]
-        if page_size.is_huge() {
+        if self.page_size.is_huge() {
             map_flags |= MapFlags::MAP_HUGETLB;
         }
         #[cfg(target_os = "linux")]
-        if page_size.is_gigantic() {
+        if self.page_size.is_gigantic() {
             map_flags |= MapFlags::MAP_HUGETLB;
             map_flags |= MapFlags::MAP_HUGE_1GB;
         }
@@ -96,7 +358,7 @@ impl Shmem {
         let addr = unsafe {
             nix::sys::mman::mmap(
                 None,
-                NonZeroUsize::new_unchecked(uplined_size as usize),
+                NonZeroUsize::new_unchecked(size as usize),
                 ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
                 map_flags,
                 &fd,
@@ -105,16 +367,251 @@ impl Shmem {
         };
         let addr = match addr {
             Ok(addr) => addr.cast(),
+            #[cfg(target_os = "linux")]
+            Err(Errno::ENOMEM) if self.mount().is_some() => {
+                // Report the pool so the failure is actionable
+                self.check_pool(size)?;
+                return Err(ShmemError::AllocationFailedErr);
+            }
             Err(e) => return Err(ShmemError::Errno(e as i32)),
         };
         Ok(Shmem {
             id: id.to_string(),
-            size: uplined_size,
-            fd: fd.as_raw_fd(),
+            size,
+            fd: fd.into_raw_fd(),
             addr,
-            page_size,
+            backing: match self.mount() {
+                Some(mount) => Backing::File(mount.join(id)),
+                None => Backing::Shm,
+            },
+            created: false,
+            unlink_on_drop: false,
         })
     }
+}
+
+/// A mapped shared memory object. Unmapped and its fd closed on drop.
+pub struct Shmem {
+    pub id: String,
+    pub(crate) size: i64,
+    pub fd: i32,
+    addr: NonNull<()>,
+    backing: Backing,
+    /// Whether this mapping created the object.
+    created: bool,
+    unlink_on_drop: bool,
+}
+
+/// What the name of a [Shmem] refers to.
+enum Backing {
+    /// A POSIX shm object
+    Shm,
+    /// A file, e.g. on hugetlbfs
+    File(PathBuf),
+    /// A memfd or an fd received from another process, with no name
+    Anonymous,
+}
+
+// The mapping is plain memory shared with other processes; the channel
+// types built on top of it do their own synchronization.
+unsafe impl Send for Shmem {}
+unsafe impl Sync for Shmem {}
+
+impl Shmem {
+    /// Open or create a shmem
+    ///
+    /// An existing shmem is never resized: if its size differs from
+    /// `uplined_size`, this fails with [ShmemError::SizeMismatch], as
+    /// does an empty shmem that is not sized within a second.
+    ///
+    /// See [ShmemConfig] for where huge pages are expected.
+    pub fn open_or_create(
+        id: &str,
+        uplined_size: i64,
+        #[cfg(target_os = "linux")] config: impl Into<ShmemConfig>,
+    ) -> Result<Shmem, ShmemError> {
+        #[cfg(not(target_os = "linux"))]
+        let config = ShmemConfig::default();
+
+        config
+            .into()
+            .open_or_create(id, uplined_size)
+    }
+
+    /// Creates a shmem of `uplined_size` bytes, failing with
+    /// [ShmemError::AlreadyExists] if it exists.
+    ///
+    /// See [ShmemConfig] for where huge pages are expected.
+    pub fn create_exclusive(
+        id: &str,
+        uplined_size: i64,
+        #[cfg(target_os = "linux")] config: impl Into<ShmemConfig>,
+    ) -> Result<Shmem, ShmemError> {
+        #[cfg(not(target_os = "linux"))]
+        let config = ShmemConfig::default();
+
+        config
+            .into()
+            .create_exclusive(id, uplined_size)
+    }
+
+    /// Opens an existing shmem, failing with [ShmemError::NotFound] if
+    /// it does not exist and with [ShmemError::SizeMismatch] if it is
+    /// not `uplined_size` bytes.
+    ///
+    /// See [ShmemConfig] for where huge pages are expected.
+    pub fn open_existing(
+        id: &str,
+        uplined_size: i64,
+        #[cfg(target_os = "linux")] config: impl Into<ShmemConfig>,
+    ) -> Result<Shmem, ShmemError> {
+        #[cfg(not(target_os = "linux"))]
+        let config = ShmemConfig::default();
+
+        config
+            .into()
+            .open_existing(id, uplined_size)
+    }
+
+    /// Maps the regular file at `path`, creating it with `size` bytes
+    /// if it does not exist. The mapping is `MAP_SHARED`, so the
+    /// contents outlive every process and survive reboots, written
+    /// back by the kernel or on [Shmem::flush].
+    ///
+    /// An existing file is never resized: if its size differs from
+    /// `size`, this fails with [ShmemError::SizeMismatch], as does an
+    /// empty file that is not sized within a second. Files always use
+    /// standard pages.
+    pub fn open_or_create_file(
+        path: impl AsRef<Path>,
+        size: i64,
+    ) -> Result<Shmem, ShmemError> {
+        let path = path.as_ref();
+        let mode = Mode::from_bits(S_IRUSR | S_IWUSR).unwrap();
+        let config = ShmemConfig::default();
+        let id = path.to_string_lossy();
+
+        let mut deadline = None;
+        loop {
+            let flags = OFlag::O_RDWR | OFlag::O_CREAT | OFlag::O_EXCL;
+            match open(path, flags, mode) {
+                Ok(fd) => {
+                    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+                    let shmem = ftruncate(&fd, size)
+                        .map_err(ShmemError::from)
+                        .and_then(|()| config.map(&id, fd, size));
+                    return match shmem {
+                        Ok(mut shmem) => {
+                            shmem.backing =
+                                Backing::File(path.to_owned());
+                            shmem.created = true;
+                            Ok(shmem)
+                        }
+                        Err(e) => {
+                            let _ = std::fs::remove_file(path);
+                            Err(e)
+                        }
+                    };
+                }
+                Err(Errno::EEXIST) => {}
+                Err(e) => return Err(e.into()),
+            }
+
+            let fd = match open(path, OFlag::O_RDWR, mode) {
+                Ok(fd) => unsafe { OwnedFd::from_raw_fd(fd) },
+                // Removed since we tried to create it
+                Err(Errno::ENOENT) => continue,
+                Err(e) => return Err(e.into()),
+            };
+            let found = nix::sys::stat::fstat(fd.as_raw_fd())?.st_size;
+            if found == size {
+                let mut shmem = config.map(&id, fd, size)?;
+                shmem.backing = Backing::File(path.to_owned());
+                return Ok(shmem);
+            } else if found != 0
+                || Instant::now()
+                    >= *deadline.get_or_insert_with(|| {
+                        Instant::now() + SIZE_TIMEOUT
+                    })
+            {
+                return Err(ShmemError::SizeMismatch {
+                    expected: size,
+                    found,
+                });
+            }
+
+            // Created but not sized yet by another process
+            std::thread::yield_now()
+        }
+    }
+
+    /// Maps the existing regular file at `path`, failing with
+    /// [ShmemError::NotFound] if it does not exist and with
+    /// [ShmemError::SizeMismatch] if it is not `size` bytes. The file
+    /// is never created, see [Shmem::open_or_create_file].
+    pub fn open_existing_file(
+        path: impl AsRef<Path>,
+        size: i64,
+    ) -> Result<Shmem, ShmemError> {
+        let path = path.as_ref();
+        let fd = open(path, OFlag::O_RDWR, Mode::empty())?;
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let found = nix::sys::stat::fstat(fd.as_raw_fd())?.st_size;
+        if found != size {
+            return Err(ShmemError::SizeMismatch {
+                expected: size,
+                found,
+            });
+        }
+
+        let mut shmem = ShmemConfig::default().map(
+            &path.to_string_lossy(),
+            fd,
+            size,
+        )?;
+        shmem.backing = Backing::File(path.to_owned());
+        Ok(shmem)
+    }
+
+    /// Maps the whole object behind `fd`, e.g. a memfd received with
+    /// [Shmem::recv]. The mapping takes ownership of the fd.
+    pub fn from_fd(fd: OwnedFd) -> Result<Shmem, ShmemError> {
+        let size = nix::sys::stat::fstat(fd.as_raw_fd())?.st_size;
+        if size <= 0 {
+            return Err(ShmemError::SizeMismatch {
+                expected: 0,
+                found: size,
+            });
+        }
+
+        // Huge pages come with the fd, no need for MAP_HUGETLB
+        let mut shmem = ShmemConfig::default().map("", fd, size)?;
+        shmem.backing = Backing::Anonymous;
+        Ok(shmem)
+    }
+
+    /// Sends the fd of this mapping over a Unix domain socket with
+    /// `SCM_RIGHTS`. The receiver maps it with [Shmem::recv].
+    pub fn send(&self, socket: &UnixStream) -> Result<(), ShmemError> {
+        crate::fd_passing::send_fd(socket, self.as_fd())
+    }
+
+    /// Receives an fd sent with [Shmem::send] and maps it.
+    pub fn recv(socket: &UnixStream) -> Result<Shmem, ShmemError> {
+        Shmem::from_fd(crate::fd_passing::recv_fd(socket)?)
+    }
+
+    /// Returns `true` if this mapping created the shared memory object.
+    pub fn is_creator(&self) -> bool {
+        self.created
+    }
+
+    /// Unlinks the shared memory object when this mapping is dropped,
+    /// if it created the object. Off by default, since other processes
+    /// may still want to join.
+    pub fn set_unlink_on_drop(&mut self, unlink: bool) {
+        self.unlink_on_drop = unlink;
+    }
 
     /// Closes the shared memory region (unmap, unlink, close)
     pub fn close(self) -> Result<(), ShmemError> {
@@ -128,7 +625,65 @@ impl Shmem {
         // 2. unlink the shared memory completely from the os if self is
         //    the owner
         // 3. close the file descriptor of the shared memory
-        println!("Unmapping shared memory: {}", self.id);
+        println!("Closing shared memory: {}", self.id);
+
+        // Unmapped and closed when dropped
+        self.unlink()
+    }
+
+    fn unlink(&self) -> Result<(), ShmemError> {
+        match &self.backing {
+            // Remove the huge page file from its hugetlbfs mount
+            Backing::File(path) => {
+                let c_path =
+                    CString::new(path.as_os_str().as_bytes()).unwrap();
+                if unsafe { unlink(c_path.as_ptr()) } != 0 {
+                    return Err(ShmemError::UnlinkError);
+                }
+            }
+            Backing::Shm => {
+                let storage_id =
+                    CString::new(self.id.as_str()).unwrap();
+                if unsafe { shm_unlink(storage_id.as_ptr()) } != 0 {
+                    println!("failed to reclaim shared memory")
+                }
+            }
+            // Freed with its last fd and mapping
+            Backing::Anonymous => {}
+        }
+
+        Ok(())
+    }
+
+    /// Writes the mapping back to its backing object and waits for
+    /// the write to complete. Only useful for files on disk; shm and
+    /// memfd objects live in memory anyway.
+    pub fn flush(&self) -> Result<(), ShmemError> {
+        self.msync(MsFlags::MS_SYNC)
+    }
+
+    /// Schedules the mapping to be written back without waiting.
+    pub fn flush_async(&self) -> Result<(), ShmemError> {
+        self.msync(MsFlags::MS_ASYNC)
+    }
+
+    fn msync(&self, flags: MsFlags) -> Result<(), ShmemError> {
+        unsafe { msync(self.addr.cast(), self.size as usize, flags)? };
+
+        Ok(())
+    }
+
+    /// Returns a raw pointer to the shared memory region
+    pub fn get_mut_ptr(&self) -> *mut u8 {
+        self.addr.as_ptr() as *mut u8
+    }
+}
+
+impl Drop for Shmem {
+    fn drop(&mut self) {
+        if self.created && self.unlink_on_drop {
+            let _ = self.unlink();
+        }
 
         let res = unsafe {
             munmap(
@@ -141,55 +696,28 @@ impl Shmem {
             println!("failed to unmap shared memory from the virtual memory space: {res} -> {err:?}")
         }
 
-        println!("Closing shared memory: {}", self.id);
-
-        if self.page_size.is_gigantic() {
-            // Remove the huge page file from /mnt/gigantic if it
-            // exists
-            let path = format!("/mnt/gigantic/{}", self.id);
-            let c_path = CString::new(path).unwrap();
-            if unsafe { unlink(c_path.as_ptr()) } != 0 {
-                return Err(ShmemError::UnlinkError);
-            }
-        } else if self.page_size.is_huge() {
-            // Remove the huge page file from /mnt/hugepages if it
-            // exists
-            let path = format!("/mnt/hugepages/{}", self.id);
-            let c_path = CString::new(path).unwrap();
-            if unsafe { unlink(c_path.as_ptr()) } != 0 {
-                return Err(ShmemError::UnlinkError);
-            }
-        } else {
-            let storage_id: *const c_char =
-                self.id.as_bytes().as_ptr() as *const c_char;
-            if unsafe { shm_unlink(storage_id) } != 0 {
-                println!("failed to reclaim shared memory")
-            }
-        }
-
-        println!("fd closed: {}", self.id);
-
-        Ok(())
+        unsafe { drop(OwnedFd::from_raw_fd(self.fd)) }
     }
+}
 
-    /// Returns a raw pointer to the shared memory region
-    pub fn get_mut_ptr(&self) -> *mut u8 {
-        self.addr.as_ptr() as *mut u8
+impl AsFd for Shmem {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        unsafe { BorrowedFd::borrow_raw(self.fd) }
     }
 }
 
 /// Cleans up a shared memory region by opening it and then closing it
+///
+/// The region is mapped whole whatever its size, so `_size` is unused.
 pub fn cleanup_shmem(
     id: &str,
-    size: i64,
-    #[cfg(target_os = "linux")] page_size: PageSize,
+    _size: i64,
+    #[cfg(target_os = "linux")] config: impl Into<ShmemConfig>,
 ) -> Result<(), ShmemError> {
-    let shmem = Shmem::open_or_create(
-        id,
-        size,
-        #[cfg(target_os = "linux")]
-        page_size,
-    )?;
+    #[cfg(not(target_os = "linux"))]
+    let config = ShmemConfig::default();
+
+    let shmem = config.into().open_existing_whole(id)?;
 
     shmem.close()
 }
@@ -200,6 +728,27 @@ pub enum ShmemError {
     AllocationFailedErr,
     InvalidPermissions,
     UnlinkError,
+    /// The shmem was to be created but already exists
+    AlreadyExists,
+    /// The shmem was to be opened but does not exist
+    NotFound,
+    /// The existing shmem has a different size than expected
+    SizeMismatch {
+        expected: i64,
+        found: i64,
+    },
+    /// The peer sent no file descriptor or closed the socket
+    NoFdReceived,
+    /// No hugetlbfs is mounted for the requested page size
+    NoHugetlbfsMount {
+        page_size: usize,
+    },
+    /// The huge page pool cannot back the mapping
+    InsufficientHugePages {
+        page_size: usize,
+        required: u64,
+        available: u64,
+    },
     Errno(i32),
 }
 
@@ -220,6 +769,34 @@ impl core::fmt::Debug for ShmemError {
             ShmemError::UnlinkError => {
                 f.write_str("Failed to unlink huge page")
             }
+            ShmemError::AlreadyExists => {
+                f.write_str("Shared memory object already exists")
+            }
+            ShmemError::NotFound => {
+                f.write_str("Shared memory object does not exist")
+            }
+            ShmemError::SizeMismatch { expected, found } => write!(
+                f,
+                "Shared memory object is {found} bytes, expected {expected}"
+            ),
+            ShmemError::NoFdReceived => {
+                f.write_str("No file descriptor was received")
+            }
+            ShmemError::NoHugetlbfsMount { page_size } => write!(
+                f,
+                "No hugetlbfs mounted with {} KiB pages",
+                page_size >> 10
+            ),
+            ShmemError::InsufficientHugePages {
+                page_size,
+                required,
+                available,
+            } => write!(
+                f,
+                "Mapping needs {required} huge pages of {} KiB but only {available} are available. Raise nr_hugepages in /sys/kernel/mm/hugepages/hugepages-{}kB",
+                page_size >> 10,
+                page_size >> 10
+            ),
             ShmemError::Errno(e) => write!(f, "Other system error: {}", e),
         }
     }
@@ -237,7 +814,137 @@ impl From<Errno> for ShmemError {
             Errno::EBADF => ShmemError::BadFileDescriptor,
             Errno::ENOMEM => ShmemError::AllocationFailedErr,
             Errno::EACCES => ShmemError::InvalidPermissions,
+            Errno::EEXIST => ShmemError::AlreadyExists,
+            Errno::ENOENT => ShmemError::NotFound,
             e => ShmemError::Errno(e as i32),
         }
     }
 }
+
+impl From<std::io::Error> for ShmemError {
+    fn from(e: std::io::Error) -> Self {
+        match e.raw_os_error() {
+            Some(errno) => Errno::from_raw(errno).into(),
+            None => ShmemError::Errno(0),
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux", not(loom)))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_mounts() {
+        let config: ShmemConfig = PageSize::Standard.into();
+        assert_eq!(config.mount(), None);
+
+        let config = ShmemConfig::new(PageSize::Huge);
+        assert_eq!(config.mount(), Some(Path::new("/mnt/hugepages")));
+
+        let config = ShmemConfig::new(PageSize::Gigantic)
+            .huge_mount("/dev/hugepages")
+            .gigantic_mount("/dev/hugepages-1G");
+        assert_eq!(
+            config.mount(),
+            Some(Path::new("/dev/hugepages-1G"))
+        );
+
+        // Discovery agrees with the mounts found
+        let Ok(mounts) = crate::hugetlb::mounts() else {
+            return;
+        };
+        let found = mounts
+            .iter()
+            .find(|m| m.page_size == PageSize::HUGE);
+        match ShmemConfig::new(PageSize::Huge).discover() {
+            Ok(config) => {
+                assert_eq!(
+                    config.mount(),
+                    found.map(|m| m.path.as_path())
+                )
+            }
+            Err(e) => {
+                assert!(found.is_none());
+                assert!(matches!(
+                    e,
+                    ShmemError::NoHugetlbfsMount { page_size }
+                        if page_size == PageSize::HUGE
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn test_insufficient_huge_pages() {
+        let Ok(pool) = crate::hugetlb::pool(PageSize::HUGE) else {
+            return;
+        };
+
+        // One page more than the pool has left
+        let required = pool.available() + 1;
+        let config =
+            ShmemConfig::new(PageSize::Huge).huge_mount("/nonexistent");
+        let res = config.create_exclusive(
+            "que_insufficient_huge_pages",
+            (required * PageSize::HUGE as u64) as i64,
+        );
+        assert!(matches!(
+            res,
+            Err(ShmemError::InsufficientHugePages {
+                page_size: PageSize::HUGE,
+                required: r,
+                available: a,
+            }) if r == required && a == pool.available()
+        ));
+
+        // Without the check the failure comes from the filesystem
+        assert!(matches!(
+            config
+                .check_free_pages(false)
+                .create_exclusive(
+                    "que_insufficient_huge_pages",
+                    (required * PageSize::HUGE as u64) as i64,
+                ),
+            Err(ShmemError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn test_failed_create_is_removed() {
+        // MAP_HUGETLB fails on a file outside hugetlbfs, after the file
+        // was created and sized
+        let dir = std::env::temp_dir();
+        let id = format!("que_failed_create_{}", std::process::id());
+        let config = ShmemConfig::new(PageSize::Huge)
+            .huge_mount(&dir)
+            .check_free_pages(false);
+
+        assert!(config
+            .create_exclusive(&id, PageSize::HUGE as i64)
+            .is_err());
+        assert!(!dir.join(&id).exists());
+    }
+
+    #[test]
+    fn test_unsized_object_times_out() {
+        let id = format!("que_unsized_{}", std::process::id());
+        let config = ShmemConfig::default();
+
+        // Left behind by a creator that died before sizing it
+        drop(config.open_fd(&id, OFlag::O_RDWR | OFlag::O_CREAT));
+        assert!(matches!(
+            config.open_or_create(&id, 4096),
+            Err(ShmemError::SizeMismatch { found: 0, .. })
+        ));
+        config.remove(&id);
+
+        let path = std::env::temp_dir().join(&id);
+        std::fs::File::create(&path).unwrap();
+        assert!(matches!(
+            Shmem::open_or_create_file(&path, 4096),
+            Err(ShmemError::SizeMismatch { found: 0, .. })
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
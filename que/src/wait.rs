@@ -0,0 +1,220 @@
+//! Spin-then-park waiting on a futex word stored in the channel.
+//!
+//! Futex words live in the shared [`Channel`](crate::Channel) header and
+//! are not `FUTEX_PRIVATE`, so a waiter in one process can be woken by a
+//! notifier in another when the channel is backed by shared memory.
+
+use std::time::{Duration, Instant};
+
+use crate::atomic_compat::{fence, AtomicU32, Ordering};
+
+/// Number of times the readiness check is retried before parking.
+const SPIN_LIMIT: usize = 256;
+
+/// Longest a waiter stays parked before re-checking readiness on its
+/// own, see [Notify::notify].
+const MAX_PARK: Duration = Duration::from_millis(1);
+
+/// A futex-backed wakeup word.
+///
+/// Notifiers only pay for a relaxed load of `waiters` unless someone
+/// is actually parked.
+#[derive(Default)]
+#[repr(C, align(128))]
+pub(crate) struct Notify {
+    /// Bumped on every wakeup. Waiters park on this word.
+    epoch: AtomicU32,
+    /// Number of waiters parked (or about to park) on `epoch`.
+    waiters: AtomicU32,
+}
+
+impl Notify {
+    #[cfg(all(loom, test))]
+    pub(crate) fn new() -> Self {
+        Self {
+            epoch: AtomicU32::new(0),
+            waiters: AtomicU32::new(0),
+        }
+    }
+
+    /// Wakes all parked waiters, if any.
+    ///
+    /// Must be called after the state waiters are checking for has been
+    /// published.
+    ///
+    /// There is no fence between publishing and loading `waiters`, to
+    /// keep the publish path free of a full barrier. The load may then
+    /// miss a waiter that registered concurrently while that waiter
+    /// misses the published state, so every park is bounded by
+    /// [MAX_PARK] and a missed wakeup costs at most that much latency.
+    #[inline(always)]
+    pub(crate) fn notify(&self) {
+        if self.waiters.load(Ordering::Relaxed) != 0 {
+            self.epoch
+                .fetch_add(1, Ordering::Release);
+            futex_wake(&self.epoch);
+        }
+    }
+
+    /// Polls `ready` until it returns `Some`, spinning briefly and then
+    /// parking on the futex between attempts. Returns `None` if
+    /// `deadline` passes first.
+    pub(crate) fn wait_until<R>(
+        &self,
+        deadline: Option<Instant>,
+        mut ready: impl FnMut() -> Option<R>,
+    ) -> Option<R> {
+        for _ in 0..SPIN_LIMIT {
+            if let Some(r) = ready() {
+                return Some(r);
+            }
+            core::hint::spin_loop();
+        }
+
+        loop {
+            let epoch = self.register();
+
+            if let Some(r) = ready() {
+                self.deregister();
+                return Some(r);
+            }
+
+            let timeout = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        self.deregister();
+                        return None;
+                    }
+                    Some(deadline - now)
+                }
+                None => None,
+            };
+
+            self.park(epoch, timeout);
+            self.deregister();
+        }
+    }
+
+    /// Registers a waiter and returns the epoch to park on.
+    ///
+    /// Readiness must be re-checked after registering and before
+    /// parking, otherwise a notification may be missed.
+    #[inline(always)]
+    pub(crate) fn register(&self) -> u32 {
+        let epoch = self.epoch.load(Ordering::Acquire);
+        self.waiters
+            .fetch_add(1, Ordering::Relaxed);
+        fence(Ordering::SeqCst);
+        epoch
+    }
+
+    /// Removes a waiter added by [Notify::register].
+    #[inline(always)]
+    pub(crate) fn deregister(&self) {
+        self.waiters
+            .fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Returns `true` if a notification happened since `epoch` was
+    /// returned by [Notify::register].
+    #[cfg(feature = "async")]
+    #[inline(always)]
+    pub(crate) fn notified_since(&self, epoch: u32) -> bool {
+        self.epoch.load(Ordering::Acquire) != epoch
+    }
+
+    /// Parks until the epoch moves past `epoch`, `timeout` or
+    /// [MAX_PARK] passes, or a spurious wakeup.
+    #[inline(always)]
+    pub(crate) fn park(&self, epoch: u32, timeout: Option<Duration>) {
+        let timeout = timeout.map_or(MAX_PARK, |t| t.min(MAX_PARK));
+        futex_wait(&self.epoch, epoch, Some(timeout));
+    }
+
+    /// Unconditionally bumps the epoch and wakes every parked waiter.
+    #[cfg(feature = "async")]
+    pub(crate) fn wake_all(&self) {
+        self.epoch
+            .fetch_add(1, Ordering::Release);
+        futex_wake(&self.epoch);
+    }
+}
+
+#[cfg(all(target_os = "linux", not(loom)))]
+fn futex_wait(
+    word: &AtomicU32,
+    expected: u32,
+    timeout: Option<Duration>,
+) {
+    use nix::libc;
+
+    let timespec = timeout.map(|timeout| libc::timespec {
+        tv_sec: timeout
+            .as_secs()
+            .min(libc::time_t::MAX as u64)
+            as libc::time_t,
+        tv_nsec: timeout.subsec_nanos() as _,
+    });
+    let timespec_ptr = timespec
+        .as_ref()
+        .map_or(core::ptr::null(), |ts| ts as *const libc::timespec);
+
+    // Spurious wakeups, EAGAIN (epoch already moved) and ETIMEDOUT are
+    // all handled by the caller re-checking readiness.
+    unsafe {
+        libc::syscall(
+            libc::SYS_futex,
+            word.as_ptr(),
+            libc::FUTEX_WAIT,
+            expected,
+            timespec_ptr,
+        );
+    }
+}
+
+#[cfg(all(target_os = "linux", not(loom)))]
+fn futex_wake(word: &AtomicU32) {
+    use nix::libc;
+
+    unsafe {
+        libc::syscall(
+            libc::SYS_futex,
+            word.as_ptr(),
+            libc::FUTEX_WAKE,
+            i32::MAX,
+        );
+    }
+}
+
+/// Without futexes we fall back to short sleeps; `notify` is a no-op
+/// since waiters wake up on their own.
+#[cfg(all(not(target_os = "linux"), not(loom)))]
+fn futex_wait(
+    word: &AtomicU32,
+    expected: u32,
+    timeout: Option<Duration>,
+) {
+    const MAX_SLEEP: Duration = Duration::from_micros(50);
+
+    if word.load(Ordering::Acquire) == expected {
+        std::thread::sleep(
+            timeout.map_or(MAX_SLEEP, |t| t.min(MAX_SLEEP)),
+        );
+    }
+}
+
+#[cfg(all(not(target_os = "linux"), not(loom)))]
+fn futex_wake(_word: &AtomicU32) {}
+
+#[cfg(loom)]
+fn futex_wait(
+    _word: &AtomicU32,
+    _expected: u32,
+    _timeout: Option<Duration>,
+) {
+    loom::thread::yield_now();
+}
+
+#[cfg(loom)]
+fn futex_wake(_word: &AtomicU32) {}
@@ -0,0 +1,319 @@
+use std::{
+    ptr::NonNull,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use bytemuck::{AnyBitPattern, Pod};
+
+use crate::{
+    atomic_compat::Ordering,
+    cursor::Cursor,
+    error::QueError,
+    shmem::{Shmem, ShmemConfig},
+    Channel, ChannelMode, LocalMode, Metadata, MultiChannel, ShmemMode,
+};
+
+use super::MAGIC;
+
+unsafe impl<M: ChannelMode<T>, T, const N: usize> Send
+    for Consumer<M, T, N>
+{
+}
+
+/// One of up to [MAX_CURSORS](crate::MAX_CURSORS) consumers of a work
+/// queue. Holds a cursor in the channel header until dropped.
+#[repr(C)]
+pub struct Consumer<M: ChannelMode<T>, T, const N: usize> {
+    spsc: NonNull<MultiChannel<M, T, N>>,
+    /// Index of our cursor in the channel header
+    cursor: usize,
+    /// Generation of `cursor` while we hold it.
+    generation: u64,
+    last_producer_heartbeat: usize,
+    /// Mapping backing the channel, if joined through shmem.
+    shmem: Option<Shmem>,
+}
+
+impl<T: AnyBitPattern, const N: usize> Consumer<ShmemMode, T, N> {
+    /// Joins an existing work queue backed by shared memory as a
+    /// consumer.
+    ///
+    /// # Safety
+    /// The shared memory region must only ever be mapped as a work
+    /// queue `Channel` with the same `T` and `N`.
+    pub unsafe fn join_shmem(
+        shmem_id: &str,
+        #[cfg(target_os = "linux")] config: impl Into<ShmemConfig>,
+    ) -> Result<Consumer<ShmemMode, T, N>, QueError> {
+        #[cfg(not(target_os = "linux"))]
+        let config = ShmemConfig::default();
+        #[cfg(target_os = "linux")]
+        let config: ShmemConfig = config.into();
+
+        // Calculate buffer size.
+        // If using huge pages, we must uplign to page size.
+        let buffer_size: i64 = config
+            .page_size()
+            .mem_size(
+                core::mem::size_of::<MultiChannel<ShmemMode, T, N>>(),
+            )
+            .try_into()
+            .map_err(|_| QueError::InvalidSize)?;
+
+        // Open shmem
+        let shmem = config.open_existing(shmem_id, buffer_size)?;
+
+        let mut consumer = Consumer::join(shmem.get_mut_ptr())?;
+        consumer.shmem = Some(shmem);
+
+        Ok(consumer)
+    }
+
+    /// Unlinks the shared memory object when this endpoint is dropped,
+    /// if it created the object. Has no effect for endpoints joined
+    /// to an existing object or to a caller-provided buffer.
+    pub fn set_unlink_on_drop(&mut self, unlink: bool) {
+        if let Some(shmem) = self.shmem.as_mut() {
+            shmem.set_unlink_on_drop(unlink);
+        }
+    }
+}
+
+impl<T: Send, const N: usize> Consumer<LocalMode, T, N> {
+    /// Joins the same work queue as another consumer.
+    ///
+    /// Returns [QueError::TooManyConsumers] if all cursors are taken.
+    pub fn try_clone(&self) -> Result<Self, QueError> {
+        unsafe { Self::join(self.spsc.as_ptr().cast()) }
+    }
+}
+
+impl<M: ChannelMode<T>, T, const N: usize> Consumer<M, T, N> {
+    const MODULO_MASK: usize = N - 1;
+
+    /// Joins an existing work queue backed by `buffer`, claiming a free
+    /// cursor in the channel header.
+    ///
+    /// Unlike the lossless consumer, joining does not skip elements
+    /// that are already queued.
+    ///
+    /// # Safety
+    /// This must point to a buffer of proper size and alignment.
+    ///
+    /// In LocalMode, must point to a region allocated by an Arc with the strong count already incremented!
+    pub unsafe fn join(
+        buffer: *mut u8,
+    ) -> Result<Consumer<M, T, N>, QueError> {
+        assert!(
+            N > 0 && N.is_power_of_two(),
+            "Capacity must be a power of two"
+        );
+        assert!((buffer as usize).is_multiple_of(128), "unaligned");
+
+        let spsc: *mut MultiChannel<M, T, N> = buffer.cast();
+
+        // Check magic
+        let magic = (*spsc)
+            .channel
+            .magic
+            .load(Ordering::Acquire);
+        let capacity = (*spsc)
+            .channel
+            .capacity
+            .load(Ordering::Acquire);
+        if magic == MAGIC {
+            // Check capacity
+            if capacity != N {
+                return Err(QueError::IncorrectCapacity(capacity));
+            }
+
+            (*spsc).channel.check_fingerprint(0)?;
+
+            let (cursor, generation) = (*spsc)
+                .cursors
+                .acquire()
+                .ok_or(QueError::TooManyConsumers)?;
+
+            if M::BACKED_BY_ARCC {
+                unsafe {
+                    Arc::increment_strong_count(spsc);
+                }
+            }
+
+            // Successful join if magic and capacity is correct
+            Ok(Consumer {
+                spsc: NonNull::new_unchecked(spsc),
+                shmem: None,
+                cursor,
+                generation,
+                last_producer_heartbeat: (*spsc)
+                    .channel
+                    .producer_heartbeat
+                    .load(Ordering::Acquire),
+            })
+        } else if magic == 0 {
+            // Technically could be corrupted but uninitialized
+            // is most likely explanation
+            Err(QueError::Uninitialized)
+        } else {
+            // Magic is not MAGIC and not zero
+            Err(QueError::CorruptionDetected)
+        }
+    }
+
+    /// Claims and reads the next element. Returns `None` if every
+    /// published element has been claimed.
+    pub fn pop(&mut self) -> Option<T> {
+        let spsc = unsafe { &*self.spsc.as_ptr() };
+        let cursor = self.cursor();
+
+        let mut head = spsc.head.load(Ordering::Acquire);
+        let mut announced = false;
+        loop {
+            if head >= spsc.tail.load(Ordering::Acquire) {
+                // Lost the race for the last element
+                if announced {
+                    cursor.idle();
+                }
+                return None;
+            }
+
+            // Announce before claiming so the producer cannot lap us
+            // between the claim and the read
+            cursor.announce(self.generation, head);
+            announced = true;
+
+            match spsc.head.compare_exchange_weak(
+                head,
+                head + 1,
+                Ordering::SeqCst,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => break,
+                Err(current) => head = current,
+            }
+        }
+
+        let value = unsafe {
+            core::ptr::read(
+                spsc.buffer
+                    .as_ptr()
+                    .add(head & Self::MODULO_MASK),
+            )
+        };
+
+        cursor.idle();
+        spsc.producer_notify.notify();
+
+        Some(value)
+    }
+
+    /// Claims and reads the next element, waiting for the producer if
+    /// every published element has been claimed.
+    ///
+    /// Spins briefly and then parks on a futex in the channel header,
+    /// so this also works across processes.
+    pub fn pop_blocking(&mut self) -> T {
+        loop {
+            // Without a deadline we can only return with a value
+            if let Some(value) = self.pop_until(None) {
+                return value;
+            }
+        }
+    }
+
+    /// Claims and reads the next element, waiting up to `timeout` for
+    /// the producer. Returns `None` if nothing was published in time.
+    pub fn pop_timeout(&mut self, timeout: Duration) -> Option<T> {
+        self.pop_until(Instant::now().checked_add(timeout))
+    }
+
+    fn pop_until(&mut self, deadline: Option<Instant>) -> Option<T> {
+        let spsc = unsafe { &*self.spsc.as_ptr() };
+        spsc.consumer_notify
+            .wait_until(deadline, || self.pop())
+    }
+
+    /// Increments the consumer heartbeat, which is shared by all
+    /// consumers.
+    ///
+    /// Can be read by the producer to see that a consumer is still
+    /// online if done periodically.
+    pub fn beat(&self) {
+        unsafe {
+            (*self.spsc.as_ptr())
+                .channel
+                .consumer_heartbeat
+                .fetch_add(1, Ordering::Release);
+        }
+    }
+
+    /// Checks if the producer has incremented its heartbeat since last
+    /// called. Can be used by the consumer to see if the producer is
+    /// still online if done periodically.
+    pub fn producer_heartbeat(&mut self) -> bool {
+        let heartbeat = unsafe {
+            (*self.spsc.as_ptr())
+                .channel
+                .producer_heartbeat
+                .load(Ordering::Acquire)
+        };
+
+        if heartbeat != self.last_producer_heartbeat {
+            self.last_producer_heartbeat = heartbeat;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns pointer to inner padding.
+    ///
+    /// User is responsible for safe usage.
+    ///
+    /// Unsynchronized: prefer [metadata](Self::metadata) for values
+    /// that change while the channel is in use.
+    ///
+    /// Byte array is 128 byte aligned.
+    pub fn get_padding_ptr(&self) -> NonNull<[u8; 112]> {
+        unsafe {
+            NonNull::new_unchecked(
+                self.spsc.cast::<u8>().as_ptr().add(512),
+            )
+            .cast()
+        }
+    }
+
+    /// Returns a typed, versioned view of the metadata region (the
+    /// same bytes as [get_padding_ptr](Self::get_padding_ptr)).
+    ///
+    /// Fails to compile if `U` is larger than 112 bytes.
+    pub fn metadata<U: Pod>(&self) -> Metadata<'_, U> {
+        unsafe { Channel::<M, T, N>::metadata(self.spsc.cast()) }
+    }
+
+    #[inline(always)]
+    fn cursor(&self) -> &Cursor {
+        unsafe {
+            (*self.spsc.as_ptr())
+                .cursors
+                .get(self.cursor)
+        }
+    }
+}
+
+impl<M: ChannelMode<T>, T, const N: usize> Drop for Consumer<M, T, N> {
+    fn drop(&mut self) {
+        unsafe {
+            (*self.spsc.as_ptr())
+                .cursors
+                .release(self.cursor);
+        }
+
+        // LocalMode is backed by arc
+        if M::BACKED_BY_ARCC {
+            unsafe { drop(Arc::from_raw(self.spsc.as_ptr())) }
+        }
+    }
+}
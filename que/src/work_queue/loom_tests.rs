@@ -0,0 +1,96 @@
+//! Loom permutation tests for the work queue (`ShmemMode`, stack
+//! buffer).
+//!
+//! See `lossless::loom_tests` for why `LocalMode` is not used here.
+//!
+//! ```text
+//! cargo test -p que --features loom work_queue::loom_tests -- --test-threads=1
+//! ```
+
+use std::mem::size_of;
+
+use loom::thread;
+
+use super::{consumer::Consumer, producer::Producer, MAGIC};
+use crate::{MultiChannel, ShmemMode};
+
+macro_rules! channel_storage {
+    ($name:ident, $n:literal) => {
+        #[repr(C, align(128))]
+        struct $name([u8; size_of::<MultiChannel<ShmemMode, u64, $n>>()]);
+
+        impl $name {
+            fn new_zeroed() -> Self {
+                Self([0; size_of::<MultiChannel<ShmemMode, u64, $n>>()])
+            }
+
+            fn prepare(&mut self) -> *mut u8 {
+                let ptr = self.0.as_mut_ptr();
+                unsafe {
+                    MultiChannel::<ShmemMode, u64, $n>::loom_write_fresh_at(
+                        ptr, MAGIC, [0; $n],
+                    );
+                }
+                ptr
+            }
+        }
+    };
+}
+
+channel_storage!(ChannelStorage2, 2);
+
+#[test]
+fn loom_work_queue_two_consumers() {
+    loom::model(|| {
+        let mut storage = ChannelStorage2::new_zeroed();
+        let ptr = storage.prepare();
+
+        let mut producer = unsafe {
+            Producer::<ShmemMode, u64, 2>::join(ptr).unwrap()
+        };
+        let mut consumer_a = unsafe {
+            Consumer::<ShmemMode, u64, 2>::join(ptr).unwrap()
+        };
+        let mut consumer_b = unsafe {
+            Consumer::<ShmemMode, u64, 2>::join(ptr).unwrap()
+        };
+
+        producer.push(0).unwrap();
+        producer.push(1).unwrap();
+        producer.sync();
+
+        let b = thread::spawn(move || consumer_b.pop());
+
+        // Reuses the slot of position 0, which `consumer_b` may still
+        // be reading
+        let pushed = producer.push(2).is_ok();
+        producer.sync();
+
+        let mut got: Vec<u64> = b.join().unwrap().into_iter().collect();
+        got.extend(std::iter::from_fn(|| consumer_a.pop()));
+
+        // Each element is delivered exactly once
+        got.sort_unstable();
+        let expected = if pushed { 3 } else { 2 };
+        assert_eq!(got, (0..expected).collect::<Vec<_>>());
+    });
+}
+
+#[test]
+fn loom_work_queue_no_false_some_on_empty() {
+    loom::model(|| {
+        let mut storage = ChannelStorage2::new_zeroed();
+        let ptr = storage.prepare();
+
+        let _producer = unsafe {
+            Producer::<ShmemMode, u64, 2>::join(ptr).unwrap()
+        };
+        let mut consumer = unsafe {
+            Consumer::<ShmemMode, u64, 2>::join(ptr).unwrap()
+        };
+
+        for _ in 0..2 {
+            assert_eq!(consumer.pop(), None);
+        }
+    });
+}
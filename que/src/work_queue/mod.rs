@@ -0,0 +1,154 @@
+//! Lossless single-producer multi-consumer work queue.
+//!
+//! Every element is delivered to exactly one consumer, in FIFO order of
+//! claiming. Consumers claim the next element with a CAS on the shared
+//! `head`. Before claiming, a consumer publishes the position it is
+//! about to read in its cursor, in a table stored after the ring (see
+//! [MultiChannel]). The producer
+//! never writes past the lowest of `head` and those cursors, so a
+//! claimed element cannot be overwritten while it is being read.
+//!
+//! A consumer that dies between claiming and reading an element holds
+//! back the producer until its cursor is released.
+
+pub mod consumer;
+pub mod producer;
+
+use std::{mem::MaybeUninit, sync::Arc};
+
+use consumer::Consumer;
+use producer::Producer;
+
+use crate::{LocalMode, MultiChannel};
+
+/// Marks a channel initialized as a work queue.
+pub const MAGIC: u64 = u64::from_le_bytes(*b"TEMPWORK");
+
+pub fn work_queue_pair<T: Send, const N: usize>(
+) -> (Producer<LocalMode, T, N>, Consumer<LocalMode, T, N>) {
+    let arc_uninit = Arc::<MultiChannel<LocalMode, T, N>>::new_uninit();
+    let ptr: *mut MaybeUninit<MultiChannel<LocalMode, T, N>> =
+        Arc::into_raw(arc_uninit).cast_mut();
+
+    unsafe {
+        *ptr = core::mem::zeroed();
+    }
+
+    let producer = unsafe {
+        Producer::join_or_initialize_in_(ptr.cast()).unwrap()
+    };
+    let consumer = unsafe { Consumer::join(ptr.cast()).unwrap() };
+
+    unsafe {
+        Arc::decrement_strong_count(ptr);
+    }
+
+    (producer, consumer)
+}
+
+// Not run with `feature = "loom"`, see `lossless::tests`. Loom coverage
+// is in `loom_tests` (`ShmemMode`, stack buffer, `loom::thread`).
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use crate::{error::QueError, MAX_CURSORS};
+
+    #[test]
+    fn test_push_pop_multiple() {
+        let (mut producer, mut consumer) = work_queue_pair::<u64, 16>();
+        let mut consumer2 = consumer.try_clone().unwrap();
+        assert_eq!(consumer.pop(), None);
+
+        producer.push(69).unwrap();
+        producer.push(70).unwrap();
+        producer.push(71).unwrap();
+        assert_eq!(consumer.pop(), None);
+
+        producer.sync();
+        assert_eq!(consumer2.pop(), Some(69));
+        assert_eq!(consumer.pop(), Some(70));
+        assert_eq!(consumer2.pop(), Some(71));
+        assert_eq!(consumer.pop(), None);
+        assert_eq!(consumer2.pop(), None);
+    }
+
+    #[test]
+    fn test_full_until_popped() {
+        let (mut producer, consumer) = work_queue_pair::<u64, 4>();
+        let mut consumer2 = consumer.try_clone().unwrap();
+
+        for i in 0..4 {
+            producer.push(i).unwrap();
+        }
+        assert!(matches!(producer.push(4), Err(QueError::Full)));
+        producer.sync();
+
+        assert_eq!(consumer2.pop(), Some(0));
+        producer.push(4).unwrap();
+        assert!(matches!(producer.push(5), Err(QueError::Full)));
+    }
+
+    #[test]
+    fn test_consumer_limit() {
+        let (_producer, consumer) = work_queue_pair::<u64, 4>();
+
+        let clones: Vec<_> = (1..MAX_CURSORS)
+            .map(|_| consumer.try_clone().unwrap())
+            .collect();
+        assert!(matches!(
+            consumer.try_clone(),
+            Err(QueError::TooManyConsumers)
+        ));
+
+        // Dropping a consumer frees its cursor
+        drop(clones);
+        assert!(consumer.try_clone().is_ok());
+    }
+
+    #[test]
+    fn test_many_consumers() {
+        const CONSUMERS: usize = 4;
+        const ITEMS: u64 = 40_000;
+        const DONE: u64 = u64::MAX;
+
+        let (mut producer, consumer) = work_queue_pair::<u64, 64>();
+
+        let handles: Vec<_> = (0..CONSUMERS)
+            .map(|_| {
+                let mut consumer = consumer.try_clone().unwrap();
+                std::thread::spawn(move || {
+                    let mut got = Vec::new();
+                    loop {
+                        match consumer.pop_blocking() {
+                            DONE => return got,
+                            value => got.push(value),
+                        }
+                    }
+                })
+            })
+            .collect();
+        drop(consumer);
+
+        for i in 0..ITEMS {
+            producer.push_blocking(i);
+        }
+        // One stop marker per consumer
+        for _ in 0..CONSUMERS {
+            producer.push_blocking(DONE);
+        }
+        producer.sync();
+
+        let mut all = Vec::new();
+        for handle in handles {
+            let got = handle.join().unwrap();
+            // Each consumer sees its elements in FIFO order
+            assert!(got.is_sorted());
+            all.extend(got);
+        }
+        all.sort_unstable();
+        assert_eq!(all, (0..ITEMS).collect::<Vec<_>>());
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests;
@@ -0,0 +1,393 @@
+use std::{
+    ptr::NonNull,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use bytemuck::{AnyBitPattern, Pod};
+
+use crate::{
+    atomic_compat::Ordering,
+    error::QueError,
+    shmem::{Shmem, ShmemConfig},
+    Channel, ChannelMode, Metadata, MultiChannel, ShmemMode,
+};
+
+use super::MAGIC;
+
+/// The single producer of a work queue.
+#[repr(C, align(128))]
+pub struct Producer<M: ChannelMode<T>, T, const N: usize> {
+    spsc: NonNull<MultiChannel<M, T, N>>,
+    tail: usize,
+    /// Last lowest position consumers may still read. Only reloaded
+    /// when the channel appears full.
+    limit: usize,
+    last_consumer_heartbeat: usize,
+    /// Mapping backing the channel, if joined through shmem.
+    shmem: Option<Shmem>,
+}
+
+unsafe impl<M: ChannelMode<T>, T, const N: usize> Send
+    for Producer<M, T, N>
+{
+}
+
+impl<T: AnyBitPattern, const N: usize> Producer<ShmemMode, T, N> {
+    /// Joins or creates a work queue backed by shared memory as the
+    /// producer.
+    ///
+    /// # Safety
+    /// The shared memory region must only ever be mapped as a work
+    /// queue `Channel` with the same `T` and `N`.
+    pub unsafe fn join_or_create_shmem(
+        shmem_id: &str,
+        #[cfg(target_os = "linux")] config: impl Into<ShmemConfig>,
+    ) -> Result<Producer<ShmemMode, T, N>, QueError> {
+        #[cfg(not(target_os = "linux"))]
+        let config = ShmemConfig::default();
+        #[cfg(target_os = "linux")]
+        let config: ShmemConfig = config.into();
+
+        // Calculate buffer size.
+        // If using huge pages, we must uplign to page size.
+        let buffer_size: i64 = config
+            .page_size()
+            .mem_size(
+                core::mem::size_of::<MultiChannel<ShmemMode, T, N>>(),
+            )
+            .try_into()
+            .map_err(|_| QueError::InvalidSize)?;
+
+        // Open or create shmem
+        let shmem = config.open_or_create(shmem_id, buffer_size)?;
+
+        let mut producer =
+            Self::join_or_initialize_in_(shmem.get_mut_ptr())?;
+        producer.shmem = Some(shmem);
+
+        Ok(producer)
+    }
+
+    /// Initializes a work queue backed by `buffer` and joins as the
+    /// producer.
+    ///
+    /// # Safety
+    /// This must point to a buffer of proper size and alignment.
+    pub unsafe fn join_or_initialize_in(
+        buffer: *mut u8,
+    ) -> Result<Producer<ShmemMode, T, N>, QueError> {
+        Self::join_or_initialize_in_(buffer)
+    }
+
+    /// Joins an existing work queue backed by `buffer` as the producer.
+    ///
+    /// # Safety
+    /// This must point to a buffer of proper size and alignment.
+    pub unsafe fn join(
+        buffer: *mut u8,
+    ) -> Result<Producer<ShmemMode, T, N>, QueError> {
+        Self::join_(buffer)
+    }
+
+    /// Unlinks the shared memory object when this endpoint is dropped,
+    /// if it created the object. Has no effect for endpoints joined
+    /// to an existing object or to a caller-provided buffer.
+    pub fn set_unlink_on_drop(&mut self, unlink: bool) {
+        if let Some(shmem) = self.shmem.as_mut() {
+            shmem.set_unlink_on_drop(unlink);
+        }
+    }
+}
+
+impl<M: ChannelMode<T>, T, const N: usize> Producer<M, T, N> {
+    pub const MODULO_MASK: usize = N - 1;
+
+    pub(crate) unsafe fn join_or_initialize_in_(
+        buffer: *mut u8,
+    ) -> Result<Producer<M, T, N>, QueError> {
+        assert!(
+            N > 0 && N.is_power_of_two(),
+            "Capacity must be a power of two"
+        );
+        assert!((buffer as usize).is_multiple_of(128), "unaligned");
+
+        let spsc: *mut MultiChannel<M, T, N> = buffer.cast();
+
+        // Check magic
+        let magic = (*spsc)
+            .channel
+            .magic
+            .load(Ordering::Acquire);
+        if magic == MAGIC {
+            (*spsc)
+                .channel
+                .producer_heartbeat
+                .fetch_add(1, Ordering::Release);
+            Self::join_(buffer)
+        } else if magic == 0 {
+            (*spsc)
+                .channel
+                .tail
+                .store(0, Ordering::Release);
+            (*spsc)
+                .channel
+                .head
+                .store(0, Ordering::Release);
+            (*spsc)
+                .channel
+                .consumer_heartbeat
+                .store(0, Ordering::Release);
+            (*spsc)
+                .channel
+                .producer_heartbeat
+                .store(0, Ordering::Release);
+            (*spsc).cursors.reset();
+            (*spsc)
+                .channel
+                .capacity
+                .store(N, Ordering::Release);
+            (*spsc).channel.store_fingerprint(0);
+            (*spsc)
+                .channel
+                .magic
+                .store(MAGIC, Ordering::Release);
+            Self::join_(buffer)
+        } else {
+            // Magic is not MAGIC and not zero
+            Err(QueError::CorruptionDetected)
+        }
+    }
+
+    pub(crate) unsafe fn join_(
+        buffer: *mut u8,
+    ) -> Result<Producer<M, T, N>, QueError> {
+        assert!(
+            N > 0 && N.is_power_of_two(),
+            "Capacity must be a power of two"
+        );
+        assert!((buffer as usize).is_multiple_of(128), "unaligned");
+
+        let spsc: *mut MultiChannel<M, T, N> = buffer.cast();
+
+        let magic = (*spsc)
+            .channel
+            .magic
+            .load(Ordering::Acquire);
+        let capacity = (*spsc)
+            .channel
+            .capacity
+            .load(Ordering::Acquire);
+        if magic == MAGIC {
+            if capacity != N {
+                return Err(QueError::IncorrectCapacity(capacity));
+            }
+
+            (*spsc).channel.check_fingerprint(0)?;
+
+            if M::BACKED_BY_ARCC {
+                unsafe {
+                    Arc::increment_strong_count(spsc);
+                }
+            }
+
+            // Successful join if magic and capacity is correct
+            Ok(Producer {
+                spsc: NonNull::new(spsc).unwrap(),
+                shmem: None,
+                tail: (*spsc)
+                    .channel
+                    .tail
+                    .load(Ordering::Acquire),
+                limit: (*spsc)
+                    .channel
+                    .head
+                    .load(Ordering::Acquire),
+                last_consumer_heartbeat: (*spsc)
+                    .channel
+                    .consumer_heartbeat
+                    .load(Ordering::Acquire),
+            })
+        } else if magic == 0 {
+            // Technically could be corrupted but uninitialized
+            // is most likely explanation
+            Err(QueError::Uninitialized)
+        } else {
+            // Magic is not MAGIC and not zero
+            Err(QueError::CorruptionDetected)
+        }
+    }
+
+    /// Attempts to write a new element to the channel. If full, returns
+    /// [QueError::Full].
+    ///
+    /// Like the lossless producer, the value is not published until
+    /// [Producer::sync].
+    #[inline(always)]
+    pub fn push(&mut self, value: T) -> Result<(), QueError> {
+        if self.is_full() {
+            return Err(QueError::Full);
+        }
+
+        self.write_unchecked(value);
+
+        Ok(())
+    }
+
+    /// Writes a new element to the channel, waiting for consumers to
+    /// make room if the channel is full.
+    ///
+    /// Spins briefly and then parks on a futex in the channel header,
+    /// so this also works across processes.
+    pub fn push_blocking(&mut self, value: T) {
+        // Without a deadline we can only return once there is room
+        let _ = self.push_until(value, None);
+    }
+
+    /// Writes a new element to the channel, waiting up to `timeout` for
+    /// consumers to make room. If the channel is still full after
+    /// `timeout`, returns [QueError::Full].
+    pub fn push_timeout(
+        &mut self,
+        value: T,
+        timeout: Duration,
+    ) -> Result<(), QueError> {
+        self.push_until(value, Instant::now().checked_add(timeout))
+    }
+
+    fn push_until(
+        &mut self,
+        value: T,
+        deadline: Option<Instant>,
+    ) -> Result<(), QueError> {
+        if self.is_full() {
+            // Publish what we have so far; consumers may be parked
+            // waiting on exactly these values.
+            self.sync();
+
+            let spsc = unsafe { &*self.spsc.as_ptr() };
+            spsc.producer_notify
+                .wait_until(deadline, || {
+                    (!self.is_full()).then_some(())
+                })
+                .ok_or(QueError::Full)?;
+        }
+
+        self.write_unchecked(value);
+
+        Ok(())
+    }
+
+    /// Returns `true` if writing at the local tail could overwrite an
+    /// element that is unclaimed or still being read.
+    #[inline(always)]
+    fn is_full(&mut self) -> bool {
+        if self.tail < self.limit + N {
+            return false;
+        }
+
+        // `head` must be loaded before the cursors: a consumer
+        // publishes its cursor before advancing `head`, so if we miss
+        // the cursor we also see the old `head`.
+        let spsc = unsafe { &*self.spsc.as_ptr() };
+        let head = spsc.head.load(Ordering::SeqCst);
+        self.limit = spsc.cursors.min_position(head);
+        self.tail >= self.limit + N
+    }
+
+    /// Writes `value` at the local tail. Caller must check capacity.
+    #[inline(always)]
+    fn write_unchecked(&mut self, value: T) {
+        let index = self.tail & Self::MODULO_MASK;
+        unsafe {
+            core::ptr::write(
+                (*self.spsc.as_ptr())
+                    .channel
+                    .buffer
+                    .as_mut_ptr()
+                    .add(index),
+                value,
+            );
+        };
+
+        self.tail += 1;
+    }
+
+    /// Increments the producer heartbeat.
+    ///
+    /// Can be read by consumers to see that the producer is still
+    /// online if done periodically.
+    pub fn beat(&self) {
+        unsafe {
+            (*self.spsc.as_ptr())
+                .channel
+                .producer_heartbeat
+                .fetch_add(1, Ordering::Release);
+        }
+    }
+
+    /// Synchronizes the local tail with the atomic tail in the channel,
+    /// publishing newly written values and waking parked consumers.
+    #[inline(always)]
+    pub fn sync(&mut self) {
+        unsafe {
+            let spsc = &*self.spsc.as_ptr();
+            spsc.tail
+                .store(self.tail, Ordering::Release);
+            spsc.consumer_notify.notify();
+        }
+    }
+
+    /// Checks if any consumer has incremented the heartbeat since last
+    /// called. Can be used by the producer to see if consumers are
+    /// still online if done periodically.
+    pub fn consumer_heartbeat(&mut self) -> bool {
+        let heartbeat = unsafe {
+            (*self.spsc.as_ptr())
+                .channel
+                .consumer_heartbeat
+                .load(Ordering::Acquire)
+        };
+
+        if heartbeat != self.last_consumer_heartbeat {
+            self.last_consumer_heartbeat = heartbeat;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns pointer to inner padding.
+    ///
+    /// User is responsible for safe usage.
+    ///
+    /// Unsynchronized: prefer [metadata](Self::metadata) for values
+    /// that change while the channel is in use.
+    ///
+    /// Byte array is 128 byte aligned.
+    pub fn get_padding_ptr(&self) -> NonNull<[u8; 112]> {
+        unsafe {
+            NonNull::new_unchecked(
+                self.spsc.cast::<u8>().as_ptr().add(512),
+            )
+            .cast()
+        }
+    }
+
+    /// Returns a typed, versioned view of the metadata region (the
+    /// same bytes as [get_padding_ptr](Self::get_padding_ptr)).
+    ///
+    /// Fails to compile if `U` is larger than 112 bytes.
+    pub fn metadata<U: Pod>(&self) -> Metadata<'_, U> {
+        unsafe { Channel::<M, T, N>::metadata(self.spsc.cast()) }
+    }
+}
+
+impl<M: ChannelMode<T>, T, const N: usize> Drop for Producer<M, T, N> {
+    fn drop(&mut self) {
+        // LocalMode is backed by arc
+        if M::BACKED_BY_ARCC {
+            unsafe { drop(Arc::from_raw(self.spsc.as_ptr())) }
+        }
+    }
+}